@@ -1,3 +1,4 @@
+use auth::AppError;
 use axum::{
     Json, Router,
     extract::{Path, State},
@@ -9,8 +10,9 @@ use axum::{
 use dotenvy::dotenv;
 use rust_embed::Embed;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::{Pool, Sqlite, SqlitePool, sqlite::SqliteConnectOptions};
-use std::{env, fs, net::SocketAddr};
+use std::{env, net::SocketAddr};
 use uuid::Uuid;
 
 #[derive(sqlx::FromRow, Debug, Deserialize, Serialize, Clone, Default)]
@@ -28,7 +30,7 @@ struct ItemResponse {
 #[derive(Clone, Debug)]
 struct AppState {
     pub pool: Pool<Sqlite>,
-    pub key: String,
+    pub auth: auth::Auth,
 }
 
 #[tokio::main]
@@ -44,8 +46,7 @@ async fn main() -> anyhow::Result<()> {
 
     sqlx::migrate!("./migrations").run(&pool).await?;
 
-    let key_path = std::env::var("KEY_PATH")?;
-    let key = fs::read_to_string(key_path)?.trim().to_string();
+    let auth = auth::Auth::from_env("andrew-inbox").await?;
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 8082));
     let listener = tokio::net::TcpListener::bind(addr).await?;
@@ -58,7 +59,14 @@ async fn main() -> anyhow::Result<()> {
         .route("/get-items", get(get_items_handler))
         .route("/add-item", post(add_item_handler))
         .route("/{id}/delete-item", post(delete_item_handler))
-        .with_state(AppState { pool, key });
+        .layer(
+            auth::AuthLayer::new(auth.clone())
+                .trust_domain("beebfam.org")
+                .allow("/")
+                .allow("/index.html")
+                .allow("/assets/*"),
+        )
+        .with_state(AppState { pool, auth });
 
     println!("listening on {addr}");
     _ = axum::serve(listener, app).await;
@@ -103,15 +111,36 @@ async fn add_item_handler(
     .execute(&state.pool)
     .await?;
 
+    let item = Item {
+        id,
+        name: req.name,
+        created_at: now,
+    };
+    state
+        .auth
+        .audit(&current_user, "add-item", json!(null), json!(item))
+        .await?;
+
     let items = get_items(&state.pool).await?;
     Ok(Json(ItemResponse { items }))
 }
 
-#[auth_macro::auth_guard]
+#[auth_macro::auth_guard(role = "adult")]
 async fn delete_item_handler(
     State(state): State<AppState>,
     Path(id): Path<String>,
 ) -> Result<Json<ItemResponse>, AppError> {
+    let item = sqlx::query_as!(
+        Item,
+        r"
+        SELECT * FROM items WHERE id = ?1
+        ",
+        id
+    )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("No item {id}")))?;
+
     sqlx::query!(
         r"
         DELETE FROM items WHERE id = ?1
@@ -121,6 +150,11 @@ async fn delete_item_handler(
     .execute(&state.pool)
     .await?;
 
+    state
+        .auth
+        .audit(&current_user, "delete-item", json!(item), json!(null))
+        .await?;
+
     let items = get_items(&state.pool).await?;
     Ok(Json(ItemResponse { items }))
 }
@@ -138,27 +172,6 @@ async fn get_items(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<Item>> {
     Ok(items)
 }
 
-pub struct AppError(anyhow::Error);
-
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {}", self.0),
-        )
-            .into_response()
-    }
-}
-
-impl<E> From<E> for AppError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(err: E) -> Self {
-        Self(err.into())
-    }
-}
-
 #[derive(Embed)]
 #[folder = "src/client/dist/"]
 struct Asset;
@@ -1,11 +1,119 @@
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{ItemFn, parse_macro_input, parse_quote};
+use syn::{Expr, FnArg, Ident, ItemFn, LitStr, Pat, Path, Type, parse_macro_input, parse_quote};
+
+#[derive(Default)]
+struct GuardArgs {
+    cookie: Option<LitStr>,
+    state: Option<LitStr>,
+    error: Option<LitStr>,
+    role: Option<LitStr>,
+}
 
 /// A macro for adding auth to an axum endpoint
+///
+/// Accepts an `Authorization: Bearer` API token or the session cookie. Behind an
+/// `auth::AuthLayer` it takes the user the layer already looked up instead of looking again.
+/// Exposes the logged in `auth::User` to the handler body as `current_user` and the request's
+/// cookies and headers as `cookies` and `headers`. Optional arguments:
+/// - `cookie = "name"` session cookie to read, defaults to `auth::SESSION_COOKIE`
+/// - `state = "expr"` where to find the `auth::Auth`, defaults to the `auth` field of the
+///   `State` extractor's binding
+/// - `error = "Path"` error type with `Unauthorized` and `Forbidden` variants and
+///   `From<anyhow::Error>`, defaults to `AppError`
+/// - `role = "adult"` least trusted `auth::Role` allowed in, defaults to `kid` so guests
+///   stay read-only
 #[proc_macro_attribute]
-pub fn auth_guard(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn auth_guard(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut args = GuardArgs::default();
+    let arg_parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("cookie") {
+            args.cookie = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("state") {
+            args.state = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("error") {
+            args.error = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("role") {
+            args.role = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error("expected `cookie`, `state`, `error` or `role`"));
+        }
+        Ok(())
+    });
+    parse_macro_input!(attr with arg_parser);
+
     let input = parse_macro_input!(item as ItemFn);
+
+    match expand(args, input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+fn expand(args: GuardArgs, input: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    if input.sig.asyncness.is_none() {
+        return Err(syn::Error::new_spanned(
+            input.sig.fn_token,
+            "auth_guard handlers must be async",
+        ));
+    }
+
+    if let Some(jar) = input
+        .sig
+        .inputs
+        .iter()
+        .find(|arg| is_extractor(arg, "CookieJar"))
+    {
+        return Err(syn::Error::new_spanned(
+            jar,
+            "auth_guard already extracts the CookieJar, use the `cookies` binding instead",
+        ));
+    }
+
+    if let Some(header_map) = input
+        .sig
+        .inputs
+        .iter()
+        .find(|arg| is_extractor(arg, "HeaderMap"))
+    {
+        return Err(syn::Error::new_spanned(
+            header_map,
+            "auth_guard already extracts the HeaderMap, use the `headers` binding instead",
+        ));
+    }
+
+    let Some(state_binding) = input.sig.inputs.iter().find_map(state_binding) else {
+        return Err(syn::Error::new_spanned(
+            &input.sig.ident,
+            "auth_guard needs a `State(state)` extractor to reach `auth::Auth`",
+        ));
+    };
+
+    let auth_expr: Expr = match &args.state {
+        Some(state) => state.parse()?,
+        None => parse_quote!(#state_binding.auth),
+    };
+    let cookie_name: Expr = match &args.cookie {
+        Some(cookie) => parse_quote!(#cookie),
+        None => parse_quote!(auth::SESSION_COOKIE),
+    };
+    let error_ty: Path = match &args.error {
+        Some(error) => error.parse()?,
+        None => parse_quote!(AppError),
+    };
+    let role: Path = match args.role.as_ref().map(LitStr::value).as_deref() {
+        None | Some("kid") => parse_quote!(auth::Role::Kid),
+        Some("guest") => parse_quote!(auth::Role::Guest),
+        Some("adult") => parse_quote!(auth::Role::Adult),
+        Some("admin") => parse_quote!(auth::Role::Admin),
+        Some(_) => {
+            return Err(syn::Error::new_spanned(
+                &args.role,
+                "expected one of `guest`, `kid`, `adult` or `admin`",
+            ));
+        }
+    };
+
     let mut func_args = input.sig.inputs.clone();
     let func_vis = &input.vis;
     let func_asyncness = &input.sig.asyncness;
@@ -15,16 +123,59 @@ pub fn auth_guard(_attr: TokenStream, item: TokenStream) -> TokenStream {
 
     // insert early in the arg sequence because axum needs requests to go at the bottom
     func_args.insert(0, parse_quote!(cookies: axum_extra::extract::CookieJar));
+    func_args.insert(1, parse_quote!(headers: axum::http::HeaderMap));
+    func_args.insert(
+        2,
+        parse_quote!(layer_user: Option<axum::Extension<auth::User>>),
+    );
 
-    let expanded = quote! {
+    Ok(quote! {
         #func_vis #func_asyncness fn #func_name(#func_args) #func_ret {
-            if cookies.get("beebfam-key").is_none_or(|val| val.value().trim() != state.key) {
+            let current_user = match layer_user {
+                Some(axum::Extension(user)) => Some(user),
+                None => #auth_expr.authenticate(&headers, #cookie_name).await?,
+            };
+            // handlers opt into using the user, so don't warn when they ignore it
+            #[allow(unused_variables)]
+            let Some(current_user) = current_user else {
                 log::warn!("Access denied");
-                return Err(AppError(anyhow::anyhow!("Nope, sorry")));
+                return Err(#error_ty::Unauthorized);
+            };
+            if !current_user.role.allows(#role) {
+                log::warn!("{} is not allowed here", current_user.username);
+                return Err(#error_ty::Forbidden);
             }
             #func_block
         }
+    })
+}
+
+/// Whether an argument's type is `name` or `name<..>`, however it's imported
+fn is_extractor(arg: &FnArg, name: &str) -> bool {
+    let FnArg::Typed(arg) = arg else {
+        return false;
+    };
+    let Type::Path(ty) = arg.ty.as_ref() else {
+        return false;
+    };
+    ty.path.segments.last().is_some_and(|seg| seg.ident == name)
+}
+
+/// The identifier bound by `State(state): State<_>` or `state: State<_>`
+fn state_binding(arg: &FnArg) -> Option<Ident> {
+    if !is_extractor(arg, "State") {
+        return None;
+    }
+    let FnArg::Typed(arg) = arg else {
+        return None;
     };
 
-    TokenStream::from(expanded)
+    match arg.pat.as_ref() {
+        Pat::Ident(pat) => Some(pat.ident.clone()),
+        Pat::TupleStruct(pat) => match pat.elems.first() {
+            Some(Pat::Ident(inner)) => Some(inner.ident.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
 }
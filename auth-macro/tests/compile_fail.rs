@@ -0,0 +1,6 @@
+//! Mistakes `auth_guard` should reject with a clear error instead of odd code
+
+#[test]
+fn rejects_misuse() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
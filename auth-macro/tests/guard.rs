@@ -0,0 +1,169 @@
+//! `auth_guard` on handlers in a stub router, against an empty copy of root's database
+
+use auth::{AppError, Auth, Role, SESSION_COOKIE, User};
+use auth_macro::auth_guard;
+use axum::{
+    Extension, Router,
+    body::Body,
+    extract::State,
+    http::{Request, StatusCode, header},
+    routing::get,
+};
+use sqlx::sqlite::SqlitePoolOptions;
+use tower::ServiceExt;
+
+#[derive(Clone)]
+struct AppState {
+    auth: Auth,
+}
+
+#[auth_guard]
+async fn whoami(State(state): State<AppState>) -> Result<String, AppError> {
+    Ok(current_user.username)
+}
+
+#[auth_guard(role = "guest")]
+async fn guest_page(State(state): State<AppState>) -> Result<String, AppError> {
+    Ok(current_user.username)
+}
+
+/// kids are the default
+#[auth_guard]
+async fn kid_page(State(state): State<AppState>) -> Result<String, AppError> {
+    Ok(current_user.username)
+}
+
+#[auth_guard(role = "adult")]
+async fn adult_page(State(state): State<AppState>) -> Result<String, AppError> {
+    Ok(current_user.username)
+}
+
+#[auth_guard(role = "admin")]
+async fn admin_page(State(state): State<AppState>) -> Result<String, AppError> {
+    Ok(current_user.username)
+}
+
+async fn start() -> (AppState, sqlx::Pool<sqlx::Sqlite>) {
+    // one connection, every in-memory connection is its own database
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!("../root/migrations")
+        .run(&pool)
+        .await
+        .unwrap();
+    let state = AppState {
+        auth: Auth::new(pool.clone(), "root"),
+    };
+    (state, pool)
+}
+
+/// Adds someone with a session, returning the session token
+async fn add_user(pool: &sqlx::Pool<sqlx::Sqlite>, username: &str, role: Role) -> String {
+    let id = sqlx::query(
+        r"
+        INSERT INTO users (username, password_hash, role, created_at) VALUES (?1, '', ?2, 0)
+        ",
+    )
+    .bind(username)
+    .bind(role)
+    .execute(pool)
+    .await
+    .unwrap()
+    .last_insert_rowid();
+
+    let session = format!("{username}-session");
+    sqlx::query(
+        r"
+        INSERT INTO sessions (token_hash, user_id, created_at, expires_at) VALUES (?1, ?2, 0, ?3)
+        ",
+    )
+    .bind(auth::hash_token(&session))
+    .bind(id)
+    .bind(i64::MAX)
+    .execute(pool)
+    .await
+    .unwrap();
+    session
+}
+
+async fn fetch(app: Router, path: &str, session: Option<&str>) -> (StatusCode, String) {
+    let mut req = Request::get(path);
+    if let Some(session) = session {
+        req = req.header(header::COOKIE, format!("{SESSION_COOKIE}={session}"));
+    }
+    let response = app.oneshot(req.body(Body::empty()).unwrap()).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, String::from_utf8_lossy(&body).into_owned())
+}
+
+#[tokio::test]
+async fn looks_the_session_up() {
+    let (state, pool) = start().await;
+    let session = add_user(&pool, "mom", Role::Adult).await;
+    let app = Router::new()
+        .route("/whoami", get(whoami))
+        .with_state(state);
+
+    let (status, body) = fetch(app.clone(), "/whoami", Some(&session)).await;
+    assert_eq!((status, body.as_str()), (StatusCode::OK, "mom"));
+
+    let (status, _) = fetch(app.clone(), "/whoami", Some("made-up")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = fetch(app, "/whoami", None).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn takes_the_user_the_layer_found() {
+    // nobody in the database, so only the layer's user can get in
+    let (state, _pool) = start().await;
+    let dad = User {
+        id: 7,
+        username: "dad".to_string(),
+        role: Role::Adult,
+    };
+    let app = Router::new()
+        .route("/whoami", get(whoami))
+        .layer(Extension(dad))
+        .with_state(state);
+
+    let (status, body) = fetch(app, "/whoami", None).await;
+    assert_eq!((status, body.as_str()), (StatusCode::OK, "dad"));
+}
+
+#[tokio::test]
+async fn lets_in_the_role_and_anyone_more_trusted() {
+    let (state, pool) = start().await;
+    let roles = [Role::Guest, Role::Kid, Role::Adult, Role::Admin];
+    let mut sessions = vec![];
+    for role in roles {
+        sessions.push(add_user(&pool, &format!("{role:?}"), role).await);
+    }
+    let app = Router::new()
+        .route("/guest", get(guest_page))
+        .route("/kid", get(kid_page))
+        .route("/adult", get(adult_page))
+        .route("/admin", get(admin_page))
+        .with_state(state);
+
+    for (required, path) in roles
+        .into_iter()
+        .zip(["/guest", "/kid", "/adult", "/admin"])
+    {
+        for (role, session) in roles.into_iter().zip(&sessions) {
+            let (status, _) = fetch(app.clone(), path, Some(session)).await;
+            let expected = if role >= required {
+                StatusCode::OK
+            } else {
+                StatusCode::FORBIDDEN
+            };
+            assert_eq!(status, expected, "{role:?} on {path}");
+        }
+    }
+}
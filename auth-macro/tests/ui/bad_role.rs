@@ -0,0 +1,18 @@
+// the macro drops the handler when it errors, so its imports go unused
+#![allow(unused)]
+
+use auth::{AppError, Auth};
+use auth_macro::auth_guard;
+use axum::extract::State;
+
+#[derive(Clone)]
+struct AppState {
+    auth: Auth,
+}
+
+#[auth_guard(role = "parent")]
+async fn whoami(State(state): State<AppState>) -> Result<String, AppError> {
+    Ok(current_user.username)
+}
+
+fn main() {}
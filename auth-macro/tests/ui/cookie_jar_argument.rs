@@ -0,0 +1,19 @@
+// the macro drops the handler when it errors, so its imports go unused
+#![allow(unused)]
+
+use auth::{AppError, Auth};
+use auth_macro::auth_guard;
+use axum::extract::State;
+use axum_extra::extract::CookieJar;
+
+#[derive(Clone)]
+struct AppState {
+    auth: Auth,
+}
+
+#[auth_guard]
+async fn whoami(State(state): State<AppState>, jar: CookieJar) -> Result<String, AppError> {
+    Ok(current_user.username)
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+// the macro drops the handler when it errors, so its imports go unused
+#![allow(unused)]
+
+use auth::AppError;
+use auth_macro::auth_guard;
+
+#[auth_guard]
+async fn whoami() -> Result<String, AppError> {
+    Ok(current_user.username)
+}
+
+fn main() {}
@@ -0,0 +1,6 @@
+use auth_macro::auth_guard;
+
+#[auth_guard(session = "sid")]
+async fn whoami() {}
+
+fn main() {}
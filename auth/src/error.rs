@@ -0,0 +1,72 @@
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde_json::json;
+
+/// Every service's handler error, answered as `{"error", "message"}` with a matching status
+///
+/// Not every service hits every variant. Anything that converts to `anyhow::Error` turns into
+/// one with `?`, missing rows and unique violations picking the closer status.
+#[derive(Debug)]
+pub enum AppError {
+    Unauthorized,
+    Forbidden,
+    NotFound(String),
+    Conflict(String),
+    BadRequest(String),
+    Internal(anyhow::Error),
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, error, message) = match self {
+            AppError::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "Login needed".to_string(),
+            ),
+            AppError::Forbidden => (
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                "Not allowed".to_string(),
+            ),
+            AppError::NotFound(message) => (StatusCode::NOT_FOUND, "not_found", message),
+            AppError::Conflict(message) => (StatusCode::CONFLICT, "conflict", message),
+            AppError::BadRequest(message) => (StatusCode::BAD_REQUEST, "bad_request", message),
+            AppError::Internal(err) => {
+                // details stay in the logs, not in the response
+                log::error!("{err:?}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal",
+                    "Something went wrong".to_string(),
+                )
+            }
+        };
+
+        error_response(status, error, &message)
+    }
+}
+
+impl<E> From<E> for AppError
+where
+    E: Into<anyhow::Error>,
+{
+    fn from(err: E) -> Self {
+        let err = err.into();
+        match err.downcast_ref::<sqlx::Error>() {
+            Some(sqlx::Error::RowNotFound) => Self::NotFound("Not found".to_string()),
+            Some(sqlx::Error::Database(db_err)) if db_err.is_unique_violation() => {
+                Self::Conflict("Already exists".to_string())
+            }
+            _ => Self::Internal(err),
+        }
+    }
+}
+
+/// The body every error is answered with, for errors a service adds of its own
+pub fn error_response(status: StatusCode, error: &str, message: &str) -> Response {
+    (status, Json(json!({ "error": error, "message": message }))).into_response()
+}
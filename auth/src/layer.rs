@@ -0,0 +1,334 @@
+use axum::{
+    body::Body,
+    http::{HeaderMap, Request, Uri, header},
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::CookieJar;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+use crate::{AppError, Auth, SESSION_COOKIE};
+
+#[derive(Clone, Debug, Default)]
+struct LayerConfig {
+    public_paths: Vec<String>,
+    trusted_domains: Vec<String>,
+}
+
+/// Requires a valid session or API token on every route of a `Router` except the allow-listed ones
+///
+/// The resolved `User` is put in the request extensions so handlers can pull it out
+/// with `Extension<auth::User>`. Mutating requests that ride on the session cookie must also
+/// come from the same host or a trusted domain, so other sites can't forge them.
+#[derive(Clone, Debug)]
+pub struct AuthLayer {
+    auth: Auth,
+    config: Arc<LayerConfig>,
+}
+
+impl AuthLayer {
+    pub fn new(auth: Auth) -> Self {
+        Self {
+            auth,
+            config: Arc::new(LayerConfig::default()),
+        }
+    }
+
+    /// Lets a path through without a session, a trailing `*` matches anything after it
+    pub fn allow(mut self, path: &str) -> Self {
+        Arc::make_mut(&mut self.config)
+            .public_paths
+            .push(path.to_string());
+        self
+    }
+
+    /// Accepts cookie-authenticated mutations from `domain` and its subdomains
+    pub fn trust_domain(mut self, domain: &str) -> Self {
+        Arc::make_mut(&mut self.config)
+            .trusted_domains
+            .push(domain.to_string());
+        self
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = AuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService {
+            inner,
+            auth: self.auth.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AuthService<S> {
+    inner: S,
+    auth: Auth,
+    config: Arc<LayerConfig>,
+}
+
+impl<S> AuthService<S> {
+    fn is_public(&self, path: &str) -> bool {
+        self.config
+            .public_paths
+            .iter()
+            .any(|public| match public.strip_suffix('*') {
+                Some(prefix) => path.starts_with(prefix),
+                None => path == public,
+            })
+    }
+
+    /// Whether a request could have been forged by another site, which is only possible
+    /// when it changes something and the browser attached our cookie on its own
+    fn is_cross_site(&self, req: &Request<Body>) -> bool {
+        let headers = req.headers();
+        let needs_check = !req.method().is_safe()
+            && !headers.contains_key(header::AUTHORIZATION)
+            && CookieJar::from_headers(headers)
+                .get(SESSION_COOKIE)
+                .is_some();
+        if !needs_check {
+            return false;
+        }
+
+        // browsers send Origin on every fetch POST, Referer covers the odd old form post
+        let Some(source_host) =
+            header_host(headers, header::ORIGIN).or_else(|| header_host(headers, header::REFERER))
+        else {
+            return true;
+        };
+
+        let request_host = headers
+            .get(header::HOST)
+            .and_then(|val| val.to_str().ok())
+            .and_then(|host| host.split(':').next());
+
+        let is_trusted = request_host == Some(source_host.as_str())
+            || self.config.trusted_domains.iter().any(|domain| {
+                source_host == *domain || source_host.ends_with(&format!(".{domain}"))
+            });
+
+        !is_trusted
+    }
+}
+
+impl<S> Service<Request<Body>> for AuthService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let is_public = self.is_public(req.uri().path());
+        let is_cross_site = self.is_cross_site(&req);
+        let auth = self.auth.clone();
+
+        // the clone hasn't been driven to ready, so keep the one that has
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            if is_cross_site {
+                log::warn!("Blocked cross-site {} {}", req.method(), req.uri().path());
+                return Ok(AppError::Forbidden.into_response());
+            }
+
+            if is_public {
+                return inner.call(req).await;
+            }
+
+            match auth.authenticate(req.headers(), SESSION_COOKIE).await {
+                Ok(Some(user)) => {
+                    req.extensions_mut().insert(user);
+                    inner.call(req).await
+                }
+                Ok(None) => {
+                    log::warn!("Access denied to {}", req.uri().path());
+                    Ok(AppError::Unauthorized.into_response())
+                }
+                Err(err) => {
+                    Ok(AppError::Internal(err.context("Session lookup failed")).into_response())
+                }
+            }
+        })
+    }
+}
+
+fn header_host(headers: &HeaderMap, name: header::HeaderName) -> Option<String> {
+    let uri: Uri = headers.get(name)?.to_str().ok()?.parse().ok()?;
+    uri.host().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Role,
+        tests::{add_token, add_user, test_auth},
+    };
+    use axum::{
+        Router,
+        http::{Method, StatusCode},
+        routing::{get, post},
+    };
+    use tower::ServiceExt;
+
+    /// A grocery service with mom logged in, returning her session and an API token
+    async fn start() -> (Router, String, String) {
+        let auth = test_auth("grocery").await;
+        let (mom, session) = add_user(&auth, "mom", Role::Adult).await;
+        let token = add_token(&auth, mom.id, None, None).await;
+
+        let ok = || async { "ok" };
+        let app = Router::new()
+            .route("/login", post(ok))
+            .route("/login-help", get(ok))
+            .route("/assets/{*file}", get(ok))
+            .route("/get-items", get(ok))
+            .route("/add-item", post(ok))
+            .layer(
+                AuthLayer::new(auth)
+                    .trust_domain("beebfam.org")
+                    .allow("/login")
+                    .allow("/assets/*"),
+            );
+        (app, session, token)
+    }
+
+    async fn status(app: &Router, req: axum::http::request::Builder) -> StatusCode {
+        let req = req.header(header::HOST, "grocery.beebfam.org");
+        let response = app.clone().oneshot(req.body(Body::empty()).unwrap()).await;
+        response.unwrap().status()
+    }
+
+    fn request(method: Method, path: &str) -> axum::http::request::Builder {
+        Request::builder().method(method).uri(path)
+    }
+
+    fn with_session(method: Method, path: &str, session: &str) -> axum::http::request::Builder {
+        request(method, path).header(header::COOKIE, format!("{SESSION_COOKIE}={session}"))
+    }
+
+    #[tokio::test]
+    async fn lets_allowed_paths_through() {
+        let (app, ..) = start().await;
+
+        let cases = [
+            (Method::POST, "/login", StatusCode::OK),
+            (Method::GET, "/assets/app.js", StatusCode::OK),
+            (Method::GET, "/assets/icons/light.svg", StatusCode::OK),
+            // exact paths don't cover what starts with them
+            (Method::GET, "/login-help", StatusCode::UNAUTHORIZED),
+            (Method::GET, "/get-items", StatusCode::UNAUTHORIZED),
+            (Method::POST, "/add-item", StatusCode::UNAUTHORIZED),
+        ];
+        for (method, path, expected) in cases {
+            assert_eq!(
+                status(&app, request(method, path)).await,
+                expected,
+                "{path}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn needs_a_real_session_everywhere_else() {
+        let (app, session, _) = start().await;
+
+        let req = with_session(Method::GET, "/get-items", &session);
+        assert_eq!(status(&app, req).await, StatusCode::OK);
+        let req = with_session(Method::GET, "/login-help", &session);
+        assert_eq!(status(&app, req).await, StatusCode::OK);
+
+        let req = with_session(Method::GET, "/get-items", "made-up");
+        assert_eq!(status(&app, req).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn blocks_cookie_changes_from_other_sites() {
+        let (app, session, _) = start().await;
+
+        let cases = [
+            (None, None, StatusCode::FORBIDDEN),
+            (Some("https://grocery.beebfam.org"), None, StatusCode::OK),
+            (
+                Some("https://grocery.beebfam.org:8443"),
+                None,
+                StatusCode::OK,
+            ),
+            (Some("https://root.beebfam.org"), None, StatusCode::OK),
+            (Some("https://beebfam.org"), None, StatusCode::OK),
+            (Some("https://evilbeebfam.org"), None, StatusCode::FORBIDDEN),
+            (
+                Some("https://beebfam.org.evil.com"),
+                None,
+                StatusCode::FORBIDDEN,
+            ),
+            (Some("null"), None, StatusCode::FORBIDDEN),
+            (
+                None,
+                Some("https://grocery.beebfam.org/list"),
+                StatusCode::OK,
+            ),
+            (
+                None,
+                Some("https://evil.com/beebfam.org"),
+                StatusCode::FORBIDDEN,
+            ),
+            // Origin wins over Referer
+            (
+                Some("https://evil.com"),
+                Some("https://grocery.beebfam.org/"),
+                StatusCode::FORBIDDEN,
+            ),
+        ];
+        for (origin, referer, expected) in cases {
+            let mut req = with_session(Method::POST, "/add-item", &session);
+            if let Some(origin) = origin {
+                req = req.header(header::ORIGIN, origin);
+            }
+            if let Some(referer) = referer {
+                req = req.header(header::REFERER, referer);
+            }
+            assert_eq!(status(&app, req).await, expected, "{origin:?} {referer:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn only_checks_where_a_forgery_could_work() {
+        let (app, session, token) = start().await;
+        let bearer = format!("Bearer {token}");
+
+        // reads can't change anything
+        let req = with_session(Method::GET, "/get-items", &session)
+            .header(header::ORIGIN, "https://evil.com");
+        assert_eq!(status(&app, req).await, StatusCode::OK);
+
+        // other sites can't make a browser send a token
+        let req = request(Method::POST, "/add-item").header(header::AUTHORIZATION, &bearer);
+        assert_eq!(status(&app, req).await, StatusCode::OK);
+        let req = with_session(Method::POST, "/add-item", &session)
+            .header(header::AUTHORIZATION, &bearer)
+            .header(header::ORIGIN, "https://evil.com");
+        assert_eq!(status(&app, req).await, StatusCode::OK);
+
+        // without a cookie there's nothing to forge, it's just not logged in
+        let req = request(Method::POST, "/add-item").header(header::ORIGIN, "https://evil.com");
+        assert_eq!(status(&app, req).await, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,398 @@
+mod error;
+mod layer;
+
+pub use error::{AppError, error_response};
+pub use layer::{AuthLayer, AuthService};
+
+use axum::http::{HeaderMap, header};
+use axum_extra::extract::CookieJar;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite, SqlitePool, sqlite::SqliteConnectOptions};
+use std::env;
+
+/// Cookie holding the server-issued session token, shared across all beebfam subdomains
+pub const SESSION_COOKIE: &str = "beebfam-session";
+
+/// What a household member may do, ordered from least to most trusted
+#[derive(
+    sqlx::Type, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// can only read
+    Guest,
+    /// can check things off and add to lists
+    Kid,
+    /// can also delete and control the house
+    Adult,
+    /// can also manage people
+    Admin,
+}
+
+impl Role {
+    /// Whether this role is at least as trusted as `required`
+    pub fn allows(self, required: Role) -> bool {
+        self >= required
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "guest" => Ok(Role::Guest),
+            "kid" => Ok(Role::Kid),
+            "adult" => Ok(Role::Adult),
+            "admin" => Ok(Role::Admin),
+            _ => Err(anyhow::anyhow!("Unknown role {role}")),
+        }
+    }
+}
+
+/// The person behind a request, resolved from their session or API token
+#[derive(sqlx::FromRow, Debug, Serialize, Clone)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub role: Role,
+}
+
+/// Handle to the users/sessions tables that root owns, every service keeps one in its state
+///
+/// `service` is checked against the scopes of API tokens
+#[derive(Clone, Debug)]
+pub struct Auth {
+    pool: Pool<Sqlite>,
+    service: String,
+}
+
+impl Auth {
+    pub fn new(pool: Pool<Sqlite>, service: &str) -> Self {
+        Self {
+            pool,
+            service: service.to_string(),
+        }
+    }
+
+    /// Connects to root's database using `AUTH_DATABASE_URL`
+    pub async fn from_env(service: &str) -> anyhow::Result<Self> {
+        let raw_database_url = env::var("AUTH_DATABASE_URL")?;
+        let database_url = raw_database_url.split(":").last().unwrap_or_default();
+
+        let connection_options = SqliteConnectOptions::new().filename(database_url);
+        let pool = SqlitePool::connect_with(connection_options).await?;
+
+        Ok(Self::new(pool, service))
+    }
+
+    /// Resolves the user from an `Authorization: Bearer` API token, falling back to the
+    /// session cookie named `cookie_name`
+    pub async fn authenticate(
+        &self,
+        headers: &HeaderMap,
+        cookie_name: &str,
+    ) -> anyhow::Result<Option<User>> {
+        if let Some(token) = bearer_token(headers) {
+            return self.token_user(token).await;
+        }
+
+        match CookieJar::from_headers(headers).get(cookie_name) {
+            Some(cookie) => self.session_user(cookie.value().trim()).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Looks up the user owning an unexpired session token
+    ///
+    /// Matching on the hash keeps lookup timing from leaking anything about real tokens
+    pub async fn session_user(&self, token: &str) -> anyhow::Result<Option<User>> {
+        let token_hash = hash_token(token);
+        let now = chrono::Utc::now().timestamp();
+
+        // runtime query because the schema lives in root's migrations, not the caller's database
+        let user = sqlx::query_as::<_, User>(
+            r"
+            SELECT users.id, users.username, users.role FROM sessions
+            JOIN users ON users.id = sessions.user_id
+            WHERE sessions.token_hash = ?1 AND sessions.expires_at > ?2
+            ",
+        )
+        .bind(token_hash)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(user)
+    }
+
+    /// Looks up the user owning an unexpired API token that is scoped to this service
+    pub async fn token_user(&self, token: &str) -> anyhow::Result<Option<User>> {
+        let token_hash = hash_token(token);
+        let now = chrono::Utc::now().timestamp();
+
+        let row = sqlx::query_as::<_, TokenRow>(
+            r"
+            SELECT users.id, users.username, users.role, api_tokens.scopes FROM api_tokens
+            JOIN users ON users.id = api_tokens.user_id
+            WHERE api_tokens.token_hash = ?1
+            AND (api_tokens.expires_at IS NULL OR api_tokens.expires_at > ?2)
+            ",
+        )
+        .bind(&token_hash)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(row) = row.filter(|row| allows_service(row.scopes.as_deref(), &self.service))
+        else {
+            return Ok(None);
+        };
+
+        sqlx::query("UPDATE api_tokens SET last_used_at = ?1 WHERE token_hash = ?2")
+            .bind(now)
+            .bind(&token_hash)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(Some(User {
+            id: row.id,
+            username: row.username,
+            role: row.role,
+        }))
+    }
+
+    /// Records who changed what in this service, `before`/`after` are `null` when the thing
+    /// didn't exist on that side of the change
+    pub async fn audit(
+        &self,
+        user: &User,
+        action: &str,
+        before: serde_json::Value,
+        after: serde_json::Value,
+    ) -> anyhow::Result<()> {
+        let now = chrono::Utc::now().timestamp();
+        let before_json = (!before.is_null()).then(|| before.to_string());
+        let after_json = (!after.is_null()).then(|| after.to_string());
+
+        sqlx::query(
+            r"
+            INSERT INTO audit_log (user_id, username, service, action, before_json, after_json, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+            ",
+        )
+        .bind(user.id)
+        .bind(&user.username)
+        .bind(&self.service)
+        .bind(action)
+        .bind(before_json)
+        .bind(after_json)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct TokenRow {
+    id: i64,
+    username: String,
+    role: Role,
+    scopes: Option<String>,
+}
+
+/// Scopes are a comma separated list of services, `None` means every service
+fn allows_service(scopes: Option<&str>, service: &str) -> bool {
+    scopes.is_none_or(|scopes| scopes.split(',').any(|scope| scope.trim() == service))
+}
+
+/// Hex SHA-256 of a session or API token, only the hash is ever stored
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.trim().as_bytes())
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(str::trim)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    /// An `Auth` for `service` on an empty copy of root's database
+    pub(crate) async fn test_auth(service: &str) -> Auth {
+        // one connection, every in-memory connection is its own database
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("../root/migrations")
+            .run(&pool)
+            .await
+            .unwrap();
+        Auth::new(pool, service)
+    }
+
+    /// Adds someone with a session, returning them and the session token
+    pub(crate) async fn add_user(auth: &Auth, username: &str, role: Role) -> (User, String) {
+        let id = sqlx::query(
+            r"
+            INSERT INTO users (username, password_hash, role, created_at) VALUES (?1, '', ?2, 0)
+            ",
+        )
+        .bind(username)
+        .bind(role)
+        .execute(&auth.pool)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+        let session = format!("{username}-session");
+        sqlx::query(
+            r"
+            INSERT INTO sessions (token_hash, user_id, created_at, expires_at) VALUES (?1, ?2, 0, ?3)
+            ",
+        )
+        .bind(hash_token(&session))
+        .bind(id)
+        .bind(i64::MAX)
+        .execute(&auth.pool)
+        .await
+        .unwrap();
+
+        let user = User {
+            id,
+            username: username.to_string(),
+            role,
+        };
+        (user, session)
+    }
+
+    /// Adds an API token for `user_id`, returning the token
+    pub(crate) async fn add_token(
+        auth: &Auth,
+        user_id: i64,
+        scopes: Option<&str>,
+        expires_at: Option<i64>,
+    ) -> String {
+        let token = format!("beeb_{user_id}_{}_{expires_at:?}", scopes.unwrap_or("all"));
+        sqlx::query(
+            r"
+            INSERT INTO api_tokens (user_id, name, token_hash, scopes, created_at, expires_at)
+            VALUES (?1, 'script', ?2, ?3, 0, ?4)
+            ",
+        )
+        .bind(user_id)
+        .bind(hash_token(&token))
+        .bind(scopes)
+        .bind(expires_at)
+        .execute(&auth.pool)
+        .await
+        .unwrap();
+        token
+    }
+
+    fn bearer(token: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {token}").parse().unwrap(),
+        );
+        headers
+    }
+
+    async fn token_owner(auth: &Auth, token: &str) -> Option<String> {
+        let user = auth.authenticate(&bearer(token), SESSION_COOKIE).await;
+        user.unwrap().map(|user| user.username)
+    }
+
+    #[tokio::test]
+    async fn tokens_work_where_they_are_scoped() {
+        let grocery = test_auth("grocery").await;
+        let chores = Auth::new(grocery.pool.clone(), "chores");
+        let (mom, _) = add_user(&grocery, "mom", Role::Adult).await;
+
+        let everywhere = add_token(&grocery, mom.id, None, None).await;
+        let both = add_token(&grocery, mom.id, Some("grocery,chores"), None).await;
+        let only_chores = add_token(&grocery, mom.id, Some("chores"), None).await;
+        assert_eq!(
+            token_owner(&grocery, &everywhere).await.as_deref(),
+            Some("mom")
+        );
+        assert_eq!(
+            token_owner(&chores, &everywhere).await.as_deref(),
+            Some("mom")
+        );
+        assert_eq!(token_owner(&grocery, &both).await.as_deref(), Some("mom"));
+        assert_eq!(token_owner(&chores, &both).await.as_deref(), Some("mom"));
+        assert_eq!(
+            token_owner(&chores, &only_chores).await.as_deref(),
+            Some("mom")
+        );
+        assert_eq!(token_owner(&grocery, &only_chores).await, None);
+
+        let last_used: Option<i64> =
+            sqlx::query_scalar("SELECT last_used_at FROM api_tokens WHERE token_hash = ?1")
+                .bind(hash_token(&both))
+                .fetch_one(&grocery.pool)
+                .await
+                .unwrap();
+        assert!(last_used.is_some());
+    }
+
+    #[tokio::test]
+    async fn scopes_match_whole_service_names() {
+        let grocery = test_auth("grocery").await;
+        let (mom, _) = add_user(&grocery, "mom", Role::Adult).await;
+
+        for scopes in [
+            "grocery-list",
+            "grocer",
+            "groceryx,chores",
+            "%",
+            "_rocery",
+            "",
+        ] {
+            let token = add_token(&grocery, mom.id, Some(scopes), None).await;
+            assert_eq!(token_owner(&grocery, &token).await, None, "{scopes:?}");
+        }
+        let spaced = add_token(&grocery, mom.id, Some("chores, grocery"), None).await;
+        assert_eq!(token_owner(&grocery, &spaced).await.as_deref(), Some("mom"));
+    }
+
+    #[tokio::test]
+    async fn revoked_and_expired_tokens_stop_working() {
+        let auth = test_auth("grocery").await;
+        let (mom, _) = add_user(&auth, "mom", Role::Adult).await;
+        let now = chrono::Utc::now().timestamp();
+
+        let revoked = add_token(&auth, mom.id, None, None).await;
+        assert!(token_owner(&auth, &revoked).await.is_some());
+        sqlx::query("DELETE FROM api_tokens WHERE token_hash = ?1")
+            .bind(hash_token(&revoked))
+            .execute(&auth.pool)
+            .await
+            .unwrap();
+        assert_eq!(token_owner(&auth, &revoked).await, None);
+
+        let expired = add_token(&auth, mom.id, None, Some(now - 1)).await;
+        assert_eq!(token_owner(&auth, &expired).await, None);
+        let expiring = add_token(&auth, mom.id, None, Some(now + 60)).await;
+        assert_eq!(token_owner(&auth, &expiring).await.as_deref(), Some("mom"));
+
+        assert_eq!(token_owner(&auth, "beeb_made_up").await, None);
+    }
+}
@@ -0,0 +1,114 @@
+use reqwest::Response;
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+use std::env;
+
+use crate::{Failure, credentials::Credential};
+
+/// Sessions made by logging in are labelled with this, so they can be told apart when revoking
+pub const USER_AGENT: &str = "beeb";
+
+/// One of the beebfam services
+#[derive(Debug, Clone, Copy)]
+pub enum Service {
+    Root,
+    Grocery,
+    Chores,
+    Habits,
+    Exercise,
+    Media,
+    Inbox,
+}
+
+impl Service {
+    /// What it's called in error messages, the same as its crate
+    fn name(self) -> &'static str {
+        match self {
+            Service::Root => "root",
+            Service::Grocery => "grocery-list",
+            Service::Chores => "chore-kanban",
+            Service::Habits => "habit-tracker",
+            Service::Exercise => "exercise-tracker",
+            Service::Media => "media-list",
+            Service::Inbox => "andrew-inbox",
+        }
+    }
+
+    /// Where it's deployed, overridden by its `BEEBFAM_*_URL`
+    pub fn url(self) -> String {
+        let (var, default) = match self {
+            Service::Root => ("BEEBFAM_URL", "https://beebfam.org"),
+            Service::Grocery => ("BEEBFAM_GROCERY_URL", "https://supply.beebfam.org"),
+            Service::Chores => ("BEEBFAM_CHORES_URL", "https://chores.beebfam.org"),
+            Service::Habits => ("BEEBFAM_HABITS_URL", "https://habit.beebfam.org"),
+            Service::Exercise => ("BEEBFAM_EXERCISE_URL", "https://exercise.beebfam.org"),
+            Service::Media => ("BEEBFAM_MEDIA_URL", "https://media.beebfam.org"),
+            Service::Inbox => ("BEEBFAM_INBOX_URL", "https://ainbox.beebfam.org"),
+        };
+        let url = env::var(var).unwrap_or_else(|_| default.to_string());
+        url.trim_end_matches('/').to_string()
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+/// Turns a service's error bodies into something readable
+pub async fn check(service: Service, response: Response) -> Result<Response, Failure> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let message = match response.json::<ErrorBody>().await {
+        Ok(body) => body.message,
+        Err(_) => status.to_string(),
+    };
+    Err(Failure::Failed(format!("{}: {message}", service.name())))
+}
+
+/// Talks JSON to one service as whoever's logged in
+#[derive(Clone, Debug)]
+pub struct Api {
+    http: reqwest::Client,
+    service: Service,
+    base_url: String,
+}
+
+impl Api {
+    pub async fn connect(service: Service) -> Result<Self, Failure> {
+        let credential = Credential::resolve().await?;
+        let base_url = service.url();
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .default_headers(credential.headers(&base_url)?)
+            .build()?;
+
+        Ok(Api {
+            http,
+            service,
+            base_url,
+        })
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, Failure> {
+        let response = self
+            .http
+            .get(format!("{}{path}", self.base_url))
+            .send()
+            .await?;
+        Ok(check(self.service, response).await?.json().await?)
+    }
+
+    pub async fn post<T: DeserializeOwned>(&self, path: &str, body: Value) -> Result<T, Failure> {
+        let response = self
+            .http
+            .post(format!("{}{path}", self.base_url))
+            .json(&body)
+            .send()
+            .await?;
+        Ok(check(self.service, response).await?.json().await?)
+    }
+}
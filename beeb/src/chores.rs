@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    Failure,
+    api::{Api, Service},
+    local_time, print_json, print_table, unknown,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct Chore {
+    id: i64,
+    chore_name: String,
+    overdue: bool,
+    on_cadence: bool,
+    days_until_overdue: Option<f64>,
+    freq_secs: Option<i64>,
+    last_completed_at: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct ChoreResponse {
+    chores: Vec<Chore>,
+}
+
+/// `beeb chore ...`
+pub async fn run(args: Vec<String>, json: bool) -> Result<(), Failure> {
+    match args.as_slice() {
+        [command] if command == "list" => {
+            let api = Api::connect(Service::Chores).await?;
+            let response: ChoreResponse = api.get("/get-chores").await?;
+            list(response.chores, json);
+            Ok(())
+        }
+        [command, name] if command == "done" => {
+            let api = Api::connect(Service::Chores).await?;
+            let response: ChoreResponse = api.get("/get-chores").await?;
+            let chore = response
+                .chores
+                .into_iter()
+                .find(|chore| chore.chore_name.eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| Failure::Failed(format!("There's no chore called {name:?}")))?;
+
+            // toggling a chore that isn't due makes it due again, which is the opposite of done
+            if !chore.overdue {
+                if json {
+                    print_json(&chore);
+                } else {
+                    println!("{} isn't due, leaving it", chore.chore_name);
+                }
+                return Ok(());
+            }
+
+            let response: ChoreResponse = api
+                .post(&format!("/{}/toggle-chore", chore.id), json!(null))
+                .await?;
+            let done = response.chores.into_iter().find(|done| done.id == chore.id);
+
+            if json {
+                print_json(&done);
+            } else {
+                println!("{} is done", chore.chore_name);
+            }
+            Ok(())
+        }
+        _ => Err(unknown("chore", &args)),
+    }
+}
+
+fn list(mut chores: Vec<Chore>, json: bool) {
+    // overdue first, then whatever's coming up soonest
+    chores.sort_by(|a, b| {
+        b.overdue.cmp(&a.overdue).then(
+            a.days_until_overdue
+                .unwrap_or(f64::MAX)
+                .total_cmp(&b.days_until_overdue.unwrap_or(f64::MAX)),
+        )
+    });
+
+    if json {
+        print_json(&chores);
+        return;
+    }
+
+    let rows = chores
+        .into_iter()
+        .map(|chore| {
+            let status = match (chore.overdue, chore.days_until_overdue) {
+                (true, _) => "overdue".to_string(),
+                (false, Some(days)) if days < 1. => "due today".to_string(),
+                (false, Some(days)) => format!("due in {days:.0} days"),
+                (false, None) => "done".to_string(),
+            };
+            let last_done = chore.last_completed_at.map(local_time);
+            [chore.chore_name, status, last_done.unwrap_or_default()]
+        })
+        .collect();
+    print_table(["Chore", "Status", "Last done"], rows);
+}
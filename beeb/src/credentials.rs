@@ -0,0 +1,207 @@
+use reqwest::header::{AUTHORIZATION, COOKIE, HeaderMap, HeaderValue, ORIGIN, SET_COOKIE};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::{env, fs, io, path::PathBuf};
+
+use crate::{
+    Failure,
+    api::{Service, USER_AGENT, check},
+};
+
+const SESSION_COOKIE: &str = "beebfam-session";
+
+/// Who to act as. Sessions work against every service since they all check root's sessions,
+/// tokens only against the services they're scoped to.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Credential {
+    Token { token: String },
+    Session { username: String, session: String },
+}
+
+impl Credential {
+    /// `BEEBFAM_TOKEN` when it's set, then whatever's saved, then a login as `BEEBFAM_USER` with
+    /// `BEEBFAM_PASSWORD` or a password typed in. That session is saved like `beeb login` does, so
+    /// later runs use it instead of starting another one.
+    pub async fn resolve() -> Result<Self, Failure> {
+        if let Ok(token) = env::var("BEEBFAM_TOKEN")
+            && !token.is_empty()
+        {
+            return Ok(Credential::Token { token });
+        }
+        if let Some(credential) = load()? {
+            return Ok(credential);
+        }
+        if let Ok(username) = env::var("BEEBFAM_USER") {
+            let password = match env::var("BEEBFAM_PASSWORD") {
+                Ok(password) => password,
+                Err(_) => prompt_secret(&format!("Password for {username}: "))?,
+            };
+            let session = start_session(&username, &password).await?;
+            let credential = Credential::Session {
+                username: username.clone(),
+                session,
+            };
+            save(&credential)?;
+            // stderr, so it stays out of --json output
+            eprintln!("Logged in as {username}, `beeb logout` ends the session");
+            return Ok(credential);
+        }
+
+        Err(Failure::Failed(
+            "Not logged in, run `beeb login <username>` or set BEEBFAM_TOKEN".to_string(),
+        ))
+    }
+
+    /// Headers that authenticate a request to the service at `base_url`
+    pub fn headers(&self, base_url: &str) -> Result<HeaderMap, Failure> {
+        let unusable = |what: &str| Failure::Failed(format!("The saved {what} isn't usable"));
+        let mut headers = HeaderMap::new();
+
+        match self {
+            Credential::Token { token } => {
+                let bearer = HeaderValue::from_str(&format!("Bearer {}", token.trim()))
+                    .map_err(|_| unusable("token"))?;
+                headers.insert(AUTHORIZATION, bearer);
+            }
+            Credential::Session { session, .. } => {
+                let cookie = HeaderValue::from_str(&format!("{SESSION_COOKIE}={session}"))
+                    .map_err(|_| unusable("session"))?;
+                headers.insert(COOKIE, cookie);
+                // changes riding on a session have to look like they came from the service's page
+                let origin = HeaderValue::from_str(base_url)
+                    .map_err(|_| Failure::Failed(format!("{base_url} isn't a valid URL")))?;
+                headers.insert(ORIGIN, origin);
+            }
+        }
+
+        Ok(headers)
+    }
+}
+
+/// `$XDG_CONFIG_HOME/beeb/credentials.json`, or under `~/.config` without it
+fn path() -> Result<PathBuf, Failure> {
+    let config = match env::var("XDG_CONFIG_HOME") {
+        Ok(config) => PathBuf::from(config),
+        Err(_) => {
+            let home = env::var("HOME").map_err(|_| {
+                Failure::Failed("Neither XDG_CONFIG_HOME nor HOME is set".to_string())
+            })?;
+            PathBuf::from(home).join(".config")
+        }
+    };
+    Ok(config.join("beeb").join("credentials.json"))
+}
+
+fn load() -> Result<Option<Credential>, Failure> {
+    let path = path()?;
+    match fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|err| Failure::Failed(format!("{} isn't readable: {err}", path.display()))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Writes the credential where only this user can read it
+fn save(credential: &Credential) -> Result<(), Failure> {
+    let path = path()?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let text = serde_json::to_string_pretty(credential).unwrap();
+    io::Write::write_all(&mut options.open(&path)?, text.as_bytes())?;
+    Ok(())
+}
+
+/// Asks for a password or token without showing what's typed
+fn prompt_secret(question: &str) -> Result<String, Failure> {
+    let answer = rpassword::prompt_password(question)?;
+    Ok(answer.trim().to_string())
+}
+
+/// Logs in like the web page does, returning the session token
+async fn start_session(username: &str, password: &str) -> Result<String, Failure> {
+    let response = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .build()?
+        .post(format!("{}/login", Service::Root.url()))
+        .json(&json!({ "username": username, "password": password }))
+        .send()
+        .await?;
+    let response = check(Service::Root, response).await?;
+
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|val| val.to_str().ok())
+        .find_map(|cookie| {
+            let value = cookie.strip_prefix(SESSION_COOKIE)?.strip_prefix('=')?;
+            value.split(';').next().map(str::to_string)
+        })
+        .ok_or_else(|| Failure::Failed("Root didn't hand out a session".to_string()))
+}
+
+/// `beeb login <username>` or `beeb login --token`
+pub async fn login(args: &[String]) -> Result<(), Failure> {
+    let credential = match args {
+        [flag] if flag == "--token" => {
+            let token = prompt_secret("API token: ")?;
+            if token.is_empty() {
+                return Err(Failure::Usage("That's an empty token".to_string()));
+            }
+            Credential::Token { token }
+        }
+        [username] => {
+            let password = prompt_secret(&format!("Password for {username}: "))?;
+            let session = start_session(username, &password).await?;
+            Credential::Session {
+                username: username.clone(),
+                session,
+            }
+        }
+        _ => return Err(crate::unknown("login", args)),
+    };
+
+    save(&credential)?;
+    match credential {
+        Credential::Token { .. } => println!("Saved the token to {}", path()?.display()),
+        Credential::Session { username, .. } => println!("Logged in as {username}"),
+    }
+    Ok(())
+}
+
+/// `beeb logout`, ending the session on root too so it can't be used again
+pub async fn logout(args: &[String]) -> Result<(), Failure> {
+    if !args.is_empty() {
+        return Err(crate::unknown("logout", args));
+    }
+    let Some(credential) = load()? else {
+        println!("Wasn't logged in");
+        return Ok(());
+    };
+
+    if let Credential::Session { .. } = credential {
+        let base_url = Service::Root.url();
+        let response = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .default_headers(credential.headers(&base_url)?)
+            .build()?
+            .post(format!("{base_url}/logout"))
+            .send()
+            .await?;
+        check(Service::Root, response).await?;
+    }
+
+    fs::remove_file(path()?)?;
+    println!("Logged out");
+    Ok(())
+}
@@ -0,0 +1,237 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::{
+    Failure,
+    api::{Api, Service},
+    local_time, print_json, print_table, unknown,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct AerobicItem {
+    id: i64,
+    name: String,
+    duration_min: Option<f64>,
+    distance: Option<f64>,
+    date: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct AnaerobicItem {
+    id: i64,
+    name: String,
+    weight: Option<f64>,
+    sets: Option<i64>,
+    reps: Option<i64>,
+    date: i64,
+}
+
+/// Cardio and lifting come back mixed together, told apart by their fields
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+enum ExerciseItem {
+    Aerobic(AerobicItem),
+    Anaerobic(AnaerobicItem),
+}
+
+impl ExerciseItem {
+    fn date(&self) -> i64 {
+        match self {
+            ExerciseItem::Aerobic(item) => item.date,
+            ExerciseItem::Anaerobic(item) => item.date,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ItemResponse {
+    items: Vec<ExerciseItem>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct AerobicTemplate {
+    name: String,
+    duration_min: Option<f64>,
+    distance: Option<f64>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct AnaerobicTemplate {
+    name: String,
+    weight: Option<f64>,
+    sets: Option<i64>,
+    reps: Option<i64>,
+}
+
+/// An exercise that can be logged, remembering what was done last time
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+enum ExerciseTemplate {
+    Aerobic(AerobicTemplate),
+    Anaerobic(AnaerobicTemplate),
+}
+
+impl ExerciseTemplate {
+    fn name(&self) -> &str {
+        match self {
+            ExerciseTemplate::Aerobic(template) => &template.name,
+            ExerciseTemplate::Anaerobic(template) => &template.name,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TemplateResponse {
+    templates: Vec<ExerciseTemplate>,
+}
+
+/// `beeb exercise ...`
+pub async fn run(args: Vec<String>, json: bool) -> Result<(), Failure> {
+    match args.as_slice() {
+        [command] if command == "list" => {
+            let api = Api::connect(Service::Exercise).await?;
+            let response: ItemResponse = api.get("/get-items").await?;
+            list(response.items, json);
+            Ok(())
+        }
+        [command, name, amount @ ..] if command == "log" && amount.len() <= 1 => {
+            let api = Api::connect(Service::Exercise).await?;
+            let response: TemplateResponse = api.get("/get-templates").await?;
+            let template = response
+                .templates
+                .into_iter()
+                .find(|template| template.name().eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| Failure::Failed(format!("There's no exercise called {name:?}")))?;
+
+            let request = request(&template, amount.first().map(String::as_str))?;
+            let response: ItemResponse = api.post("/add-item", request).await?;
+
+            // the newest of them is the one just logged
+            let logged = response
+                .items
+                .into_iter()
+                .filter(|item| describe(item).0 == template.name())
+                .max_by_key(ExerciseItem::date);
+            if json {
+                print_json(&logged);
+            } else if let Some(logged) = logged {
+                let (name, amount) = describe(&logged);
+                println!("Logged {name} {amount}");
+            }
+            Ok(())
+        }
+        _ => Err(unknown("exercise", &args)),
+    }
+}
+
+/// The body for `/add-item`. Without an amount it's whatever was done last time.
+fn request(template: &ExerciseTemplate, amount: Option<&str>) -> Result<Value, Failure> {
+    let invalid = |amount: &str, shape: &str| {
+        Failure::Usage(format!("{amount:?} isn't an amount, it's {shape}"))
+    };
+
+    match template {
+        ExerciseTemplate::Aerobic(template) => {
+            let (duration_min, distance) = match amount {
+                None => (template.duration_min, template.distance),
+                Some(amount) => {
+                    let shape = "minutes or minutes x distance";
+                    let numbers = numbers(amount).ok_or_else(|| invalid(amount, shape))?;
+                    match numbers[..] {
+                        [minutes] => (Some(minutes), None),
+                        [minutes, distance] => (Some(minutes), Some(distance)),
+                        _ => return Err(invalid(amount, shape)),
+                    }
+                }
+            };
+            Ok(json!({
+                "type": "aerobic",
+                "name": template.name,
+                "duration_min": duration_min,
+                "distance": distance,
+            }))
+        }
+        ExerciseTemplate::Anaerobic(template) => {
+            let (weight, sets, reps) = match amount {
+                None => (template.weight, template.sets, template.reps),
+                Some(amount) => {
+                    let shape = "sets x reps or weight x sets x reps";
+                    let numbers = numbers(amount).ok_or_else(|| invalid(amount, shape))?;
+                    let whole = |number: f64| {
+                        (number.fract() == 0. && number >= 0.)
+                            .then_some(number as i64)
+                            .ok_or_else(|| invalid(amount, shape))
+                    };
+                    match numbers[..] {
+                        [sets, reps] => (None, Some(whole(sets)?), Some(whole(reps)?)),
+                        [weight, sets, reps] => {
+                            (Some(weight), Some(whole(sets)?), Some(whole(reps)?))
+                        }
+                        _ => return Err(invalid(amount, shape)),
+                    }
+                }
+            };
+            Ok(json!({
+                "type": "anaerobic",
+                "name": template.name,
+                "weight": weight,
+                "sets": sets,
+                "reps": reps,
+            }))
+        }
+    }
+}
+
+/// `20x3x10` as its numbers
+fn numbers(amount: &str) -> Option<Vec<f64>> {
+    amount
+        .split(['x', 'X'])
+        .map(|number| number.trim().parse().ok())
+        .collect()
+}
+
+/// The exercise's name and how much of it was done
+fn describe(item: &ExerciseItem) -> (&str, String) {
+    match item {
+        ExerciseItem::Aerobic(item) => {
+            let amount = [
+                item.duration_min.map(|minutes| format!("{minutes} min")),
+                item.distance.map(|distance| format!("dist {distance}")),
+            ];
+            (
+                &item.name,
+                amount.into_iter().flatten().collect::<Vec<_>>().join(", "),
+            )
+        }
+        ExerciseItem::Anaerobic(item) => {
+            let amount = [
+                item.weight.map(|weight| weight.to_string()),
+                item.sets.map(|sets| sets.to_string()),
+                item.reps.map(|reps| reps.to_string()),
+            ];
+            (
+                &item.name,
+                amount.into_iter().flatten().collect::<Vec<_>>().join("x"),
+            )
+        }
+    }
+}
+
+fn list(mut items: Vec<ExerciseItem>, json: bool) {
+    items.sort_by_key(|item| std::cmp::Reverse(item.date()));
+
+    if json {
+        print_json(&items);
+        return;
+    }
+
+    let rows = items
+        .iter()
+        .map(|item| {
+            let (name, amount) = describe(item);
+            [local_time(item.date()), name.to_string(), amount]
+        })
+        .collect();
+    print_table(["When", "Exercise", "Amount"], rows);
+}
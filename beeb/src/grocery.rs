@@ -0,0 +1,350 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+
+use crate::{
+    Failure,
+    api::{Api, Service},
+    local_time, print_json, print_table, take_flag, take_options, unknown,
+};
+
+/// The grocery page's first store, and the category for things that don't fit anywhere
+const DEFAULT_STORE: &str = "hyvee";
+const DEFAULT_CATEGORY: &str = "misc";
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct Item {
+    id: i64,
+    list_id: i64,
+    name: String,
+    /// on the list, inactive items are remembered for next time
+    active: bool,
+    qty: Option<String>,
+    category: Option<String>,
+    store: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ItemResponse {
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct List {
+    id: i64,
+    name: String,
+    created_at: i64,
+}
+
+#[derive(Deserialize)]
+struct ListResponse {
+    lists: Vec<List>,
+}
+
+/// `beeb grocery ...`
+pub async fn run(mut args: Vec<String>, json: bool) -> Result<(), Failure> {
+    if args.first().is_some_and(|command| command == "lists") {
+        return run_lists(args.split_off(1), json).await;
+    }
+
+    let all = take_flag(&mut args, "--all");
+    let (args, options) = take_options(args, &["qty", "store", "category", "list", "to"])?;
+    let command = args.first().map(String::as_str);
+    if command != Some("add") && (options.contains_key("qty") || options.contains_key("category")) {
+        return Err(Failure::Usage(
+            "--qty and --category only go with `add`".to_string(),
+        ));
+    }
+    if !matches!(command, Some("move" | "copy")) && options.contains_key("to") {
+        return Err(Failure::Usage(
+            "--to only goes with `move` and `copy`".to_string(),
+        ));
+    }
+
+    match args.as_slice() {
+        [command] if command == "list" => {
+            let api = Api::connect(Service::Grocery).await?;
+            let list = pick_list(&api, &options).await?;
+            let mut items = get_items(&api, list).await?;
+            items.retain(|item| all || item.active);
+            if let Some(store) = options.get("store") {
+                items.retain(|item| item.store.as_ref() == Some(store));
+            }
+            show_items(items, all, json);
+            Ok(())
+        }
+        [command, name] if command == "add" => {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(Failure::Usage("Items need a name".to_string()));
+            }
+            let store = options.get("store").map_or(DEFAULT_STORE, String::as_str);
+            let category = options
+                .get("category")
+                .map_or(DEFAULT_CATEGORY, String::as_str);
+
+            let api = Api::connect(Service::Grocery).await?;
+            let list = pick_list(&api, &options).await?;
+            let response: ItemResponse = api
+                .post(
+                    "/add-item",
+                    json!({
+                        "list_id": list,
+                        "name": name,
+                        "qty": options.get("qty"),
+                        "store": store,
+                        "category": category,
+                    }),
+                )
+                .await?;
+            let added = response
+                .items
+                .into_iter()
+                .find(|item| item.name == name && item.store.as_deref() == Some(store));
+
+            if json {
+                print_json(&added);
+            } else {
+                println!("Added {name} ({store}, {category})");
+            }
+            Ok(())
+        }
+        [command, name] if command == "toggle" => {
+            let api = Api::connect(Service::Grocery).await?;
+            let item = find(&api, name, &options).await?;
+            let response: ItemResponse = api
+                .post(&format!("/{}/toggle-item", item.id), json!(null))
+                .await?;
+            let toggled = response
+                .items
+                .into_iter()
+                .find(|toggled| toggled.id == item.id);
+
+            if json {
+                print_json(&toggled);
+            } else if toggled.is_some_and(|toggled| toggled.active) {
+                println!("{} is on the list", item.name);
+            } else {
+                println!("{} is off the list", item.name);
+            }
+            Ok(())
+        }
+        [command, name] if command == "delete" => {
+            let api = Api::connect(Service::Grocery).await?;
+            let item = find(&api, name, &options).await?;
+            let _: ItemResponse = api
+                .post(&format!("/{}/delete-item", item.id), json!(null))
+                .await?;
+
+            if json {
+                print_json(&item);
+            } else {
+                println!("Deleted {}", item.name);
+            }
+            Ok(())
+        }
+        [command, name] if command == "move" || command == "copy" => {
+            let to = options
+                .get("to")
+                .ok_or_else(|| Failure::Usage(format!("`{command}` needs --to <list>")))?;
+            let api = Api::connect(Service::Grocery).await?;
+            let item = find(&api, name, &options).await?;
+            let to = find_list(&api, to).await?;
+            let _: ItemResponse = api
+                .post(
+                    &format!("/{}/{command}-item", item.id),
+                    json!({ "list_id": to.id }),
+                )
+                .await?;
+
+            // answers come from the list it left, so look at where it went
+            let sent = get_items(&api, Some(to.id))
+                .await?
+                .into_iter()
+                .find(|sent| sent.name == item.name && sent.store == item.store);
+            if json {
+                print_json(&sent);
+            } else if command == "move" {
+                println!("Moved {} to {}", item.name, to.name);
+            } else {
+                println!("Copied {} to {}", item.name, to.name);
+            }
+            Ok(())
+        }
+        _ => Err(unknown("grocery", &args)),
+    }
+}
+
+/// `beeb grocery lists ...`
+async fn run_lists(args: Vec<String>, json: bool) -> Result<(), Failure> {
+    match args.as_slice() {
+        [] => {
+            let api = Api::connect(Service::Grocery).await?;
+            let response: ListResponse = api.get("/get-lists").await?;
+            if json {
+                print_json(&response.lists);
+            } else {
+                let rows = response
+                    .lists
+                    .into_iter()
+                    .map(|list| [list.name, local_time(list.created_at)])
+                    .collect();
+                print_table(["List", "Made"], rows);
+            }
+            Ok(())
+        }
+        [command, name] if command == "add" => {
+            let api = Api::connect(Service::Grocery).await?;
+            let response: ListResponse = api.post("/add-list", json!({ "name": name })).await?;
+            let added = response
+                .lists
+                .into_iter()
+                .find(|list| list.name.eq_ignore_ascii_case(name.trim()));
+
+            if json {
+                print_json(&added);
+            } else {
+                println!("Made {}", name.trim());
+            }
+            Ok(())
+        }
+        [command, name, new_name] if command == "rename" => {
+            let api = Api::connect(Service::Grocery).await?;
+            let list = find_list(&api, name).await?;
+            let response: ListResponse = api
+                .post(
+                    &format!("/{}/rename-list", list.id),
+                    json!({ "name": new_name }),
+                )
+                .await?;
+            let renamed = response
+                .lists
+                .into_iter()
+                .find(|renamed| renamed.id == list.id);
+
+            if json {
+                print_json(&renamed);
+            } else {
+                println!("Renamed {} to {}", list.name, new_name.trim());
+            }
+            Ok(())
+        }
+        [command, name] if command == "delete" => {
+            let api = Api::connect(Service::Grocery).await?;
+            let list = find_list(&api, name).await?;
+            let _: ListResponse = api
+                .post(&format!("/{}/delete-list", list.id), json!(null))
+                .await?;
+
+            if json {
+                print_json(&list);
+            } else {
+                println!("Deleted {} and everything on it", list.name);
+            }
+            Ok(())
+        }
+        _ => Err(unknown("grocery lists", &args)),
+    }
+}
+
+/// The list named by `--list`, or `None` for the first one
+async fn pick_list(api: &Api, options: &HashMap<String, String>) -> Result<Option<i64>, Failure> {
+    match options.get("list") {
+        Some(name) => Ok(Some(find_list(api, name).await?.id)),
+        None => Ok(None),
+    }
+}
+
+/// A list by name, not caring about case
+async fn find_list(api: &Api, name: &str) -> Result<List, Failure> {
+    let response: ListResponse = api.get("/get-lists").await?;
+    response
+        .lists
+        .into_iter()
+        .find(|list| list.name.eq_ignore_ascii_case(name.trim()))
+        .ok_or_else(|| Failure::Failed(format!("There's no grocery list called {name:?}")))
+}
+
+async fn get_items(api: &Api, list: Option<i64>) -> Result<Vec<Item>, Failure> {
+    let path = match list {
+        Some(list) => format!("/get-items?list_id={list}"),
+        None => "/get-items".to_string(),
+    };
+    let response: ItemResponse = api.get(&path).await?;
+    Ok(response.items)
+}
+
+/// An item on the `--list` list by name, not caring about case. `--store` picks between the same
+/// thing from different stores.
+async fn find(api: &Api, name: &str, options: &HashMap<String, String>) -> Result<Item, Failure> {
+    let list = pick_list(api, options).await?;
+    let store = options.get("store");
+    let mut matching: Vec<_> = get_items(api, list)
+        .await?
+        .into_iter()
+        .filter(|item| item.name.eq_ignore_ascii_case(name.trim()))
+        .filter(|item| store.is_none() || item.store.as_ref() == store)
+        .collect();
+
+    match matching.len() {
+        0 => Err(Failure::Failed(format!(
+            "There's no {name:?} on the grocery list"
+        ))),
+        1 => Ok(matching.remove(0)),
+        _ => {
+            let stores: Vec<_> = matching
+                .iter()
+                .filter_map(|item| item.store.as_deref())
+                .collect();
+            Err(Failure::Usage(format!(
+                "{name:?} is on the list for {}, pick one with --store",
+                stores.join(" and ")
+            )))
+        }
+    }
+}
+
+fn show_items(mut items: Vec<Item>, all: bool, json: bool) {
+    // the order they're walked past in the store
+    items.sort_by(|a, b| {
+        (&a.store, &a.category, &a.name.to_lowercase()).cmp(&(
+            &b.store,
+            &b.category,
+            &b.name.to_lowercase(),
+        ))
+    });
+
+    if json {
+        print_json(&items);
+        return;
+    }
+
+    let rows = items
+        .into_iter()
+        .map(|item| {
+            [
+                item.name,
+                item.qty.unwrap_or_default(),
+                item.store.unwrap_or_default(),
+                item.category.unwrap_or_default(),
+                if !all {
+                    String::new()
+                } else if item.active {
+                    "yes".to_string()
+                } else {
+                    "no".to_string()
+                },
+            ]
+        })
+        .collect();
+    print_table(
+        [
+            "Item",
+            "Qty",
+            "Store",
+            "Category",
+            if all { "Needed" } else { "" },
+        ],
+        rows,
+    );
+}
@@ -0,0 +1,183 @@
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    Failure,
+    api::{Api, Service},
+    print_json, print_table, take_options, unknown,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct Habit {
+    id: i64,
+    name: String,
+    date: String,
+    updated_at: i64,
+}
+
+#[derive(Deserialize)]
+struct HabitsResponse {
+    habits: Vec<Habit>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct HabitTemplate {
+    name: String,
+    max_occurrences: Option<i64>,
+}
+
+impl HabitTemplate {
+    /// How many times a day it counts, once unless the template says otherwise
+    fn max(&self) -> i64 {
+        self.max_occurrences.unwrap_or(1)
+    }
+}
+
+#[derive(Deserialize)]
+struct TemplatesResponse {
+    templates: Vec<HabitTemplate>,
+}
+
+/// One row of `habit list`
+#[derive(Serialize)]
+struct Progress {
+    name: String,
+    count: i64,
+    max: i64,
+}
+
+/// `beeb habit ...`
+pub async fn run(args: Vec<String>, json: bool) -> Result<(), Failure> {
+    let (args, options) = take_options(args, &["date"])?;
+    let date = match options.get("date") {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| Failure::Usage(format!("{date:?} isn't a YYYY-MM-DD date")))?,
+        None => Local::now().date_naive(),
+    }
+    .format("%Y-%m-%d")
+    .to_string();
+
+    match args.as_slice() {
+        [command] if command == "list" => {
+            let api = Api::connect(Service::Habits).await?;
+            let habits = get_habits(&api).await?;
+            let templates = get_templates(&api).await?;
+            list(&habits, &templates, &date, json);
+            Ok(())
+        }
+        [command, name] if command == "log" => {
+            let api = Api::connect(Service::Habits).await?;
+            let template = get_templates(&api)
+                .await?
+                .into_iter()
+                .find(|template| template.name.eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| Failure::Failed(format!("There's no habit called {name:?}")))?;
+
+            // the page stops counting once a habit's done as many times as it can be in a day
+            let count = count(&get_habits(&api).await?, &template.name, &date);
+            if count >= template.max() {
+                return Err(Failure::Failed(format!(
+                    "{} is already done {count} of {} times on {date}",
+                    template.name,
+                    template.max()
+                )));
+            }
+
+            let response: HabitsResponse = api
+                .post("/add-habit", json!({ "name": template.name, "date": date }))
+                .await?;
+
+            if json {
+                let logged = response
+                    .habits
+                    .into_iter()
+                    .filter(|habit| habit.name == template.name && habit.date == date)
+                    .max_by_key(|habit| habit.id);
+                print_json(&logged);
+            } else {
+                println!(
+                    "Logged {} on {date} ({} of {})",
+                    template.name,
+                    count + 1,
+                    template.max()
+                );
+            }
+            Ok(())
+        }
+        [command] if command == "undo" => {
+            if !options.is_empty() {
+                return Err(Failure::Usage(
+                    "`undo` always takes back the last one logged".to_string(),
+                ));
+            }
+            let api = Api::connect(Service::Habits).await?;
+            let before = get_habits(&api).await?;
+            let response: HabitsResponse = api.post("/undo-last", json!(null)).await?;
+            // the server doesn't say what it took back, so it's whatever went missing
+            let undone = before
+                .into_iter()
+                .find(|habit| !response.habits.iter().any(|after| after.id == habit.id));
+
+            if json {
+                print_json(&undone);
+            } else if let Some(undone) = undone {
+                println!("Took back {} on {}", undone.name, undone.date);
+            } else {
+                println!("Took back the last one logged");
+            }
+            Ok(())
+        }
+        _ => Err(unknown("habit", &args)),
+    }
+}
+
+async fn get_habits(api: &Api) -> Result<Vec<Habit>, Failure> {
+    let response: HabitsResponse = api.get("/get-habits").await?;
+    Ok(response.habits)
+}
+
+async fn get_templates(api: &Api) -> Result<Vec<HabitTemplate>, Failure> {
+    let response: TemplatesResponse = api.get("/get-templates").await?;
+    Ok(response.templates)
+}
+
+fn count(habits: &[Habit], name: &str, date: &str) -> i64 {
+    habits
+        .iter()
+        .filter(|habit| habit.name == name && habit.date == date)
+        .count() as i64
+}
+
+fn list(habits: &[Habit], templates: &[HabitTemplate], date: &str, json: bool) {
+    let progress: Vec<_> = templates
+        .iter()
+        .map(|template| Progress {
+            name: template.name.clone(),
+            count: count(habits, &template.name, date),
+            max: template.max(),
+        })
+        .collect();
+
+    if json {
+        print_json(&progress);
+        return;
+    }
+
+    let rows = progress
+        .into_iter()
+        .map(|progress| {
+            let done = if progress.count >= progress.max {
+                "yes"
+            } else {
+                "no"
+            };
+            [
+                progress.name,
+                format!("{} of {}", progress.count, progress.max),
+                done.to_string(),
+            ]
+        })
+        .collect();
+    print_table(["Habit", date, "Done"], rows);
+}
@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    Failure,
+    api::{Api, Service},
+    local_time, print_json, print_table, unknown,
+};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct Item {
+    id: String,
+    name: String,
+    created_at: i64,
+}
+
+#[derive(Deserialize)]
+struct ItemResponse {
+    items: Vec<Item>,
+}
+
+/// `beeb inbox ...`
+pub async fn run(args: Vec<String>, json: bool) -> Result<(), Failure> {
+    match args.as_slice() {
+        [command] if command == "list" => {
+            let api = Api::connect(Service::Inbox).await?;
+            let response: ItemResponse = api.get("/get-items").await?;
+            list(response.items, json);
+            Ok(())
+        }
+        // the text doesn't have to be quoted
+        [command, text @ ..] if command == "add" && !text.is_empty() => {
+            let text = text.join(" ");
+            let api = Api::connect(Service::Inbox).await?;
+            let response: ItemResponse = api.post("/add-item", json!({ "name": text })).await?;
+            let added = response
+                .items
+                .into_iter()
+                .filter(|item| item.name == text)
+                .max_by_key(|item| item.created_at);
+
+            if json {
+                print_json(&added);
+            } else {
+                println!("Added {text:?}");
+            }
+            Ok(())
+        }
+        [command, target @ ..] if command == "delete" && !target.is_empty() => {
+            let target = target.join(" ");
+            let api = Api::connect(Service::Inbox).await?;
+            let response: ItemResponse = api.get("/get-items").await?;
+            let item = find(response.items, &target)?;
+            let _: ItemResponse = api
+                .post(&format!("/{}/delete-item", item.id), json!(null))
+                .await?;
+
+            if json {
+                print_json(&item);
+            } else {
+                println!("Deleted {:?}", item.name);
+            }
+            Ok(())
+        }
+        _ => Err(unknown("inbox", &args)),
+    }
+}
+
+/// The item with `target` as its id, or else the only one with `target` as its text
+fn find(items: Vec<Item>, target: &str) -> Result<Item, Failure> {
+    let target = target.trim();
+    if let Some(item) = items.iter().find(|item| item.id == target) {
+        return Ok(item.clone());
+    }
+
+    let mut matching: Vec<_> = items
+        .into_iter()
+        .filter(|item| item.name.eq_ignore_ascii_case(target))
+        .collect();
+    match matching.len() {
+        0 => Err(Failure::Failed(format!(
+            "There's nothing in the inbox like {target:?}"
+        ))),
+        1 => Ok(matching.remove(0)),
+        count => Err(Failure::Failed(format!(
+            "{count} items say {target:?}, delete one by its id from `beeb inbox list`"
+        ))),
+    }
+}
+
+fn list(mut items: Vec<Item>, json: bool) {
+    items.sort_by_key(|item| item.created_at);
+
+    if json {
+        print_json(&items);
+        return;
+    }
+
+    let rows = items
+        .into_iter()
+        .map(|item| [item.id, local_time(item.created_at), item.name])
+        .collect();
+    print_table(["Id", "Added", "Text"], rows);
+}
@@ -0,0 +1,315 @@
+use govee::{Capability, Client, Device, DeviceState, GoveeApi, LanClient, Rgb, Target};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::{
+    Failure, print_json, prompt,
+    root::Root,
+    scenes::{self, Scene},
+    take_flag, tui,
+};
+
+/// Somewhere lights can be listed, switched and asked about: Govee itself, or root on its behalf
+pub trait Lights: Clone + Send + Sync + 'static {
+    fn devices(&self) -> impl Future<Output = Result<Vec<Device>, Failure>> + Send;
+
+    fn control(
+        &self,
+        target: &Target,
+        capability: Capability,
+    ) -> impl Future<Output = Result<(), Failure>> + Send;
+
+    fn state(&self, target: &Target) -> impl Future<Output = Result<DeviceState, Failure>> + Send;
+
+    /// Scenes the TUI offers
+    fn scenes(&self) -> impl Future<Output = Result<Vec<Scene>, Failure>> + Send;
+}
+
+/// Straight to Govee, through its cloud or over the LAN
+#[derive(Clone)]
+pub struct Govee<C>(pub C);
+
+impl<C> Lights for Govee<C>
+where
+    C: GoveeApi + Clone + Send + Sync + 'static,
+{
+    async fn devices(&self) -> Result<Vec<Device>, Failure> {
+        Ok(self.0.devices().await?)
+    }
+
+    async fn control(&self, target: &Target, capability: Capability) -> Result<(), Failure> {
+        Ok(self.0.control(target, capability).await?)
+    }
+
+    async fn state(&self, target: &Target) -> Result<DeviceState, Failure> {
+        Ok(self.0.state(target).await?)
+    }
+
+    /// Govee doesn't keep our scenes, they're read from a file instead
+    async fn scenes(&self) -> Result<Vec<Scene>, Failure> {
+        scenes::load()
+    }
+}
+
+enum Command {
+    Devices,
+    Set(String, Capability),
+    State(String),
+    Tui,
+    Interactive,
+}
+
+#[derive(Serialize)]
+struct Listed<'a> {
+    index: usize,
+    name: &'a str,
+    sku: &'a str,
+    device: &'a str,
+}
+
+#[derive(Serialize)]
+struct Reported<'a> {
+    name: &'a str,
+    sku: &'a str,
+    device: &'a str,
+    #[serde(flatten)]
+    state: DeviceState,
+}
+
+/// `beeb lights`, straight to Govee's cloud unless `--lan` or `--root` say otherwise
+pub async fn run(mut args: Vec<String>, json: bool) -> Result<(), Failure> {
+    // `--lan` talks to the lights directly instead of through Govee's cloud, `--root` leaves
+    // Govee to the server
+    let lan = take_flag(&mut args, "--lan");
+    let through_root = take_flag(&mut args, "--root");
+    let command = parse(&args)?;
+
+    if lan && through_root {
+        Err(Failure::Usage(
+            "--lan and --root don't go together".to_string(),
+        ))
+    } else if lan {
+        execute(&Govee(LanClient::from_env().await?), command, json).await
+    } else if through_root {
+        execute(&Root::connect().await?, command, json).await
+    } else {
+        execute(&Govee(Client::from_env()?), command, json).await
+    }
+}
+
+fn parse(args: &[String]) -> Result<Command, Failure> {
+    match args {
+        [] => Ok(Command::Interactive),
+        [command] if command == "devices" => Ok(Command::Devices),
+        [command, device] if command == "on" => {
+            Ok(Command::Set(device.clone(), Capability::Power(true)))
+        }
+        [command, device] if command == "off" => {
+            Ok(Command::Set(device.clone(), Capability::Power(false)))
+        }
+        [command, device, percent] if command == "brightness" => {
+            let percent = percent
+                .trim_end_matches('%')
+                .parse()
+                .ok()
+                .filter(|percent| (1..=100).contains(percent))
+                .ok_or_else(|| {
+                    Failure::Usage(format!("{percent} isn't a brightness between 1 and 100"))
+                })?;
+            Ok(Command::Set(
+                device.clone(),
+                Capability::Brightness(percent),
+            ))
+        }
+        [command, device, hex] if command == "color" => {
+            let color = parse_hex(hex)
+                .ok_or_else(|| Failure::Usage(format!("{hex} isn't a hex color like ff8800")))?;
+            Ok(Command::Set(device.clone(), Capability::Color(color)))
+        }
+        [command, device] if command == "state" => Ok(Command::State(device.clone())),
+        [command] if command == "tui" => Ok(Command::Tui),
+        _ => Err(Failure::Usage(format!(
+            "Don't know what to do with `{}`",
+            args.join(" ")
+        ))),
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Rgb> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok().map(Rgb::from_packed)
+}
+
+/// What to call a device, LAN devices don't have names
+pub fn label(device: &Device) -> String {
+    if device.device_name.is_empty() {
+        format!("{} {}", device.sku, device.device)
+    } else {
+        device.device_name.clone()
+    }
+}
+
+/// A device by name, index or id. Names don't care about case and win over indices, so a light
+/// called "2" can still be reached.
+fn find<'a>(devices: &'a [Device], wanted: &str) -> Result<&'a Device, Failure> {
+    let by_name = devices.iter().find(|device| {
+        device.device_name.eq_ignore_ascii_case(wanted)
+            || device.device.eq_ignore_ascii_case(wanted)
+    });
+    if let Some(device) = by_name {
+        return Ok(device);
+    }
+
+    match wanted.parse::<usize>() {
+        Ok(index) => devices.get(index).ok_or_else(|| {
+            Failure::Failed(format!(
+                "There's no device {index}, there are {}",
+                devices.len()
+            ))
+        }),
+        Err(_) => Err(Failure::Failed(format!(
+            "There's no device called {wanted:?}, `beeb lights devices` lists them"
+        ))),
+    }
+}
+
+async fn execute(lights: &impl Lights, command: Command, json: bool) -> Result<(), Failure> {
+    let devices = lights.devices().await?;
+
+    match command {
+        Command::Devices => {
+            if json {
+                let listed: Vec<_> = devices
+                    .iter()
+                    .enumerate()
+                    .map(|(index, device)| Listed {
+                        index,
+                        name: &device.device_name,
+                        sku: &device.sku,
+                        device: &device.device,
+                    })
+                    .collect();
+                print_json(&listed);
+            } else {
+                for (index, device) in devices.iter().enumerate() {
+                    println!("{index}: {}", label(device));
+                }
+            }
+            Ok(())
+        }
+        Command::Set(wanted, capability) => {
+            let device = find(&devices, &wanted)?;
+            set(lights, device, capability).await?;
+
+            if json {
+                print_json(&json!({
+                    "name": device.device_name,
+                    "sku": device.sku,
+                    "device": device.device,
+                    "instance": capability.instance(),
+                    "value": capability.value(),
+                }));
+            } else {
+                println!("{}: {}", label(device), describe(capability));
+            }
+            Ok(())
+        }
+        Command::State(wanted) => {
+            let device = find(&devices, &wanted)?;
+            let state = lights.state(&device.target()).await?;
+
+            if json {
+                print_json(&Reported {
+                    name: &device.device_name,
+                    sku: &device.sku,
+                    device: &device.device,
+                    state,
+                });
+            } else {
+                println!("{}", label(device));
+                print_state(&state);
+            }
+            Ok(())
+        }
+        Command::Tui => {
+            let scenes = lights.scenes().await?;
+            tui::run(lights.clone(), devices, scenes).await
+        }
+        Command::Interactive => interactive(lights, &devices).await,
+    }
+}
+
+/// Sends one change, refusing ones the device says it can't do
+async fn set(lights: &impl Lights, device: &Device, capability: Capability) -> Result<(), Failure> {
+    check(device, capability)?;
+    lights.control(&device.target(), capability).await
+}
+
+/// Whether the device says it can take a change. Root's devices don't say, root checks itself.
+pub fn check(device: &Device, capability: Capability) -> Result<(), Failure> {
+    if device.capabilities.is_empty() {
+        return Ok(());
+    }
+
+    let support = device.support();
+    match capability {
+        Capability::Brightness(_) if !support.brightness => Err(Failure::Failed(format!(
+            "{} doesn't do brightness",
+            label(device)
+        ))),
+        Capability::Color(_) if !support.color => Err(Failure::Failed(format!(
+            "{} doesn't do colors",
+            label(device)
+        ))),
+        _ => Ok(()),
+    }
+}
+
+pub fn describe(capability: Capability) -> String {
+    match capability {
+        Capability::Power(true) => "on".to_string(),
+        Capability::Power(false) => "off".to_string(),
+        Capability::Brightness(percent) => format!("brightness {percent}%"),
+        Capability::Color(color) => format!("color #{:06x}", color.to_packed()),
+        Capability::ColorTemperature(kelvin) => format!("color temperature {kelvin}K"),
+    }
+}
+
+fn print_state(state: &DeviceState) {
+    let yes_no = |value: bool| if value { "yes" } else { "no" };
+
+    if let Some(online) = state.online {
+        println!("  online: {}", yes_no(online));
+    }
+    if let Some(power) = state.power {
+        println!("  power: {}", if power { "on" } else { "off" });
+    }
+    if let Some(brightness) = state.brightness {
+        println!("  brightness: {brightness}%");
+    }
+    if let Some(color) = state.color {
+        println!("  color: #{:06x}", color.to_packed());
+    }
+    if let Some(kelvin) = state.kelvin {
+        println!("  color temperature: {kelvin}K");
+    }
+}
+
+/// The original toggle prompt, for poking at lights by hand
+async fn interactive(lights: &impl Lights, devices: &[Device]) -> Result<(), Failure> {
+    for (index, device) in devices.iter().enumerate() {
+        println!("{index}: {}", label(device));
+    }
+
+    let device = find(devices, &prompt("Toggle: "))?;
+    let on = match prompt("On (1)/Off (0): ").as_str() {
+        "1" => true,
+        "0" => false,
+        other => return Err(Failure::Usage(format!("{other:?} isn't 1 or 0"))),
+    };
+
+    set(lights, device, Capability::Power(on)).await
+}
@@ -0,0 +1,252 @@
+mod api;
+mod chores;
+mod credentials;
+mod exercise;
+mod grocery;
+mod habits;
+mod inbox;
+mod lights;
+mod media;
+mod root;
+mod scenes;
+mod tui;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use std::{collections::HashMap, io::Write, process::ExitCode};
+
+const USAGE: &str = "\
+Usage: beeb [--json] <COMMAND>
+
+Account:
+  login <username>                       Log in and remember the session
+  login --token                          Remember an API token instead
+  logout                                 Forget the session
+
+Groceries:
+  grocery list [--all] [--store <store>] What's needed, or everything with --all
+  grocery add <name> [--qty <qty>] [--store <store>] [--category <category>]
+  grocery toggle <name> [--store <store>]
+                                         Put an item on or take it off the list, --store
+                                         picks when it's needed from more than one
+  grocery delete <name> [--store <store>]
+  grocery move <name> --to <list> [--store <store>]
+  grocery copy <name> --to <list> [--store <store>]
+  grocery lists                          Every grocery list
+  grocery lists add <name>
+  grocery lists rename <name> <new name>
+  grocery lists delete <name>            Along with everything on it
+
+  Item commands take --list <list> to work on a list other than the first.
+
+Chores:
+  chore list
+  chore done <name>
+
+Habits:
+  habit list [--date <YYYY-MM-DD>]       What's been logged today, or on another day
+  habit log <name> [--date <YYYY-MM-DD>]
+  habit undo                             Take back the last one logged
+
+Exercise:
+  exercise list
+  exercise log <name> [<amount>]         20x3x10 is weight x sets x reps, 3x10 leaves out the
+                                         weight, and 30x2.5 is minutes x distance for cardio.
+                                         Without an amount it's the same as last time.
+
+Media:
+  media list [--category <category>]
+  media add <name> --category <category>
+  media delete <name>
+
+Inbox:
+  inbox list
+  inbox add <text>
+  inbox delete <id or text>
+
+Lights:
+  lights [--lan | --root] devices        List devices with their indices
+  lights [--lan | --root] on <device>
+  lights [--lan | --root] off <device>
+  lights [--lan | --root] brightness <device> <pct>
+  lights [--lan | --root] color <device> <hex>
+  lights [--lan | --root] state <device>
+  lights [--lan | --root] tui            Full screen control of every light and scene
+  lights [--lan | --root]                Asks which light to toggle
+
+  <device> is a name, an index from `devices` or a Govee device id. --lan talks to the
+  lights over the local network and --root goes through root instead of GOVEE_KEY. Scenes
+  for `tui` come from root, or else GOVEE_SCENES or scenes.json.
+
+--json prints JSON instead of tables. Services are at their beebfam.org addresses unless
+BEEBFAM_URL, BEEBFAM_GROCERY_URL, BEEBFAM_CHORES_URL, BEEBFAM_HABITS_URL,
+BEEBFAM_EXERCISE_URL, BEEBFAM_MEDIA_URL or BEEBFAM_INBOX_URL say otherwise.
+
+Exits with 1 when something fails and 2 when the command doesn't make sense.";
+
+/// Why a command didn't work, which decides the exit code
+enum Failure {
+    /// the command line doesn't make sense
+    Usage(String),
+    /// a service, Govee or a light didn't do it
+    Failed(String),
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::Usage(message) | Failure::Failed(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<govee::Error> for Failure {
+    fn from(err: govee::Error) -> Self {
+        Failure::Failed(err.to_string())
+    }
+}
+
+impl From<reqwest::Error> for Failure {
+    fn from(err: reqwest::Error) -> Self {
+        Failure::Failed(err.to_string())
+    }
+}
+
+impl From<std::io::Error> for Failure {
+    fn from(err: std::io::Error) -> Self {
+        Failure::Failed(err.to_string())
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    _ = dotenvy::dotenv();
+
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    let json = take_flag(&mut args, "--json");
+
+    let result = match args.first().map(String::as_str) {
+        Some("login") => credentials::login(&args[1..]).await,
+        Some("logout") => credentials::logout(&args[1..]).await,
+        Some("grocery") => grocery::run(args.split_off(1), json).await,
+        Some("chore") => chores::run(args.split_off(1), json).await,
+        Some("habit") => habits::run(args.split_off(1), json).await,
+        Some("exercise") => exercise::run(args.split_off(1), json).await,
+        Some("media") => media::run(args.split_off(1), json).await,
+        Some("inbox") => inbox::run(args.split_off(1), json).await,
+        Some("lights") => lights::run(args.split_off(1), json).await,
+        Some(other) => Err(Failure::Usage(format!("There's no {other:?} command"))),
+        None => Err(Failure::Usage("What should it do?".to_string())),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Usage(message)) => {
+            eprintln!("{message}\n\n{USAGE}");
+            ExitCode::from(2)
+        }
+        Err(Failure::Failed(message)) => {
+            eprintln!("Error: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Removes `flag` from `args`, saying whether it was there
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    let before = args.len();
+    args.retain(|arg| arg != flag);
+    args.len() != before
+}
+
+/// Pulls `--name value` options out of `args`, refusing any not in `allowed`. What's left is
+/// returned in order.
+fn take_options(
+    args: Vec<String>,
+    allowed: &[&str],
+) -> Result<(Vec<String>, HashMap<String, String>), Failure> {
+    let mut rest = vec![];
+    let mut options = HashMap::new();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let Some(name) = arg.strip_prefix("--") else {
+            rest.push(arg);
+            continue;
+        };
+        if !allowed.contains(&name) {
+            return Err(Failure::Usage(format!("--{name} doesn't go here")));
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| Failure::Usage(format!("--{name} needs a value")))?;
+        options.insert(name.to_string(), value);
+    }
+
+    Ok((rest, options))
+}
+
+/// Whatever wasn't understood, as a usage error
+fn unknown(service: &str, args: &[String]) -> Failure {
+    Failure::Usage(format!(
+        "Don't know what to do with `{service} {}`",
+        args.join(" ")
+    ))
+}
+
+/// Writes a line to stdout, quietly giving up once whatever's reading it (like `head`) has gone
+fn print_line(line: &str) {
+    _ = writeln!(std::io::stdout(), "{line}");
+}
+
+fn print_json(value: &impl Serialize) {
+    print_line(&serde_json::to_string_pretty(value).unwrap());
+}
+
+/// Prints rows under a header with the columns lined up
+fn print_table<const N: usize>(header: [&str; N], rows: Vec<[String; N]>) {
+    let mut widths = header.map(|title| title.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let line = |cells: [&str; N]| {
+        let padded: Vec<_> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:width$}"))
+            .collect();
+        print_line(padded.join("  ").trim_end());
+    };
+
+    line(header);
+    for row in &rows {
+        line(row.each_ref().map(String::as_str));
+    }
+}
+
+/// A unix timestamp as a local date and time
+fn local_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|time| {
+            time.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default()
+}
+
+fn prompt(question: &str) -> String {
+    print!("{question}");
+    _ = std::io::stdout().flush();
+
+    let mut input = String::new();
+    _ = std::io::stdin().read_line(&mut input);
+    input.trim().to_string()
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    Failure,
+    api::{Api, Service},
+    local_time, print_json, print_table, take_options, unknown,
+};
+
+/// The same categories the media page has
+const CATEGORIES: [&str; 4] = ["books", "movies", "games", "shows"];
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+struct Item {
+    name: String,
+    category: Option<String>,
+    created_at: i64,
+}
+
+#[derive(Deserialize)]
+struct ItemResponse {
+    items: Vec<Item>,
+}
+
+/// `beeb media ...`
+pub async fn run(args: Vec<String>, json: bool) -> Result<(), Failure> {
+    let (args, options) = take_options(args, &["category"])?;
+    let category = options
+        .get("category")
+        .map(|category| {
+            CATEGORIES
+                .into_iter()
+                .find(|known| known.eq_ignore_ascii_case(category))
+                .ok_or_else(|| {
+                    Failure::Usage(format!(
+                        "{category:?} isn't a category, it's one of {}",
+                        CATEGORIES.join(", ")
+                    ))
+                })
+        })
+        .transpose()?;
+
+    match args.as_slice() {
+        [command] if command == "list" => {
+            let api = Api::connect(Service::Media).await?;
+            let mut items = get_items(&api).await?;
+            items.retain(|item| category.is_none() || item.category.as_deref() == category);
+            list(items, json);
+            Ok(())
+        }
+        [command, name] if command == "add" => {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(Failure::Usage("Media needs a name".to_string()));
+            }
+            let category = category.ok_or_else(|| {
+                Failure::Usage(format!(
+                    "`add` needs a --category, one of {}",
+                    CATEGORIES.join(", ")
+                ))
+            })?;
+
+            let api = Api::connect(Service::Media).await?;
+            let response: ItemResponse = api
+                .post("/add-item", json!({ "name": name, "category": category }))
+                .await?;
+            let added = response.items.into_iter().find(|item| item.name == name);
+
+            if json {
+                print_json(&added);
+            } else {
+                println!("Added {name} to {category}");
+            }
+            Ok(())
+        }
+        [command, name] if command == "delete" => {
+            if category.is_some() {
+                return Err(Failure::Usage(
+                    "--category only goes with `list` and `add`".to_string(),
+                ));
+            }
+            let api = Api::connect(Service::Media).await?;
+            let item = get_items(&api)
+                .await?
+                .into_iter()
+                .find(|item| item.name.eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| Failure::Failed(format!("There's no {name:?} on the media list")))?;
+            let _: ItemResponse = api
+                .post("/delete-item", json!({ "name": item.name }))
+                .await?;
+
+            if json {
+                print_json(&item);
+            } else {
+                println!("Deleted {}", item.name);
+            }
+            Ok(())
+        }
+        _ => Err(unknown("media", &args)),
+    }
+}
+
+async fn get_items(api: &Api) -> Result<Vec<Item>, Failure> {
+    let response: ItemResponse = api.get("/get-items").await?;
+    Ok(response.items)
+}
+
+fn list(mut items: Vec<Item>, json: bool) {
+    items.sort_by(|a, b| (&a.category, a.created_at).cmp(&(&b.category, b.created_at)));
+
+    if json {
+        print_json(&items);
+        return;
+    }
+
+    let rows = items
+        .into_iter()
+        .map(|item| {
+            [
+                item.name,
+                item.category.unwrap_or_default(),
+                local_time(item.created_at),
+            ]
+        })
+        .collect();
+    print_table(["Name", "Category", "Added"], rows);
+}
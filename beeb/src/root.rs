@@ -0,0 +1,163 @@
+use govee::{Capability, Device, DeviceState, Target};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    Failure,
+    api::{Api, Service},
+    lights::Lights,
+    scenes::Scene,
+};
+
+/// Goes through root's `/light-control` and `/light-state`, so only the server needs
+/// `GOVEE_KEY`. Lights go by root's names.
+#[derive(Clone, Debug)]
+pub struct Root {
+    api: Api,
+    /// root's names by Govee device id, root controls lights by name
+    names: Arc<Mutex<HashMap<String, String>>>,
+}
+
+#[derive(Deserialize)]
+struct RootDevice {
+    name: String,
+    device: String,
+    sku: String,
+}
+
+#[derive(Deserialize)]
+struct DevicesResponse {
+    devices: Vec<RootDevice>,
+}
+
+#[derive(Deserialize)]
+struct LightResult {
+    name: String,
+    status: String,
+    #[serde(default)]
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LightResponse {
+    results: Vec<LightResult>,
+}
+
+#[derive(Deserialize)]
+struct LightState {
+    name: String,
+    #[serde(flatten)]
+    state: DeviceState,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LightStateResponse {
+    lights: Vec<LightState>,
+}
+
+#[derive(Deserialize)]
+struct ScenesResponse {
+    scenes: Vec<Scene>,
+}
+
+impl Root {
+    pub async fn connect() -> Result<Self, Failure> {
+        Ok(Root {
+            api: Api::connect(Service::Root).await?,
+            names: Default::default(),
+        })
+    }
+
+    /// Root's name for a device, listing devices when it hasn't seen them yet
+    async fn name_of(&self, target: &Target) -> Result<String, Failure> {
+        if let Some(name) = self.names.lock().unwrap().get(&target.device) {
+            return Ok(name.clone());
+        }
+
+        self.devices()
+            .await?
+            .into_iter()
+            .find(|device| device.device == target.device)
+            .map(|device| device.device_name)
+            .ok_or_else(|| Failure::Failed(format!("Root doesn't know {}", target.device)))
+    }
+}
+
+impl Lights for Root {
+    /// Root doesn't say what each light can do, it checks commands itself
+    async fn devices(&self) -> Result<Vec<Device>, Failure> {
+        let response: DevicesResponse = self.api.get("/get-devices").await?;
+
+        let mut names = self.names.lock().unwrap();
+        let devices = response
+            .devices
+            .into_iter()
+            .map(|device| {
+                names.insert(device.device.clone(), device.name.clone());
+                Device {
+                    sku: device.sku,
+                    device: device.device,
+                    device_name: device.name,
+                    kind: "devices.types.light".to_string(),
+                    capabilities: vec![],
+                }
+            })
+            .collect();
+
+        Ok(devices)
+    }
+
+    async fn control(&self, target: &Target, capability: Capability) -> Result<(), Failure> {
+        let name = self.name_of(target).await?;
+        let mut light = json!({ "name": name });
+        match capability {
+            Capability::Power(on) => light["toggle"] = json!(on),
+            Capability::Brightness(percent) => light["brightness"] = json!(percent),
+            Capability::Color(color) => light["color"] = json!(color),
+            Capability::ColorTemperature(kelvin) => light["kelvin"] = json!(kelvin),
+        }
+
+        let response: LightResponse = self
+            .api
+            .post("/light-control", json!({ "requests": [light] }))
+            .await?;
+
+        match response
+            .results
+            .into_iter()
+            .find(|result| result.status != "ok")
+        {
+            None => Ok(()),
+            Some(result) => Err(Failure::Failed(match result.message {
+                Some(message) => message,
+                None => format!("{}: {}", result.name, result.status.replace('_', " ")),
+            })),
+        }
+    }
+
+    async fn state(&self, target: &Target) -> Result<DeviceState, Failure> {
+        let name = self.name_of(target).await?;
+        let response: LightStateResponse = self.api.get("/light-state").await?;
+
+        let light = response
+            .lights
+            .into_iter()
+            .find(|light| light.name == name)
+            .ok_or_else(|| Failure::Failed(format!("Root doesn't know {name}")))?;
+        match light.error {
+            Some(err) => Err(Failure::Failed(err)),
+            None => Ok(light.state),
+        }
+    }
+
+    async fn scenes(&self) -> Result<Vec<Scene>, Failure> {
+        let response: ScenesResponse = self.api.get("/get-scenes").await?;
+        Ok(response.scenes)
+    }
+}
@@ -0,0 +1,64 @@
+use govee::{Capability, Rgb};
+use serde::Deserialize;
+use std::{env, fs, io};
+
+use crate::Failure;
+
+/// Where scenes are read from when `GOVEE_SCENES` isn't set
+const DEFAULT_PATH: &str = "scenes.json";
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Scene {
+    pub name: String,
+    pub lights: Vec<SceneLight>,
+}
+
+/// What a scene changes on one light, the same as root's light entries
+#[derive(Deserialize, Debug, Clone)]
+pub struct SceneLight {
+    pub name: String,
+    pub toggle: Option<bool>,
+    /// percent
+    pub brightness: Option<u8>,
+    pub color: Option<Rgb>,
+    pub kelvin: Option<i64>,
+}
+
+impl SceneLight {
+    /// Govee takes one capability per request, so each change becomes its own
+    pub fn capabilities(&self) -> Vec<Capability> {
+        let mut capabilities = vec![];
+        if let Some(on) = self.toggle {
+            capabilities.push(Capability::Power(on));
+        }
+        if let Some(percent) = self.brightness {
+            capabilities.push(Capability::Brightness(percent));
+        }
+        if let Some(color) = self.color {
+            capabilities.push(Capability::Color(color));
+        }
+        if let Some(kelvin) = self.kelvin {
+            capabilities.push(Capability::ColorTemperature(kelvin));
+        }
+        capabilities
+    }
+}
+
+/// The same shape as root's `/get-scenes`, so its answer can be saved as is
+#[derive(Deserialize)]
+struct SceneFile {
+    scenes: Vec<Scene>,
+}
+
+/// Scenes from `GOVEE_SCENES` or `scenes.json`. No file just means no scenes.
+pub fn load() -> Result<Vec<Scene>, Failure> {
+    let path = env::var("GOVEE_SCENES").unwrap_or_else(|_| DEFAULT_PATH.to_string());
+
+    match fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str::<SceneFile>(&text)
+            .map(|file| file.scenes)
+            .map_err(|err| Failure::Failed(format!("{path} isn't a scene file: {err}"))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(Failure::Failed(format!("Couldn't read {path}: {err}"))),
+    }
+}
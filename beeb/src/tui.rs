@@ -0,0 +1,465 @@
+use govee::{Capability, Device, DeviceState};
+use ratatui::{
+    DefaultTerminal, Frame,
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, List, ListState, Row, Table, TableState},
+};
+use std::time::Duration;
+use tokio::{
+    sync::mpsc::{UnboundedSender, unbounded_channel},
+    time::{Instant, sleep_until},
+};
+
+use crate::{
+    Failure,
+    lights::{Lights, check, describe, label},
+    scenes::{Scene, SceneLight},
+};
+
+/// How often every light is asked for its state. Govee allows 10,000 requests a day and each
+/// refresh costs one per light.
+const REFRESH: Duration = Duration::from_secs(30);
+/// Brightness waits this long for more arrow presses before being sent, so holding a key
+/// doesn't send a request per step
+const BRIGHTNESS_SETTLE: Duration = Duration::from_millis(400);
+const BRIGHTNESS_STEP: u8 = 10;
+const HELP: &str =
+    "↑↓ pick  space toggle  ←→ brightness  tab lights/scenes  enter apply scene  r refresh  q quit";
+
+/// Answers from the lights, sent back to the loop by the tasks that asked
+enum Update {
+    State(usize, Result<DeviceState, Failure>),
+    Sent {
+        index: usize,
+        /// what went through before anything failed
+        sent: Vec<Capability>,
+        error: Option<Failure>,
+    },
+    SceneApplied {
+        name: String,
+        failures: Vec<String>,
+    },
+}
+
+#[derive(PartialEq, Eq)]
+enum Focus {
+    Lights,
+    Scenes,
+}
+
+struct App {
+    devices: Vec<Device>,
+    /// by device index, `None` until the light has answered
+    states: Vec<Option<DeviceState>>,
+    errors: Vec<Option<String>>,
+    scenes: Vec<Scene>,
+    focus: Focus,
+    table: TableState,
+    scene_list: ListState,
+    status: String,
+    /// a brightness waiting for the arrow keys to settle: device index, percent and when to send
+    pending: Option<(usize, u8, Instant)>,
+}
+
+pub async fn run<L: Lights>(
+    lights: L,
+    devices: Vec<Device>,
+    scenes: Vec<Scene>,
+) -> Result<(), Failure> {
+    if devices.is_empty() {
+        return Err(Failure::Failed("There aren't any devices".to_string()));
+    }
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, lights, devices, scenes).await;
+    ratatui::restore();
+    result
+}
+
+async fn event_loop<L: Lights>(
+    terminal: &mut DefaultTerminal,
+    lights: L,
+    devices: Vec<Device>,
+    scenes: Vec<Scene>,
+) -> Result<(), Failure> {
+    let mut app = App {
+        states: vec![None; devices.len()],
+        errors: vec![None; devices.len()],
+        devices,
+        scenes,
+        focus: Focus::Lights,
+        table: TableState::default().with_selected(0),
+        scene_list: ListState::default().with_selected(Some(0)),
+        status: String::new(),
+        pending: None,
+    };
+
+    // crossterm only reads terminal events by blocking
+    let (events_tx, mut events) = unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if events_tx.send(event).is_err() {
+                break;
+            }
+        }
+    });
+
+    let (updates_tx, mut updates) = unbounded_channel();
+    let mut refresh = tokio::time::interval(REFRESH);
+
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+
+        let settle_at = app.pending.map(|(_, _, at)| at);
+        tokio::select! {
+            _ = refresh.tick() => app.refresh(&lights, &updates_tx),
+            Some(event) = events.recv() => {
+                if let Event::Key(key) = event
+                    && key.kind == KeyEventKind::Press
+                    && !app.key(key, &lights, &updates_tx)
+                {
+                    // a brightness still settling would be dropped with the runtime
+                    if let Some((index, percent, _)) = app.pending.take() {
+                        let capability = Capability::Brightness(percent);
+                        send_all(&lights, &app.devices[index], vec![capability]).await;
+                    }
+                    return Ok(());
+                }
+            }
+            Some(update) = updates.recv() => app.update(update),
+            _ = sleep_until(settle_at.unwrap_or_else(Instant::now)), if settle_at.is_some() => {
+                app.send_pending(&lights, &updates_tx);
+            }
+        }
+    }
+}
+
+/// Sends capabilities one at a time, stopping at the first that fails
+async fn send_all(
+    lights: &impl Lights,
+    device: &Device,
+    capabilities: Vec<Capability>,
+) -> (Vec<Capability>, Option<Failure>) {
+    let target = device.target();
+    let mut sent = vec![];
+
+    for capability in capabilities {
+        if let Err(err) = lights.control(&target, capability).await {
+            return (sent, Some(err));
+        }
+        sent.push(capability);
+    }
+    (sent, None)
+}
+
+impl App {
+    fn selected(&self) -> usize {
+        self.table.selected().unwrap_or(0)
+    }
+
+    fn refresh<L: Lights>(&mut self, lights: &L, updates: &UnboundedSender<Update>) {
+        for (index, device) in self.devices.iter().enumerate() {
+            let lights = lights.clone();
+            let updates = updates.clone();
+            let target = device.target();
+            tokio::spawn(async move {
+                let state = lights.state(&target).await;
+                _ = updates.send(Update::State(index, state));
+            });
+        }
+    }
+
+    fn send<L: Lights>(
+        &mut self,
+        lights: &L,
+        updates: &UnboundedSender<Update>,
+        index: usize,
+        capability: Capability,
+    ) {
+        let device = self.devices[index].clone();
+        if let Err(failure) = check(&device, capability) {
+            self.status = failure.to_string();
+            return;
+        }
+
+        let lights = lights.clone();
+        let updates = updates.clone();
+        tokio::spawn(async move {
+            let (sent, error) = send_all(&lights, &device, vec![capability]).await;
+            _ = updates.send(Update::Sent { index, sent, error });
+        });
+    }
+
+    fn send_pending<L: Lights>(&mut self, lights: &L, updates: &UnboundedSender<Update>) {
+        if let Some((index, percent, _)) = self.pending.take() {
+            self.send(lights, updates, index, Capability::Brightness(percent));
+        }
+    }
+
+    /// Nudges the selected light's brightness, sent once the keys settle
+    fn nudge<L: Lights>(&mut self, lights: &L, updates: &UnboundedSender<Update>, up: bool) {
+        let index = self.selected();
+        if !self.devices[index].support().brightness {
+            self.status = format!("{} doesn't do brightness", label(&self.devices[index]));
+            return;
+        }
+
+        let current = match self.pending {
+            Some((pending, percent, _)) if pending == index => percent,
+            _ => {
+                // another light's change shouldn't wait on this one
+                self.send_pending(lights, updates);
+                self.states[index]
+                    .as_ref()
+                    .and_then(|state| state.brightness)
+                    .unwrap_or(50)
+            }
+        };
+        let percent = if up {
+            current.saturating_add(BRIGHTNESS_STEP).min(100)
+        } else {
+            current.saturating_sub(BRIGHTNESS_STEP).max(1)
+        };
+
+        self.pending = Some((index, percent, Instant::now() + BRIGHTNESS_SETTLE));
+    }
+
+    fn apply_scene<L: Lights>(&mut self, lights: &L, updates: &UnboundedSender<Update>) {
+        let Some(scene) = self.scene_list.selected().and_then(|i| self.scenes.get(i)) else {
+            return;
+        };
+        let name = scene.name.clone();
+
+        let mut failures = vec![];
+        let mut sends = vec![];
+        for light in &scene.lights {
+            match self.plan(light) {
+                Ok((index, capabilities)) => {
+                    let lights = lights.clone();
+                    let device = self.devices[index].clone();
+                    sends.push(tokio::spawn(async move {
+                        let (sent, error) = send_all(&lights, &device, capabilities).await;
+                        (index, sent, error)
+                    }));
+                }
+                Err(message) => failures.push(message),
+            }
+        }
+
+        self.status = format!("Applying {name}…");
+        let updates = updates.clone();
+        tokio::spawn(async move {
+            for send in sends {
+                let Ok((index, sent, error)) = send.await else {
+                    continue;
+                };
+                if let Some(err) = &error {
+                    failures.push(err.to_string());
+                }
+                _ = updates.send(Update::Sent { index, sent, error });
+            }
+            _ = updates.send(Update::SceneApplied { name, failures });
+        });
+    }
+
+    /// Which device a scene light means and what to send it
+    fn plan(&self, light: &SceneLight) -> Result<(usize, Vec<Capability>), String> {
+        let index = self
+            .devices
+            .iter()
+            .position(|device| device.device_name.eq_ignore_ascii_case(&light.name))
+            .ok_or_else(|| format!("There's no device called {:?}", light.name))?;
+
+        let capabilities = light.capabilities();
+        for capability in &capabilities {
+            check(&self.devices[index], *capability).map_err(|failure| failure.to_string())?;
+        }
+        Ok((index, capabilities))
+    }
+
+    /// Handles a key press, false means quit
+    fn key<L: Lights>(
+        &mut self,
+        key: KeyEvent,
+        lights: &L,
+        updates: &UnboundedSender<Update>,
+    ) -> bool {
+        match (key.code, &self.focus) {
+            (KeyCode::Char('q') | KeyCode::Esc, _) => return false,
+            (KeyCode::Tab, Focus::Lights) if !self.scenes.is_empty() => self.focus = Focus::Scenes,
+            (KeyCode::Tab, Focus::Scenes) => self.focus = Focus::Lights,
+            (KeyCode::Char('r'), _) => {
+                self.status = "Refreshing…".to_string();
+                self.refresh(lights, updates);
+            }
+            (KeyCode::Up | KeyCode::Char('k'), Focus::Lights) => self.table.select_previous(),
+            (KeyCode::Down | KeyCode::Char('j'), Focus::Lights) => {
+                let next = (self.selected() + 1).min(self.devices.len() - 1);
+                self.table.select(Some(next));
+            }
+            (KeyCode::Char(' ') | KeyCode::Enter, Focus::Lights) => {
+                let index = self.selected();
+                let on = self.states[index]
+                    .as_ref()
+                    .and_then(|state| state.power)
+                    .is_none_or(|on| !on);
+                self.send(lights, updates, index, Capability::Power(on));
+            }
+            (KeyCode::Left, Focus::Lights) => self.nudge(lights, updates, false),
+            (KeyCode::Right, Focus::Lights) => self.nudge(lights, updates, true),
+            (KeyCode::Up | KeyCode::Char('k'), Focus::Scenes) => self.scene_list.select_previous(),
+            (KeyCode::Down | KeyCode::Char('j'), Focus::Scenes) => {
+                let next = self.scene_list.selected().map_or(0, |i| i + 1);
+                self.scene_list
+                    .select(Some(next.min(self.scenes.len().saturating_sub(1))));
+            }
+            (KeyCode::Char(' ') | KeyCode::Enter, Focus::Scenes) => {
+                self.apply_scene(lights, updates);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn update(&mut self, update: Update) {
+        match update {
+            Update::State(index, Ok(state)) => {
+                self.states[index] = Some(state);
+                self.errors[index] = None;
+            }
+            Update::State(index, Err(err)) => self.errors[index] = Some(err.to_string()),
+            Update::Sent { index, sent, error } => {
+                let state = self.states[index].get_or_insert_default();
+                for capability in &sent {
+                    state.apply(*capability);
+                }
+
+                let name = label(&self.devices[index]);
+                self.status = match &error {
+                    Some(err) => format!("{name}: {err}"),
+                    None => {
+                        let changes: Vec<_> = sent.into_iter().map(describe).collect();
+                        format!("{name}: {}", changes.join(", "))
+                    }
+                };
+                self.errors[index] = error.map(|err| err.to_string());
+            }
+            Update::SceneApplied { name, failures } if failures.is_empty() => {
+                self.status = format!("Applied {name}");
+            }
+            Update::SceneApplied { name, failures } => {
+                self.status = format!("{name} didn't fully apply: {}", failures.join("; "));
+            }
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [body, status, help] = Layout::vertical([
+            Constraint::Min(0),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [lights_area, scenes_area] =
+            Layout::horizontal([Constraint::Percentage(70), Constraint::Percentage(30)])
+                .areas(body);
+
+        let focused = |focus: Focus| {
+            if self.focus == focus {
+                Style::new().fg(Color::Cyan)
+            } else {
+                Style::new()
+            }
+        };
+        let highlight = Style::new().add_modifier(Modifier::REVERSED);
+
+        let rows = self.devices.iter().enumerate().map(|(index, device)| {
+            let state = self.states[index].as_ref();
+            let pending = self
+                .pending
+                .filter(|(pending, _, _)| *pending == index)
+                .map(|(_, percent, _)| percent);
+
+            let power = match state.and_then(|state| state.power) {
+                Some(true) => "on",
+                Some(false) => "off",
+                None => "?",
+            };
+            let brightness = match (pending, state.and_then(|state| state.brightness)) {
+                (Some(percent), _) => format!("{percent}%…"),
+                (None, Some(percent)) => format!("{percent}%"),
+                (None, None) => String::new(),
+            };
+            let color = match (
+                state.and_then(|state| state.color),
+                state.and_then(|state| state.kelvin),
+            ) {
+                (Some(color), _) => format!("#{:06x}", color.to_packed()),
+                (None, Some(kelvin)) => format!("{kelvin}K"),
+                (None, None) => String::new(),
+            };
+            let note = match (&self.errors[index], state.and_then(|state| state.online)) {
+                (Some(err), _) => err.clone(),
+                (None, Some(false)) => "offline".to_string(),
+                _ => String::new(),
+            };
+
+            let row = Row::new([label(device), power.to_string(), brightness, color, note]);
+            if self.errors[index].is_some() {
+                row.style(Style::new().fg(Color::Red))
+            } else {
+                row
+            }
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(3),
+                Constraint::Length(5),
+                Constraint::Length(10),
+                Constraint::Length(8),
+                Constraint::Fill(2),
+            ],
+        )
+        .header(
+            Row::new(["Light", "Power", "Brightness", "Color", ""])
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+        )
+        .row_highlight_style(highlight)
+        .block(
+            Block::bordered()
+                .title("Lights")
+                .border_style(focused(Focus::Lights)),
+        );
+        frame.render_stateful_widget(table, lights_area, &mut self.table);
+
+        let scenes_block = Block::bordered()
+            .title("Scenes")
+            .border_style(focused(Focus::Scenes));
+        if self.scenes.is_empty() {
+            frame.render_widget(
+                List::new(["None, save root's /get-scenes as scenes.json"]).block(scenes_block),
+                scenes_area,
+            );
+        } else {
+            let names = self.scenes.iter().map(|scene| scene.name.as_str());
+            let list = List::new(names)
+                .highlight_style(if self.focus == Focus::Scenes {
+                    highlight
+                } else {
+                    Style::new()
+                })
+                .block(scenes_block);
+            frame.render_stateful_widget(list, scenes_area, &mut self.scene_list);
+        }
+
+        frame.render_widget(Line::raw(self.status.as_str()), status);
+        frame.render_widget(
+            Line::raw(HELP).style(Style::new().add_modifier(Modifier::DIM)),
+            help,
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use auth::AppError;
 use axum::{
     Json, Router,
     extract::{ConnectInfo, Path, State},
@@ -10,8 +11,9 @@ use dotenvy::dotenv;
 use log::info;
 use rust_embed::Embed;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::{Pool, Sqlite, SqlitePool, sqlite::SqliteConnectOptions};
-use std::{env, fs, net::SocketAddr};
+use std::{env, net::SocketAddr};
 
 const SECS_IN_DAY: u64 = 60 * 60 * 24;
 
@@ -34,7 +36,7 @@ struct ChoreResponse {
 #[derive(Clone, Debug)]
 struct AppState {
     pub pool: Pool<Sqlite>,
-    pub key: String,
+    pub auth: auth::Auth,
 }
 
 #[tokio::main]
@@ -51,8 +53,7 @@ async fn main() -> anyhow::Result<()> {
 
     sqlx::migrate!("./migrations").run(&pool).await?;
 
-    let key_path = std::env::var("KEY_PATH")?;
-    let key = fs::read_to_string(key_path)?.trim().to_string();
+    let auth = auth::Auth::from_env("chore-kanban").await?;
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 8081));
     let listener = tokio::net::TcpListener::bind(addr).await?;
@@ -64,7 +65,14 @@ async fn main() -> anyhow::Result<()> {
         .route("/assets/{*file}", get(static_handler))
         .route("/get-chores", get(get_chores_handler))
         .route("/{id}/toggle-chore", post(toggle_chore_handler))
-        .with_state(AppState { pool, key });
+        .layer(
+            auth::AuthLayer::new(auth.clone())
+                .trust_domain("beebfam.org")
+                .allow("/")
+                .allow("/index.html")
+                .allow("/assets/*"),
+        )
+        .with_state(AppState { pool, auth });
 
     println!("listening on {addr}");
     _ = axum::serve(
@@ -185,6 +193,17 @@ async fn toggle_chore_handler(
         .await?;
     }
 
+    let updated_chore = get_chore_by_id(&id, &state.pool).await?;
+    state
+        .auth
+        .audit(
+            &current_user,
+            "toggle-chore",
+            json!(chore),
+            json!(updated_chore),
+        )
+        .await?;
+
     let chores = get_chores(&state.pool).await?;
     Ok(Json(ChoreResponse { chores }))
 }
@@ -264,27 +283,6 @@ fn map_record_to_chore(record: &ChoreRow) -> Chore {
     }
 }
 
-pub struct AppError(anyhow::Error);
-
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {}", self.0),
-        )
-            .into_response()
-    }
-}
-
-impl<E> From<E> for AppError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(err: E) -> Self {
-        Self(err.into())
-    }
-}
-
 #[derive(Embed)]
 #[folder = "src/client/dist/"]
 struct Asset;
@@ -1,3 +1,4 @@
+use auth::AppError;
 use axum::{
     Json, Router,
     extract::{Query, State},
@@ -9,8 +10,9 @@ use axum::{
 use dotenvy::dotenv;
 use rust_embed::Embed;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::{Pool, Sqlite, SqlitePool, sqlite::SqliteConnectOptions};
-use std::{env, fs, net::SocketAddr};
+use std::{env, net::SocketAddr};
 
 #[derive(sqlx::FromRow, Debug, Deserialize, Serialize, Clone, Default)]
 struct AerobicItem {
@@ -75,7 +77,7 @@ struct TemplateResponse {
 #[derive(Clone, Debug)]
 struct AppState {
     pub pool: Pool<Sqlite>,
-    pub key: String,
+    pub auth: auth::Auth,
 }
 
 #[tokio::main]
@@ -91,8 +93,7 @@ async fn main() -> anyhow::Result<()> {
 
     sqlx::migrate!("./migrations").run(&pool).await?;
 
-    let key_path = std::env::var("KEY_PATH")?;
-    let key = fs::read_to_string(key_path)?.trim().to_string();
+    let auth = auth::Auth::from_env("exercise-tracker").await?;
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 8085));
     let listener = tokio::net::TcpListener::bind(addr).await?;
@@ -110,7 +111,15 @@ async fn main() -> anyhow::Result<()> {
         )
         .route("/get-templates", get(get_templates_handler))
         .route("/add-item", post(add_item_handler))
-        .with_state(AppState { pool, key });
+        .layer(
+            auth::AuthLayer::new(auth.clone())
+                .trust_domain("beebfam.org")
+                .allow("/")
+                .allow("/index.html")
+                .allow("/assets/*")
+                .allow("/heatmap"),
+        )
+        .with_state(AppState { pool, auth });
 
     println!("listening on {addr}");
     _ = axum::serve(listener, app).await;
@@ -152,14 +161,14 @@ async fn get_templates_handler(
     Ok(Json(TemplateResponse { templates }))
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 struct AerobicRequest {
     name: String,
     duration_min: Option<f64>,
     distance: Option<f64>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 struct AnaerobicRequest {
     name: String,
     weight: Option<f64>,
@@ -167,7 +176,7 @@ struct AnaerobicRequest {
     reps: Option<i64>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(tag = "type", rename_all = "lowercase")]
 enum ExerciseItemRequest {
     Aerobic(AerobicRequest),
@@ -182,6 +191,7 @@ async fn add_item_handler(
     let now = chrono::Utc::now().timestamp();
 
     println!("{req:?}");
+    let logged_item = json!(req);
     match req {
         ExerciseItemRequest::Aerobic(req) => {
             sqlx::query!(
@@ -235,6 +245,11 @@ async fn add_item_handler(
         }
     }
 
+    state
+        .auth
+        .audit(&current_user, "add-exercise", json!(null), logged_item)
+        .await?;
+
     let items = get_items(&state.pool).await?;
     Ok(Json(ItemResponse { items }))
 }
@@ -341,27 +356,6 @@ async fn get_templates(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<ExerciseTempla
     Ok(items)
 }
 
-pub struct AppError(anyhow::Error);
-
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {}", self.0),
-        )
-            .into_response()
-    }
-}
-
-impl<E> From<E> for AppError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(err: E) -> Self {
-        Self(err.into())
-    }
-}
-
 #[derive(Embed)]
 #[folder = "src/client/dist/"]
 struct Asset;
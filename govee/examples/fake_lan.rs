@@ -0,0 +1,22 @@
+//! Serves a fake LAN light until killed, answering on `reply_port` like a real one answers on 4002
+//!
+//! `cargo run --example fake_lan --features fake -- 4002`
+
+use govee::{Target, fake::FakeLanDevice};
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let reply_port: u16 = std::env::args()
+        .nth(1)
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(govee::lan::LISTEN_PORT);
+
+    let target = Target {
+        sku: "H612D".to_string(),
+        device: "67:5D:CD:2A:06:06:46:5F".to_string(),
+    };
+    let fake = FakeLanDevice::start(target, reply_port).await?;
+    println!("fake LAN light listening on {}", fake.addr());
+
+    tokio::signal::ctrl_c().await
+}
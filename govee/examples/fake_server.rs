@@ -0,0 +1,19 @@
+//! Serves a fake Govee API with the house's lamps until killed
+//!
+//! `cargo run --example fake_server --features fake -- 127.0.0.1:4080`
+
+use govee::fake::FakeGovee;
+use std::net::SocketAddr;
+
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let addr: SocketAddr = std::env::args()
+        .nth(1)
+        .and_then(|addr| addr.parse().ok())
+        .unwrap_or(([127, 0, 0, 1], 4080).into());
+
+    let fake = FakeGovee::start(addr, FakeGovee::house()).await?;
+    println!("fake Govee listening on {}", fake.base_url());
+
+    tokio::signal::ctrl_c().await
+}
@@ -0,0 +1,232 @@
+use log::warn;
+use reqwest::{
+    StatusCode,
+    header::{HeaderMap, HeaderValue, RETRY_AFTER},
+};
+use serde::{Deserialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use std::{env, time::Duration};
+use uuid::Uuid;
+
+use crate::{Capability, DEFAULT_BASE_URL, Device, DeviceState, Error, GoveeApi, Target};
+
+/// Govee usually answers well under a second
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+/// Doubles after each retry
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// Longer rate limit waits are reported instead of waited out
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(5);
+
+/// Talks to the Govee cloud API. Cheap to clone, clones share connections.
+#[derive(Clone, Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    timeout: Duration,
+    max_attempts: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct Envelope<T> {
+    code: i64,
+    #[serde(default, alias = "msg")]
+    message: String,
+    #[serde(flatten)]
+    body: T,
+}
+
+#[derive(Deserialize, Debug)]
+struct DevicesBody {
+    #[serde(default)]
+    data: Vec<Device>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StateBody {
+    payload: StatePayload,
+}
+
+#[derive(Deserialize, Debug)]
+struct StatePayload {
+    #[serde(default)]
+    capabilities: Vec<StateCapability>,
+}
+
+#[derive(Deserialize, Debug)]
+struct StateCapability {
+    instance: String,
+    state: StateValue,
+}
+
+#[derive(Deserialize, Debug)]
+struct StateValue {
+    #[serde(default)]
+    value: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct Ignored {}
+
+impl Client {
+    pub fn new(api_key: &str) -> Result<Self, Error> {
+        let mut headers = HeaderMap::new();
+        let api_key = HeaderValue::from_str(api_key).map_err(|_| Error::MissingKey)?;
+        headers.insert("Govee-API-Key", api_key);
+
+        let http = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Client {
+            http,
+            base_url: DEFAULT_BASE_URL.to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        })
+    }
+
+    /// Key from `GOVEE_KEY`, and the API from `GOVEE_BASE_URL` when it's set
+    pub fn from_env() -> Result<Self, Error> {
+        let api_key = env::var("GOVEE_KEY").map_err(|_| Error::MissingKey)?;
+        let client = Client::new(&api_key)?;
+
+        Ok(match env::var("GOVEE_BASE_URL") {
+            Ok(base_url) => client.base_url(&base_url),
+            Err(_) => client,
+        })
+    }
+
+    /// Where the API lives, for pointing at a fake
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Per attempt
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Tries per call, counting the first
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sends a request, retrying timeouts, dropped connections, server errors and rate limits
+    /// with backoff
+    async fn call<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<T, Error> {
+        let mut backoff = RETRY_BACKOFF;
+        let mut attempt = 1;
+
+        loop {
+            let (err, retry_after) = match self.call_once(path, body).await {
+                Ok(body) => return Ok(body),
+                Err(failed) => failed,
+            };
+            let wait = retry_after.unwrap_or_default().max(backoff);
+            if !err.is_retryable() || attempt >= self.max_attempts || wait > MAX_RETRY_WAIT {
+                return Err(err);
+            }
+
+            warn!("Govee {path} failed, retrying: {err}");
+            tokio::time::sleep(wait).await;
+            backoff *= 2;
+            attempt += 1;
+        }
+    }
+
+    /// On failure also hands back how long Govee asked us to wait, if it did
+    async fn call_once<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        body: Option<&Value>,
+    ) -> Result<T, (Error, Option<Duration>)> {
+        let url = format!("{}{path}", self.base_url);
+        let request = match body {
+            Some(body) => self.http.post(url).json(body),
+            None => self.http.get(url),
+        };
+        let res = request
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(|err| (err.into(), None))?;
+
+        match res.status() {
+            StatusCode::TOO_MANY_REQUESTS => {
+                let retry_after = res
+                    .headers()
+                    .get(RETRY_AFTER)
+                    .and_then(|val| val.to_str().ok())
+                    .and_then(|val| val.parse().ok())
+                    .map(Duration::from_secs);
+                return Err((Error::RateLimited, retry_after));
+            }
+            status if !status.is_success() => return Err((Error::Status(status), None)),
+            _ => {}
+        }
+
+        let envelope: Envelope<T> = res.json().await.map_err(|err| (err.into(), None))?;
+        match envelope.code {
+            200 => Ok(envelope.body),
+            429 => Err((Error::RateLimited, None)),
+            code => Err((
+                Error::Govee {
+                    code,
+                    message: envelope.message,
+                },
+                None,
+            )),
+        }
+    }
+}
+
+impl GoveeApi for Client {
+    async fn devices(&self) -> Result<Vec<Device>, Error> {
+        let body: DevicesBody = self.call("/router/api/v1/user/devices", None).await?;
+        Ok(body.data)
+    }
+
+    async fn control(&self, target: &Target, capability: Capability) -> Result<(), Error> {
+        let body = json!({
+            "requestId": Uuid::new_v4().to_string(),
+            "payload": {
+                "sku": target.sku,
+                "device": target.device,
+                "capability": capability.to_json()
+            }
+        });
+
+        let _: Ignored = self
+            .call("/router/api/v1/device/control", Some(&body))
+            .await?;
+        Ok(())
+    }
+
+    async fn state(&self, target: &Target) -> Result<DeviceState, Error> {
+        let body = json!({
+            "requestId": Uuid::new_v4().to_string(),
+            "payload": {
+                "sku": target.sku,
+                "device": target.device
+            }
+        });
+
+        let body: StateBody = self
+            .call("/router/api/v1/device/state", Some(&body))
+            .await?;
+
+        let mut state = DeviceState::default();
+        for capability in &body.payload.capabilities {
+            state.read(&capability.instance, &capability.state.value);
+        }
+        Ok(state)
+    }
+}
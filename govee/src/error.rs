@@ -0,0 +1,62 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// `GOVEE_KEY` isn't set
+    MissingKey,
+    /// couldn't reach Govee, timed out or got a body we couldn't read
+    Http(reqwest::Error),
+    /// Govee answered with a non-2xx status
+    Status(reqwest::StatusCode),
+    RateLimited,
+    /// Govee answered but refused, `code` is its own error code
+    Govee {
+        code: i64,
+        message: String,
+    },
+    /// couldn't use the LAN socket
+    Lan(std::io::Error),
+    /// the device didn't answer a LAN scan or status request in time
+    NoReply,
+}
+
+impl Error {
+    /// Whether trying again later could work
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Http(err) => err.is_timeout() || err.is_connect(),
+            Error::Status(status) => status.is_server_error(),
+            Error::RateLimited => true,
+            Error::MissingKey | Error::Govee { .. } | Error::Lan(_) | Error::NoReply => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::MissingKey => write!(f, "GOVEE_KEY isn't set"),
+            Error::Http(err) if err.is_timeout() => write!(f, "Timed out"),
+            Error::Http(err) => write!(f, "{err}"),
+            Error::Status(status) => write!(f, "Govee answered {status}"),
+            Error::RateLimited => write!(f, "Rate limited"),
+            Error::Govee { code, message } => write!(f, "Govee error {code}: {message}"),
+            Error::Lan(err) => write!(f, "LAN: {err}"),
+            Error::NoReply => write!(f, "Didn't answer on the LAN"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Http(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Lan(err)
+    }
+}
@@ -0,0 +1,456 @@
+use axum::{
+    Json, Router,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    Capability, Device, DeviceState, Target,
+    lan::message,
+    model::{power_capability, range_capability},
+};
+
+/// Something to go wrong on an upcoming request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    /// HTTP 429
+    RateLimited,
+    /// a bare HTTP status, like 500
+    Status(u16),
+    /// HTTP 200 with a Govee error code in the body
+    GoveeCode(i64),
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+    devices: Vec<Device>,
+    values: HashMap<Target, HashMap<&'static str, i64>>,
+    controls: Vec<(Target, Capability)>,
+    failures: VecDeque<Failure>,
+}
+
+/// A stand-in for the Govee cloud API served on a local port. Remembers what each device was
+/// set to, rejects capabilities a device doesn't list and can be told to fail.
+#[derive(Clone, Debug)]
+pub struct FakeGovee {
+    base_url: String,
+    state: Arc<Mutex<FakeState>>,
+}
+
+impl FakeGovee {
+    /// Serves `devices` on `addr`, use port 0 for any free one
+    pub async fn start(addr: SocketAddr, devices: Vec<Device>) -> std::io::Result<Self> {
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let base_url = format!("http://{}", listener.local_addr()?);
+        let state = Arc::new(Mutex::new(FakeState {
+            devices,
+            ..Default::default()
+        }));
+
+        let app = Router::new()
+            .route("/router/api/v1/user/devices", get(devices_handler))
+            .route("/router/api/v1/device/control", post(control_handler))
+            .route("/router/api/v1/device/state", post(state_handler))
+            .with_state(state.clone());
+        tokio::spawn(async move {
+            _ = axum::serve(listener, app).await;
+        });
+
+        Ok(FakeGovee { base_url, state })
+    }
+
+    /// Pass to `Client::base_url`
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// The next request fails with `failure`, queue several for several requests
+    pub fn fail_next(&self, failure: Failure) {
+        self.state.lock().unwrap().failures.push_back(failure);
+    }
+
+    /// Every control request that went through, oldest first
+    pub fn controls(&self) -> Vec<(Target, Capability)> {
+        self.state.lock().unwrap().controls.clone()
+    }
+
+    /// What a device's capability was last set to
+    pub fn value(&self, target: &Target, instance: &str) -> Option<i64> {
+        self.state
+            .lock()
+            .unwrap()
+            .values
+            .get(target)?
+            .get(instance)
+            .copied()
+    }
+
+    /// The house's lamps: four H5080 plugs and the H612D studio strip
+    pub fn house() -> Vec<Device> {
+        let plugs = [
+            ("1A:A6:D4:AD:FC:EF:A6:1B", "Tall living room"),
+            ("61:F6:D4:AD:FC:F3:6A:0F", "Small living room"),
+            ("38:31:D4:AD:FC:A8:91:CB", "Bubble lamp"),
+            ("44:CB:D4:AD:FC:EF:A5:E1", "Bedroom black"),
+        ];
+
+        let mut devices: Vec<Device> = plugs
+            .iter()
+            .map(|(device, name)| Device {
+                sku: "H5080".to_string(),
+                device: device.to_string(),
+                device_name: name.to_string(),
+                kind: "devices.types.socket".to_string(),
+                capabilities: vec![power_capability()],
+            })
+            .collect();
+
+        devices.push(Device {
+            sku: "H612D".to_string(),
+            device: "67:5D:CD:2A:06:06:46:5F".to_string(),
+            device_name: "Studio lights".to_string(),
+            kind: "devices.types.light".to_string(),
+            capabilities: vec![
+                power_capability(),
+                range_capability(
+                    "devices.capabilities.range",
+                    "brightness",
+                    Some("unit.percent"),
+                    1,
+                    100,
+                ),
+                range_capability(
+                    "devices.capabilities.color_setting",
+                    "colorRgb",
+                    None,
+                    0,
+                    0xFFFFFF,
+                ),
+                range_capability(
+                    "devices.capabilities.color_setting",
+                    "colorTemperatureK",
+                    None,
+                    2000,
+                    9000,
+                ),
+            ],
+        });
+
+        devices
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ControlRequest {
+    #[serde(rename = "requestId")]
+    request_id: String,
+    payload: ControlPayload,
+}
+
+#[derive(Deserialize, Debug)]
+struct StateRequest {
+    #[serde(rename = "requestId")]
+    request_id: String,
+    payload: Target,
+}
+
+#[derive(Deserialize, Debug)]
+struct ControlPayload {
+    sku: String,
+    device: String,
+    capability: ControlCapability,
+}
+
+#[derive(Deserialize, Debug)]
+struct ControlCapability {
+    instance: String,
+    value: i64,
+}
+
+/// Checks the key and pops a queued failure, like the real API would fail
+fn check_request(headers: &HeaderMap, state: &mut FakeState) -> Option<Response> {
+    if !headers.contains_key("Govee-API-Key") {
+        return Some(StatusCode::UNAUTHORIZED.into_response());
+    }
+
+    match state.failures.pop_front()? {
+        Failure::RateLimited => Some(StatusCode::TOO_MANY_REQUESTS.into_response()),
+        Failure::Status(status) => Some(
+            StatusCode::from_u16(status)
+                .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+                .into_response(),
+        ),
+        Failure::GoveeCode(code) => Some(govee_error(code, "Fake failure")),
+    }
+}
+
+fn govee_error(code: i64, message: &str) -> Response {
+    Json(json!({ "code": code, "msg": message })).into_response()
+}
+
+async fn devices_handler(
+    State(state): State<Arc<Mutex<FakeState>>>,
+    headers: HeaderMap,
+) -> Response {
+    let mut state = state.lock().unwrap();
+    if let Some(failure) = check_request(&headers, &mut state) {
+        return failure;
+    }
+
+    Json(json!({ "code": 200, "message": "success", "data": state.devices })).into_response()
+}
+
+async fn control_handler(
+    State(state): State<Arc<Mutex<FakeState>>>,
+    headers: HeaderMap,
+    Json(req): Json<Value>,
+) -> Response {
+    let mut state = state.lock().unwrap();
+    if let Some(failure) = check_request(&headers, &mut state) {
+        return failure;
+    }
+
+    let Ok(req) = serde_json::from_value::<ControlRequest>(req) else {
+        return govee_error(400, "Invalid parameter");
+    };
+    let target = Target {
+        sku: req.payload.sku,
+        device: req.payload.device,
+    };
+    let instance = req.payload.capability.instance;
+    let value = req.payload.capability.value;
+
+    let Some(device) = state
+        .devices
+        .iter()
+        .find(|device| device.target() == target)
+    else {
+        return govee_error(400, "Device not found");
+    };
+    let Some(info) = device
+        .capabilities
+        .iter()
+        .find(|capability| capability.instance == instance)
+        .cloned()
+    else {
+        return govee_error(400, "Unsupported capability");
+    };
+    if let Some(range) = info.parameters.range
+        && !(range.min..=range.max).contains(&value)
+    {
+        return govee_error(400, "Value out of range");
+    }
+    let Some(capability) = Capability::from_instance(&instance, value) else {
+        return govee_error(400, "Unsupported capability");
+    };
+
+    state
+        .values
+        .entry(target.clone())
+        .or_default()
+        .insert(capability.instance(), value);
+    state.controls.push((target, capability));
+
+    Json(json!({
+        "requestId": req.request_id,
+        "msg": "success",
+        "code": 200,
+        "capability": {
+            "type": info.kind,
+            "instance": instance,
+            "state": { "status": "success" },
+            "value": value
+        }
+    }))
+    .into_response()
+}
+
+/// Reports whatever was last set, 0 for anything never set
+async fn state_handler(
+    State(state): State<Arc<Mutex<FakeState>>>,
+    headers: HeaderMap,
+    Json(req): Json<Value>,
+) -> Response {
+    let mut state = state.lock().unwrap();
+    if let Some(failure) = check_request(&headers, &mut state) {
+        return failure;
+    }
+
+    let Ok(req) = serde_json::from_value::<StateRequest>(req) else {
+        return govee_error(400, "Invalid parameter");
+    };
+    let Some(device) = state
+        .devices
+        .iter()
+        .find(|device| device.target() == req.payload)
+    else {
+        return govee_error(400, "Device not found");
+    };
+
+    let values = state.values.get(&req.payload);
+    let mut capabilities = vec![json!({
+        "type": "devices.capabilities.online",
+        "instance": "online",
+        "state": { "value": true }
+    })];
+    capabilities.extend(device.capabilities.iter().map(|info| {
+        let value = values
+            .and_then(|values| values.get(info.instance.as_str()))
+            .copied()
+            .unwrap_or(0);
+        json!({
+            "type": info.kind,
+            "instance": info.instance,
+            "state": { "value": value }
+        })
+    }));
+
+    Json(json!({
+        "requestId": req.request_id,
+        "msg": "success",
+        "code": 200,
+        "payload": {
+            "sku": req.payload.sku,
+            "device": req.payload.device,
+            "capabilities": capabilities
+        }
+    }))
+    .into_response()
+}
+
+/// A stand-in for one light on the LAN. Answers scans and status requests like a real one and
+/// keeps track of the commands it gets.
+#[derive(Clone, Debug)]
+pub struct FakeLanDevice {
+    addr: SocketAddr,
+    state: Arc<Mutex<LanDeviceState>>,
+}
+
+#[derive(Debug, Default)]
+struct LanDeviceState {
+    state: DeviceState,
+    controls: Vec<Capability>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LanMessage {
+    msg: LanInner,
+}
+
+#[derive(Deserialize, Debug)]
+struct LanInner {
+    cmd: String,
+    #[serde(default)]
+    data: Value,
+}
+
+impl FakeLanDevice {
+    /// Serves `target` on a free local port. Real lights answer on a fixed port of whoever
+    /// asked, here that's `reply_port`.
+    pub async fn start(target: Target, reply_port: u16) -> std::io::Result<Self> {
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await?;
+        let addr = socket.local_addr()?;
+        let state = Arc::new(Mutex::new(LanDeviceState {
+            state: DeviceState {
+                online: Some(true),
+                power: Some(false),
+                brightness: Some(100),
+                ..Default::default()
+            },
+            ..Default::default()
+        }));
+
+        let device_state = state.clone();
+        tokio::spawn(async move {
+            let mut buf = [0; 4096];
+            while let Ok((len, from)) = socket.recv_from(&mut buf).await {
+                let Ok(req) = serde_json::from_slice::<LanMessage>(&buf[..len]) else {
+                    continue;
+                };
+                let reply_to = SocketAddr::new(from.ip(), reply_port);
+
+                let reply = match lan_reply(&target, addr, &device_state, req.msg) {
+                    Some(reply) => reply,
+                    None => continue,
+                };
+                _ = socket.send_to(&reply, reply_to).await;
+            }
+        });
+
+        Ok(FakeLanDevice { addr, state })
+    }
+
+    /// Use as both `LanConfig::scan_addr` and, with its port, `LanConfig::control_port`
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Every command that came in, oldest first
+    pub fn controls(&self) -> Vec<Capability> {
+        self.state.lock().unwrap().controls.clone()
+    }
+
+    pub fn state(&self) -> DeviceState {
+        self.state.lock().unwrap().state.clone()
+    }
+}
+
+/// Applies a command, or builds the answer to a scan or status request
+fn lan_reply(
+    target: &Target,
+    addr: SocketAddr,
+    state: &Mutex<LanDeviceState>,
+    msg: LanInner,
+) -> Option<Vec<u8>> {
+    let mut state = state.lock().unwrap();
+    let value = msg.data["value"].as_i64();
+
+    let capability = match msg.cmd.as_str() {
+        "scan" => {
+            return Some(message(
+                "scan",
+                json!({
+                    "ip": addr.ip(),
+                    "device": target.device,
+                    "sku": target.sku,
+                    "bleVersionHard": "3.01.01",
+                    "bleVersionSoft": "1.03.01",
+                    "wifiVersionHard": "1.00.10",
+                    "wifiVersionSoft": "1.02.03"
+                }),
+            ));
+        }
+        "devStatus" => {
+            let color = state.state.color.unwrap_or(crate::Rgb { r: 0, g: 0, b: 0 });
+            return Some(message(
+                "devStatus",
+                json!({
+                    "onOff": u8::from(state.state.power.unwrap_or_default()),
+                    "brightness": state.state.brightness.unwrap_or_default(),
+                    "color": color,
+                    "colorTemInKelvin": state.state.kelvin.unwrap_or_default()
+                }),
+            ));
+        }
+        "turn" => Capability::Power(value? != 0),
+        "brightness" => Capability::Brightness(u8::try_from(value?).ok()?),
+        "colorwc" => match msg.data["colorTemInKelvin"].as_i64() {
+            Some(kelvin) if kelvin > 0 => Capability::ColorTemperature(kelvin),
+            _ => Capability::Color(serde_json::from_value(msg.data["color"].clone()).ok()?),
+        },
+        _ => return None,
+    };
+
+    state.state.apply(capability);
+    state.controls.push(capability);
+    None
+}
@@ -0,0 +1,319 @@
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::{
+    collections::HashMap,
+    env, io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time::Instant};
+
+use crate::{
+    Capability, Device, DeviceState, Error, GoveeApi, Rgb, Target,
+    model::{BRIGHTNESS, COLOR_RGB, COLOR_TEMPERATURE_K, power_capability, range_capability},
+};
+
+/// Where devices listen for scans
+pub const SCAN_ADDR: SocketAddr =
+    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(239, 255, 255, 250)), 4001);
+/// Devices answer scans and status requests on this port of whoever asked
+pub const LISTEN_PORT: u16 = 4002;
+/// Devices take commands on this port
+pub const CONTROL_PORT: u16 = 4003;
+/// How long to wait for scan and status answers
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+/// The LAN protocol always takes all of these, whatever the light
+const MIN_KELVIN: i64 = 2000;
+const MAX_KELVIN: i64 = 9000;
+
+/// Where to send and listen, only worth changing to point at a stub
+#[derive(Debug, Clone)]
+pub struct LanConfig {
+    pub scan_addr: SocketAddr,
+    pub listen_addr: SocketAddr,
+    pub control_port: u16,
+    pub timeout: Duration,
+}
+
+impl Default for LanConfig {
+    fn default() -> Self {
+        LanConfig {
+            scan_addr: SCAN_ADDR,
+            listen_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), LISTEN_PORT),
+            control_port: CONTROL_PORT,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl LanConfig {
+    /// The defaults, overridden by `GOVEE_LAN_SCAN_ADDR`, `GOVEE_LAN_LISTEN_ADDR` and
+    /// `GOVEE_LAN_CONTROL_PORT` when they're set
+    pub fn from_env() -> Result<Self, Error> {
+        let mut config = LanConfig::default();
+        if let Some(scan_addr) = parse_env("GOVEE_LAN_SCAN_ADDR")? {
+            config.scan_addr = scan_addr;
+        }
+        if let Some(listen_addr) = parse_env("GOVEE_LAN_LISTEN_ADDR")? {
+            config.listen_addr = listen_addr;
+        }
+        if let Some(control_port) = parse_env("GOVEE_LAN_CONTROL_PORT")? {
+            config.control_port = control_port;
+        }
+        Ok(config)
+    }
+}
+
+fn parse_env<T: std::str::FromStr>(name: &str) -> Result<Option<T>, Error> {
+    match env::var(name) {
+        Ok(val) => val.parse().map(Some).map_err(|_| {
+            Error::Lan(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{name} isn't valid"),
+            ))
+        }),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Talks to lights directly over Govee's LAN protocol, which has to be switched on per device in
+/// the Govee app. Commands aren't acknowledged, only scans and status requests get answers.
+/// Cheap to clone, clones share the socket.
+#[derive(Clone, Debug)]
+pub struct LanClient {
+    socket: Arc<UdpSocket>,
+    config: LanConfig,
+    /// addresses found by scanning, by device id
+    ips: Arc<Mutex<HashMap<String, IpAddr>>>,
+    /// answers all arrive on the one socket, so only one exchange waits on it at a time
+    exchange: Arc<tokio::sync::Mutex<()>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Message {
+    msg: Inner,
+}
+
+#[derive(Deserialize, Debug)]
+struct Inner {
+    cmd: String,
+    #[serde(default)]
+    data: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct ScanData {
+    ip: IpAddr,
+    device: String,
+    sku: String,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct StatusData {
+    on_off: i64,
+    brightness: i64,
+    color: Option<Rgb>,
+    color_tem_in_kelvin: i64,
+}
+
+/// A LAN message as it goes over the wire
+pub(crate) fn message(cmd: &str, data: Value) -> Vec<u8> {
+    json!({ "msg": { "cmd": cmd, "data": data } })
+        .to_string()
+        .into_bytes()
+}
+
+/// The LAN command for a capability
+pub(crate) fn command(capability: Capability) -> (&'static str, Value) {
+    match capability {
+        Capability::Power(on) => ("turn", json!({ "value": u8::from(on) })),
+        Capability::Brightness(percent) => ("brightness", json!({ "value": percent })),
+        Capability::Color(color) => ("colorwc", json!({ "color": color, "colorTemInKelvin": 0 })),
+        Capability::ColorTemperature(kelvin) => (
+            "colorwc",
+            json!({ "color": { "r": 0, "g": 0, "b": 0 }, "colorTemInKelvin": kelvin }),
+        ),
+    }
+}
+
+impl LanClient {
+    pub async fn bind(config: LanConfig) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(config.listen_addr).await?;
+
+        Ok(LanClient {
+            socket: Arc::new(socket),
+            config,
+            ips: Default::default(),
+            exchange: Default::default(),
+        })
+    }
+
+    /// Bound with `LanConfig::from_env`
+    pub async fn from_env() -> Result<Self, Error> {
+        LanClient::bind(LanConfig::from_env()?).await
+    }
+
+    /// Reads datagrams until `deadline`, handing each message to `want` until it returns something
+    async fn recv_until<T>(
+        &self,
+        deadline: Instant,
+        mut want: impl FnMut(Inner, SocketAddr) -> Option<T>,
+    ) -> Result<Option<T>, Error> {
+        let mut buf = [0; 4096];
+
+        loop {
+            let (len, from) =
+                match tokio::time::timeout_at(deadline, self.socket.recv_from(&mut buf)).await {
+                    Ok(received) => received?,
+                    Err(_) => return Ok(None),
+                };
+
+            match serde_json::from_slice::<Message>(&buf[..len]) {
+                Ok(message) => {
+                    if let Some(found) = want(message.msg, from) {
+                        return Ok(Some(found));
+                    }
+                }
+                Err(err) => debug!("Ignoring LAN message from {from}: {err}"),
+            }
+        }
+    }
+
+    /// Every device that answers a scan within the timeout
+    async fn scan(&self) -> Result<Vec<ScanData>, Error> {
+        let _exchange = self.exchange.lock().await;
+
+        let scan = message("scan", json!({ "account_topic": "reserve" }));
+        self.socket.send_to(&scan, self.config.scan_addr).await?;
+
+        let mut found: Vec<ScanData> = vec![];
+        let deadline = Instant::now() + self.config.timeout;
+        self.recv_until(deadline, |msg, from| {
+            if msg.cmd != "scan" {
+                return None::<()>;
+            }
+            match serde_json::from_value::<ScanData>(msg.data) {
+                Ok(data) if !found.iter().any(|seen| seen.device == data.device) => {
+                    found.push(data)
+                }
+                Ok(_) => {}
+                Err(err) => warn!("Unreadable scan answer from {from}: {err}"),
+            }
+            None
+        })
+        .await?;
+
+        let mut ips = self.ips.lock().unwrap();
+        for data in &found {
+            ips.insert(data.device.clone(), data.ip);
+        }
+
+        Ok(found)
+    }
+
+    /// Where a device is, scanning when it hasn't been seen yet
+    async fn ip_of(&self, target: &Target) -> Result<IpAddr, Error> {
+        if let Some(ip) = self.ips.lock().unwrap().get(&target.device) {
+            return Ok(*ip);
+        }
+
+        self.scan()
+            .await?
+            .into_iter()
+            .find(|data| data.device == target.device)
+            .map(|data| data.ip)
+            .ok_or(Error::NoReply)
+    }
+}
+
+impl GoveeApi for LanClient {
+    /// Whatever answers a scan. The LAN protocol doesn't know names, and every device gets the
+    /// same capabilities since the protocol has no way to ask.
+    async fn devices(&self) -> Result<Vec<Device>, Error> {
+        let devices = self
+            .scan()
+            .await?
+            .into_iter()
+            .map(|data| Device {
+                sku: data.sku,
+                device: data.device,
+                device_name: String::new(),
+                kind: "devices.types.light".to_string(),
+                capabilities: vec![
+                    power_capability(),
+                    range_capability(
+                        "devices.capabilities.range",
+                        BRIGHTNESS,
+                        Some("unit.percent"),
+                        1,
+                        100,
+                    ),
+                    range_capability(
+                        "devices.capabilities.color_setting",
+                        COLOR_RGB,
+                        None,
+                        0,
+                        0xFFFFFF,
+                    ),
+                    range_capability(
+                        "devices.capabilities.color_setting",
+                        COLOR_TEMPERATURE_K,
+                        None,
+                        MIN_KELVIN,
+                        MAX_KELVIN,
+                    ),
+                ],
+            })
+            .collect();
+
+        Ok(devices)
+    }
+
+    async fn control(&self, target: &Target, capability: Capability) -> Result<(), Error> {
+        let ip = self.ip_of(target).await?;
+        let (cmd, data) = command(capability);
+
+        self.socket
+            .send_to(&message(cmd, data), (ip, self.config.control_port))
+            .await?;
+        Ok(())
+    }
+
+    async fn state(&self, target: &Target) -> Result<DeviceState, Error> {
+        let ip = self.ip_of(target).await?;
+        let _exchange = self.exchange.lock().await;
+
+        self.socket
+            .send_to(
+                &message("devStatus", json!({})),
+                (ip, self.config.control_port),
+            )
+            .await?;
+
+        let deadline = Instant::now() + self.config.timeout;
+        let status = self
+            .recv_until(deadline, |msg, from| {
+                (msg.cmd == "devStatus" && from.ip() == ip)
+                    .then(|| serde_json::from_value::<StatusData>(msg.data).unwrap_or_default())
+            })
+            .await?;
+
+        let Some(status) = status else {
+            // it may have moved, look for it again next time
+            self.ips.lock().unwrap().remove(&target.device);
+            return Err(Error::NoReply);
+        };
+
+        let kelvin = (status.color_tem_in_kelvin > 0).then_some(status.color_tem_in_kelvin);
+        Ok(DeviceState {
+            online: Some(true),
+            power: Some(status.on_off != 0),
+            brightness: u8::try_from(status.brightness).ok(),
+            color: status.color.filter(|_| kelvin.is_none()),
+            kelvin,
+        })
+    }
+}
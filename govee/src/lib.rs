@@ -0,0 +1,33 @@
+mod client;
+mod error;
+#[cfg(feature = "fake")]
+pub mod fake;
+pub mod lan;
+mod model;
+
+pub use client::Client;
+pub use error::Error;
+pub use lan::{LanClient, LanConfig};
+pub use model::{
+    Capability, CapabilityInfo, Device, DeviceState, Parameters, Range, Rgb, Support, Target,
+};
+
+use std::future::Future;
+
+pub const DEFAULT_BASE_URL: &str = "https://openapi.api.govee.com";
+
+/// The Govee operations the house uses, so callers don't care how a device is reached
+pub trait GoveeApi {
+    /// Every device on the account with what it can do
+    fn devices(&self) -> impl Future<Output = Result<Vec<Device>, Error>> + Send;
+
+    /// Sets one capability on one device
+    fn control(
+        &self,
+        target: &Target,
+        capability: Capability,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// What one device reports it's doing right now
+    fn state(&self, target: &Target) -> impl Future<Output = Result<DeviceState, Error>> + Send;
+}
@@ -0,0 +1,257 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+pub const POWER_SWITCH: &str = "powerSwitch";
+pub const BRIGHTNESS: &str = "brightness";
+pub const COLOR_RGB: &str = "colorRgb";
+pub const COLOR_TEMPERATURE_K: &str = "colorTemperatureK";
+pub const ONLINE: &str = "online";
+
+/// A device as `/user/devices` describes it
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Device {
+    pub sku: String,
+    /// the MAC-like id Govee addresses the device by
+    pub device: String,
+    #[serde(default)]
+    pub device_name: String,
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    #[serde(default)]
+    pub capabilities: Vec<CapabilityInfo>,
+}
+
+/// One thing a device can do, `kind` is Govee's `devices.capabilities.*` type
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CapabilityInfo {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub instance: String,
+    #[serde(default)]
+    pub parameters: Parameters,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Parameters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    pub min: i64,
+    pub max: i64,
+    #[serde(default = "default_precision")]
+    pub precision: i64,
+}
+
+fn default_precision() -> i64 {
+    1
+}
+
+pub(crate) fn power_capability() -> CapabilityInfo {
+    CapabilityInfo {
+        kind: "devices.capabilities.on_off".to_string(),
+        instance: "powerSwitch".to_string(),
+        parameters: Parameters {
+            data_type: Some("ENUM".to_string()),
+            ..Default::default()
+        },
+    }
+}
+
+pub(crate) fn range_capability(
+    kind: &str,
+    instance: &str,
+    unit: Option<&str>,
+    min: i64,
+    max: i64,
+) -> CapabilityInfo {
+    CapabilityInfo {
+        kind: kind.to_string(),
+        instance: instance.to_string(),
+        parameters: Parameters {
+            data_type: Some("INTEGER".to_string()),
+            unit: unit.map(str::to_string),
+            range: Some(Range {
+                min,
+                max,
+                precision: 1,
+            }),
+        },
+    }
+}
+
+/// What a device accepts beyond switching on and off
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Support {
+    pub brightness: bool,
+    pub color: bool,
+    /// supported color temperatures in Kelvin
+    pub kelvin: Option<Range>,
+}
+
+/// Which device a command is for
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Target {
+    pub sku: String,
+    pub device: String,
+}
+
+impl Device {
+    pub fn target(&self) -> Target {
+        Target {
+            sku: self.sku.clone(),
+            device: self.device.clone(),
+        }
+    }
+
+    pub fn support(&self) -> Support {
+        let mut support = Support::default();
+        for capability in &self.capabilities {
+            match capability.instance.as_str() {
+                BRIGHTNESS => support.brightness = true,
+                COLOR_RGB => support.color = true,
+                COLOR_TEMPERATURE_K => support.kelvin = capability.parameters.range,
+                _ => {}
+            }
+        }
+        support
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Packed as `0xRRGGBB`, how Govee takes it
+    pub fn to_packed(self) -> u32 {
+        (u32::from(self.r) << 16) | (u32::from(self.g) << 8) | u32::from(self.b)
+    }
+
+    pub fn from_packed(packed: u32) -> Self {
+        Self {
+            r: (packed >> 16) as u8,
+            g: (packed >> 8) as u8,
+            b: packed as u8,
+        }
+    }
+}
+
+/// A change to send to a device
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    Power(bool),
+    /// percent
+    Brightness(u8),
+    Color(Rgb),
+    ColorTemperature(i64),
+}
+
+impl Capability {
+    pub fn instance(self) -> &'static str {
+        match self {
+            Capability::Power(_) => POWER_SWITCH,
+            Capability::Brightness(_) => BRIGHTNESS,
+            Capability::Color(_) => COLOR_RGB,
+            Capability::ColorTemperature(_) => COLOR_TEMPERATURE_K,
+        }
+    }
+
+    pub fn kind(self) -> &'static str {
+        match self {
+            Capability::Power(_) => "devices.capabilities.on_off",
+            Capability::Brightness(_) => "devices.capabilities.range",
+            Capability::Color(_) | Capability::ColorTemperature(_) => {
+                "devices.capabilities.color_setting"
+            }
+        }
+    }
+
+    /// The value as Govee sends and receives it
+    pub fn value(self) -> i64 {
+        match self {
+            Capability::Power(on) => on.into(),
+            Capability::Brightness(percent) => percent.into(),
+            Capability::Color(color) => color.to_packed().into(),
+            Capability::ColorTemperature(kelvin) => kelvin,
+        }
+    }
+
+    /// Reads a capability back from its instance name and value
+    pub fn from_instance(instance: &str, value: i64) -> Option<Self> {
+        match instance {
+            POWER_SWITCH => Some(Capability::Power(value != 0)),
+            BRIGHTNESS => u8::try_from(value).ok().map(Capability::Brightness),
+            COLOR_RGB => u32::try_from(value)
+                .ok()
+                .map(|packed| Capability::Color(Rgb::from_packed(packed))),
+            COLOR_TEMPERATURE_K => Some(Capability::ColorTemperature(value)),
+            _ => None,
+        }
+    }
+
+    /// The `capability` object of a control request
+    pub fn to_json(self) -> Value {
+        json!({
+            "type": self.kind(),
+            "instance": self.instance(),
+            "value": self.value()
+        })
+    }
+}
+
+/// What a device last reported, anything it didn't report is `None`
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceState {
+    pub online: Option<bool>,
+    pub power: Option<bool>,
+    /// percent
+    pub brightness: Option<u8>,
+    pub color: Option<Rgb>,
+    pub kelvin: Option<i64>,
+}
+
+impl DeviceState {
+    /// Fills in one capability of a `/device/state` answer. Govee reports the color mode that
+    /// isn't in use as 0.
+    pub(crate) fn read(&mut self, instance: &str, value: &Value) {
+        let number = value.as_i64().or_else(|| value.as_bool().map(i64::from));
+        match (instance, number) {
+            (ONLINE, Some(online)) => self.online = Some(online != 0),
+            (POWER_SWITCH, Some(on)) => self.power = Some(on != 0),
+            (BRIGHTNESS, Some(percent)) => self.brightness = u8::try_from(percent).ok(),
+            (COLOR_RGB, Some(packed)) if packed > 0 => {
+                self.color = u32::try_from(packed).ok().map(Rgb::from_packed)
+            }
+            (COLOR_TEMPERATURE_K, Some(kelvin)) if kelvin > 0 => self.kelvin = Some(kelvin),
+            _ => {}
+        }
+    }
+
+    /// What the device should report after `capability` went through
+    pub fn apply(&mut self, capability: Capability) {
+        match capability {
+            Capability::Power(on) => self.power = Some(on),
+            Capability::Brightness(percent) => self.brightness = Some(percent),
+            Capability::Color(color) => {
+                self.color = Some(color);
+                self.kelvin = None;
+            }
+            Capability::ColorTemperature(kelvin) => {
+                self.kelvin = Some(kelvin);
+                self.color = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! `Client` against the in-process fake of Govee's cloud API
+
+use govee::{
+    Capability, Client, Error, GoveeApi, Rgb, Target,
+    fake::{Failure, FakeGovee},
+};
+
+const STUDIO: &str = "67:5D:CD:2A:06:06:46:5F";
+const BUBBLE_LAMP: &str = "38:31:D4:AD:FC:A8:91:CB";
+
+async fn start() -> (FakeGovee, Client) {
+    let fake = FakeGovee::start(([127, 0, 0, 1], 0).into(), FakeGovee::house())
+        .await
+        .unwrap();
+    let client = Client::new("test-key").unwrap().base_url(fake.base_url());
+    (fake, client)
+}
+
+fn target(sku: &str, device: &str) -> Target {
+    Target {
+        sku: sku.to_string(),
+        device: device.to_string(),
+    }
+}
+
+#[tokio::test]
+async fn lists_devices() {
+    let (_fake, client) = start().await;
+
+    let devices = client.devices().await.unwrap();
+    assert_eq!(devices.len(), 5);
+
+    let studio = devices
+        .iter()
+        .find(|device| device.device == STUDIO)
+        .unwrap();
+    let support = studio.support();
+    assert!(support.brightness && support.color);
+    assert_eq!(
+        support.kelvin.map(|range| (range.min, range.max)),
+        Some((2000, 9000))
+    );
+}
+
+#[tokio::test]
+async fn controls_and_reads_back() {
+    let (fake, client) = start().await;
+    let studio = target("H612D", STUDIO);
+    let teal = Rgb {
+        r: 0,
+        g: 128,
+        b: 128,
+    };
+
+    for capability in [
+        Capability::Power(true),
+        Capability::Brightness(40),
+        Capability::Color(teal),
+    ] {
+        client.control(&studio, capability).await.unwrap();
+    }
+
+    assert_eq!(
+        fake.controls(),
+        [
+            (studio.clone(), Capability::Power(true)),
+            (studio.clone(), Capability::Brightness(40)),
+            (studio.clone(), Capability::Color(teal)),
+        ]
+    );
+    assert_eq!(fake.value(&studio, "powerSwitch"), Some(1));
+    assert_eq!(fake.value(&studio, "brightness"), Some(40));
+    assert_eq!(fake.value(&studio, "colorRgb"), Some(0x008080));
+
+    let state = client.state(&studio).await.unwrap();
+    assert_eq!(state.online, Some(true));
+    assert_eq!(state.power, Some(true));
+    assert_eq!(state.brightness, Some(40));
+    assert_eq!(state.color, Some(teal));
+    assert_eq!(state.kelvin, None);
+}
+
+#[tokio::test]
+async fn retries_server_errors_and_rate_limits() {
+    let (fake, client) = start().await;
+    let lamp = target("H5080", BUBBLE_LAMP);
+
+    fake.fail_next(Failure::Status(500));
+    fake.fail_next(Failure::RateLimited);
+    client
+        .control(&lamp, Capability::Power(true))
+        .await
+        .unwrap();
+
+    // only the attempt that got through lands
+    assert_eq!(fake.controls(), [(lamp.clone(), Capability::Power(true))]);
+    assert_eq!(fake.value(&lamp, "powerSwitch"), Some(1));
+}
+
+#[tokio::test]
+async fn gives_up_after_max_attempts() {
+    let (fake, client) = start().await;
+    let client = client.max_attempts(2);
+    let lamp = target("H5080", BUBBLE_LAMP);
+
+    fake.fail_next(Failure::Status(503));
+    fake.fail_next(Failure::Status(503));
+    let err = client
+        .control(&lamp, Capability::Power(true))
+        .await
+        .unwrap_err();
+
+    assert!(matches!(err, Error::Status(status) if status.as_u16() == 503));
+    assert!(fake.controls().is_empty());
+}
+
+#[tokio::test]
+async fn doesnt_retry_govee_refusals() {
+    let (fake, client) = start().await;
+    let lamp = target("H5080", BUBBLE_LAMP);
+
+    fake.fail_next(Failure::GoveeCode(400));
+    let err = client
+        .control(&lamp, Capability::Power(true))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Govee { code: 400, .. }));
+
+    // the plug can't be dimmed, which the fake refuses like Govee does
+    let err = client
+        .control(&lamp, Capability::Brightness(50))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::Govee { code: 400, .. }));
+
+    assert!(fake.controls().is_empty());
+    assert_eq!(fake.value(&lamp, "brightness"), None);
+}
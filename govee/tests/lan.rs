@@ -0,0 +1,144 @@
+//! `LanClient` against the local UDP stand-in for a light
+
+use govee::{Capability, Error, GoveeApi, LanClient, LanConfig, Rgb, Target, fake::FakeLanDevice};
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::Duration,
+};
+
+fn studio() -> Target {
+    Target {
+        sku: "H612D".to_string(),
+        device: "67:5D:CD:2A:06:06:46:5F".to_string(),
+    }
+}
+
+/// A port nothing is listening on right now
+fn free_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// A client for `scan_addr`, which is also where commands go
+async fn client(scan_addr: SocketAddr, listen_port: u16) -> LanClient {
+    LanClient::bind(LanConfig {
+        scan_addr,
+        listen_addr: ([127, 0, 0, 1], listen_port).into(),
+        control_port: scan_addr.port(),
+        timeout: Duration::from_millis(500),
+    })
+    .await
+    .unwrap()
+}
+
+async fn start() -> (FakeLanDevice, LanClient) {
+    let listen_port = free_port();
+    let fake = FakeLanDevice::start(studio(), listen_port).await.unwrap();
+    let client = client(fake.addr(), listen_port).await;
+    (fake, client)
+}
+
+/// Commands aren't acknowledged, so wait for the light to have seen them
+async fn wait_for_controls(fake: &FakeLanDevice, count: usize) {
+    for _ in 0..50 {
+        if fake.controls().len() >= count {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the light only got {:?}", fake.controls());
+}
+
+#[tokio::test]
+async fn scans_for_devices() {
+    let (_fake, client) = start().await;
+
+    let devices = client.devices().await.unwrap();
+    assert_eq!(devices.len(), 1);
+    assert_eq!(devices[0].target(), studio());
+    assert!(devices[0].support().brightness);
+}
+
+#[tokio::test]
+async fn controls_the_light() {
+    let (fake, client) = start().await;
+    let orange = Rgb {
+        r: 255,
+        g: 136,
+        b: 0,
+    };
+
+    for capability in [
+        Capability::Power(true),
+        Capability::Brightness(25),
+        Capability::Color(orange),
+        Capability::ColorTemperature(2700),
+    ] {
+        client.control(&studio(), capability).await.unwrap();
+    }
+    wait_for_controls(&fake, 4).await;
+
+    assert_eq!(
+        fake.controls(),
+        [
+            Capability::Power(true),
+            Capability::Brightness(25),
+            Capability::Color(orange),
+            Capability::ColorTemperature(2700),
+        ]
+    );
+    let state = fake.state();
+    assert_eq!(state.power, Some(true));
+    assert_eq!(state.brightness, Some(25));
+    assert_eq!(state.kelvin, Some(2700));
+    assert_eq!(state.color, None);
+}
+
+#[tokio::test]
+async fn asks_for_status() {
+    let (fake, client) = start().await;
+    let orange = Rgb {
+        r: 255,
+        g: 136,
+        b: 0,
+    };
+
+    let state = client.state(&studio()).await.unwrap();
+    assert_eq!(state.online, Some(true));
+    assert_eq!(state.power, Some(false));
+    assert_eq!(state.brightness, Some(100));
+
+    client
+        .control(&studio(), Capability::Power(true))
+        .await
+        .unwrap();
+    client
+        .control(&studio(), Capability::Color(orange))
+        .await
+        .unwrap();
+    wait_for_controls(&fake, 2).await;
+
+    let state = client.state(&studio()).await.unwrap();
+    assert_eq!(state, fake.state());
+    assert_eq!(state.power, Some(true));
+    assert_eq!(state.color, Some(orange));
+    assert_eq!(state.kelvin, None);
+}
+
+#[tokio::test]
+async fn times_out_when_nothing_answers() {
+    let nobody: SocketAddr = ([127, 0, 0, 1], free_port()).into();
+    let client = client(nobody, free_port()).await;
+
+    assert!(client.devices().await.unwrap().is_empty());
+    let err = client
+        .control(&studio(), Capability::Power(true))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, Error::NoReply));
+    let err = client.state(&studio()).await.unwrap_err();
+    assert!(matches!(err, Error::NoReply));
+}
@@ -0,0 +1,18 @@
+-- Items were keyed by name, so something needed from two stores could only be on the list once.
+-- SQLite can't change a primary key, so the table is rebuilt with the old rows copied over.
+CREATE TABLE items_by_id
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  name                     TEXT    NOT NULL,
+  active                   BOOLEAN NOT NULL DEFAULT 1,
+  qty                      VARCHAR,
+  category                 VARCHAR NOT NULL DEFAULT 'misc',
+  store                    VARCHAR NOT NULL DEFAULT 'hyvee',
+  UNIQUE (name, store)
+);
+
+INSERT INTO items_by_id (name, active, qty, category, store)
+SELECT name, active, qty, category, store FROM items ORDER BY rowid;
+
+DROP TABLE items;
+ALTER TABLE items_by_id RENAME TO items;
@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS lists
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  name                     TEXT    UNIQUE NOT NULL COLLATE NOCASE,
+  created_at               INTEGER NOT NULL
+);
+
+-- everything so far was the weekly shop
+INSERT INTO lists (id, name, created_at) VALUES (1, 'weekly groceries', CAST(strftime('%s', 'now') AS INTEGER));
+
+-- items move onto lists, which is another rebuild since SQLite can't change a unique constraint
+CREATE TABLE items_on_lists
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  list_id                  INTEGER NOT NULL REFERENCES lists(id) ON DELETE CASCADE,
+  name                     TEXT    NOT NULL,
+  active                   BOOLEAN NOT NULL DEFAULT 1,
+  qty                      VARCHAR,
+  category                 VARCHAR NOT NULL DEFAULT 'misc',
+  store                    VARCHAR NOT NULL DEFAULT 'hyvee',
+  UNIQUE (list_id, name, store)
+);
+
+INSERT INTO items_on_lists (id, list_id, name, active, qty, category, store)
+SELECT id, 1, name, active, qty, category, store FROM items;
+
+DROP TABLE items;
+ALTER TABLE items_on_lists RENAME TO items;
@@ -2,6 +2,8 @@ import { useCallback, useEffect, useRef, useState } from 'react';
 import './App.css';
 
 type Item = {
+  id: number,
+  list_id: number,
   name: string,
   active: boolean,
   category: string,
@@ -9,6 +11,14 @@ type Item = {
   store: string,
 }
 
+type List = {
+  id: number,
+  name: string,
+}
+
+// the list that was open last time, so reopening the page goes back to it
+const LIST_KEY = "grocery-list";
+
 const CATEGORIES = [
   "produce",
   "deli",
@@ -48,6 +58,9 @@ for (const store of STORES) {
 
 function App() {
   const [items, setItems] = useState<Item[]>([]);
+  const [lists, setLists] = useState<List[]>([]);
+  const [listId, setListId] = useState<number | null>(null);
+  const [newList, setNewList] = useState<string>("");
 
   // For some reason when we add new items to the datalist it opens, so this is in separate state and not updated after first fetch
   const [itemsForSelect, setItemsForSelect] = useState<Item[]>([]);
@@ -63,8 +76,27 @@ function App() {
 
 
   useEffect(() => {
+    async function getLists() {
+      const response = await fetch("/get-lists");
+      if (!response.ok) {
+        throw new Error('Network response was not ok');
+      }
+      const { lists }: { lists: List[] } = await response.json();
+      setLists(lists);
+      const saved = Number(localStorage.getItem(LIST_KEY));
+      setListId(lists.find((list) => list.id === saved)?.id ?? lists[0]?.id ?? null);
+    }
+    getLists();
+  }, [])
+
+  useEffect(() => {
+    if (listId === null) {
+      return;
+    }
+    localStorage.setItem(LIST_KEY, String(listId));
+
     async function getItems() {
-      const response = await fetch("/get-items");
+      const response = await fetch(`/get-items?list_id=${listId}`);
       if (!response.ok) {
         throw new Error('Network response was not ok');
       }
@@ -73,15 +105,108 @@ function App() {
       setItemsForSelect(items);
     }
     getItems();
+  }, [listId])
+
+  const addList = useCallback(async (event: React.KeyboardEvent<HTMLInputElement>) => {
+    if (event.key !== "Enter" || !newList.trim()) {
+      return;
+    }
+    event.preventDefault();
+    const response = await fetch(`/add-list`, {
+      method: "POST",
+      body: JSON.stringify({
+        name: newList,
+      }),
+      headers: {
+        "Content-Type": "application/json"
+      }
+    })
+    if (!response.ok) {
+      throw new Error('Network response was not ok');
+    }
+    const { lists }: { lists: List[] } = await response.json();
+    setLists(lists);
+    setListId(lists.find((list) => list.name === newList.trim())?.id ?? listId);
+    setNewList("");
+  }, [newList, listId])
+
+  const renameList = useCallback(async () => {
+    const list = lists.find((list) => list.id === listId);
+    const name = list && prompt("Rename list", list.name);
+    if (!list || !name?.trim()) {
+      return;
+    }
+    const response = await fetch(`/${list.id}/rename-list`, {
+      method: "POST",
+      body: JSON.stringify({
+        name,
+      }),
+      headers: {
+        "Content-Type": "application/json"
+      }
+    })
+    if (!response.ok) {
+      throw new Error('Network response was not ok');
+    }
+    const { lists: fetchedLists } = await response.json();
+    setLists(fetchedLists);
+  }, [lists, listId])
+
+  const deleteList = useCallback(async () => {
+    const list = lists.find((list) => list.id === listId);
+    if (!list || !confirm(`Delete ${list.name} and everything on it?`)) {
+      return;
+    }
+    const response = await fetch(`/${list.id}/delete-list`, {
+      method: "POST",
+      headers: {
+        "Content-Type": "application/json"
+      }
+    })
+    if (!response.ok) {
+      throw new Error('Network response was not ok');
+    }
+    const { lists: fetchedLists }: { lists: List[] } = await response.json();
+    setLists(fetchedLists);
+    setListId(fetchedLists[0]?.id ?? null);
+  }, [lists, listId])
+
+  // action is "move" or "copy"
+  const sendItem = useCallback(async (item: Item, action: string, toListId: number) => {
+    const response = await fetch(`/${item.id}/${action}-item`, {
+      method: "POST",
+      body: JSON.stringify({
+        list_id: toListId,
+      }),
+      headers: {
+        "Content-Type": "application/json"
+      }
+    })
+    if (!response.ok) {
+      throw new Error('Network response was not ok');
+    }
+    const { items } = await response.json();
+    setItems(items);
   }, [])
 
+  const listActions = (item: Item) => lists.length > 1 && (
+    <select className="send-item" value="" onClick={(event) => event.stopPropagation()}
+      onChange={(event) => {
+        const [action, toListId] = event.target.value.split(":");
+        sendItem(item, action, Number(toListId));
+      }}>
+      <option value="">move / copy</option>
+      {lists.filter((list) => list.id !== listId).flatMap((list) => [
+        <option value={`move:${list.id}`}>move to {list.name}</option>,
+        <option value={`copy:${list.id}`}>copy to {list.name}</option>,
+      ])}
+    </select>
+  );
+
   const deleteItem = useCallback(async (item: Item, event: React.MouseEvent<HTMLButtonElement>) => {
     event.stopPropagation();
-    const response = await fetch(`/delete-item`, {
+    const response = await fetch(`/${item.id}/delete-item`, {
       method: "POST",
-      body: JSON.stringify({
-        name: item.name
-      }),
       headers: {
         "Content-Type": "application/json"
       }
@@ -99,6 +224,7 @@ function App() {
       const response = await fetch(`/add-item`, {
         method: "POST",
         body: JSON.stringify({
+          list_id: listId,
           name: newItem,
           qty,
           category: category,
@@ -118,14 +244,11 @@ function App() {
       setFlashSuccess(true);
       setTimeout(() => setFlashSuccess(false), 500);
     }
-  }, [newItem, qty, category, store])
+  }, [listId, newItem, qty, category, store])
 
   const toggleItem = useCallback(async (item: Item) => {
-    const response = await fetch(`/toggle-item`, {
+    const response = await fetch(`/${item.id}/toggle-item`, {
       method: "POST",
-      body: JSON.stringify({
-        name: item.name
-      }),
       headers: {
         "Content-Type": "application/json"
       }
@@ -141,10 +264,22 @@ function App() {
     <>
       <a id="hub-link" href="https://beebfam.org">Back to Hub</a>
       <div id="content">
+        <div id="lists">
+          <select onChange={(event) => setListId(Number(event.target.value))} value={listId ?? ""}>
+            {lists.map((list) =>
+              <option value={list.id}>{list.name}</option>
+            )}
+          </select>
+          <button onClick={renameList}>rename</button>
+          <button onClick={deleteList} disabled={lists.length < 2}>delete</button>
+          <input type="text" value={newList} placeholder='new list' onKeyDown={addList} onChange={(event) => setNewList(event.target.value)} />
+        </div>
         <div id="input" className={flashSuccess ? 'flash-green' : ''}>
           <input type="text" list="existing-names" ref={newItemRef} value={newItem ? newItem : ""} placeholder='name' onKeyDown={addItem} onChange={(event) => {
             setNewItem(event.target.value);
-            const existingValue = items.find((item) => item.name === event.target.value);
+            // an item can be on the list for more than one store, so the picked store's wins
+            const existingValue = items.find((item) => item.name === event.target.value && item.store === store)
+              ?? items.find((item) => item.name === event.target.value);
             if (existingValue) {
               setCategory(existingValue.category);
               setStore(existingValue.store);
@@ -163,8 +298,8 @@ function App() {
           </select>
           <datalist id="existing-names">
             {
-              itemsForSelect?.map((item) =>
-                <option value={item.name}></option>
+              [...new Set(itemsForSelect?.map((item) => item.name))].map((name) =>
+                <option value={name}></option>
               )
             }
           </datalist>
@@ -201,6 +336,7 @@ function App() {
                     <div className="item-card">
                       <div className="item-name">{item.name}</div>
                       <div className='item-qty'>{item.qty}</div>
+                      {listActions(item)}
                       <button className="delete-item" onClick={(event) => deleteItem(item, event)}>X</button>
                     </div>
                   </button>
@@ -221,6 +357,7 @@ function App() {
                     <div className="item-card">
                       <div className="item-name">{item.name}</div>
                       <div className='item-qty'>{item.qty}</div>
+                      {listActions(item)}
                       <button className="delete-item" onClick={(event) => deleteItem(item, event)}>X</button>
                     </div>
                   </button>
@@ -1,6 +1,7 @@
+use auth::AppError;
 use axum::{
     Json, Router,
-    extract::State,
+    extract::{Path, Query, State},
     http::{StatusCode, Uri, header},
     response::{IntoResponse, Response},
     routing::{get, post},
@@ -9,11 +10,14 @@ use axum::{
 use dotenvy::dotenv;
 use rust_embed::Embed;
 use serde::{Deserialize, Serialize};
-use sqlx::{Pool, Sqlite, SqlitePool, sqlite::SqliteConnectOptions};
-use std::{env, fs, net::SocketAddr};
+use serde_json::json;
+use sqlx::{Pool, Sqlite, SqliteConnection, SqlitePool, sqlite::SqliteConnectOptions};
+use std::{env, net::SocketAddr};
 
 #[derive(sqlx::FromRow, Debug, Deserialize, Serialize, Clone, Default)]
 struct Item {
+    id: i64,
+    list_id: i64,
     name: String,
     active: bool,
     qty: Option<String>,
@@ -26,10 +30,23 @@ struct ItemResponse {
     items: Vec<Item>,
 }
 
+/// A list items go on, like the weekly shop or a party
+#[derive(sqlx::FromRow, Debug, Deserialize, Serialize, Clone, Default)]
+struct List {
+    id: i64,
+    name: String,
+    created_at: i64,
+}
+
+#[derive(Deserialize, Serialize, Clone, Default, Debug)]
+struct ListResponse {
+    lists: Vec<List>,
+}
+
 #[derive(Clone, Debug)]
 struct AppState {
     pub pool: Pool<Sqlite>,
-    pub key: String,
+    pub auth: auth::Auth,
 }
 
 #[tokio::main]
@@ -45,26 +62,42 @@ async fn main() -> anyhow::Result<()> {
 
     sqlx::migrate!("./migrations").run(&pool).await?;
 
-    let key_path = std::env::var("KEY_PATH")?;
-    let key = fs::read_to_string(key_path)?.trim().to_string();
+    let auth = auth::Auth::from_env("grocery-list").await?;
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 8083));
     let listener = tokio::net::TcpListener::bind(addr).await?;
     let addr = listener.local_addr()?;
 
-    let app = Router::new()
+    let app = router(AppState { pool, auth });
+
+    println!("listening on {addr}");
+    _ = axum::serve(listener, app).await;
+    Ok(())
+}
+
+fn router(state: AppState) -> Router {
+    Router::new()
         .route("/", get(index_handler))
         .route("/index.html", get(index_handler))
         .route("/assets/{*file}", get(static_handler))
         .route("/get-items", get(get_items_handler))
         .route("/add-item", post(add_item_handler))
-        .route("/toggle-item", post(toggle_item_handler))
-        .route("/delete-item", post(delete_item_handler))
-        .with_state(AppState { pool, key });
-
-    println!("listening on {addr}");
-    _ = axum::serve(listener, app).await;
-    Ok(())
+        .route("/{id}/toggle-item", post(toggle_item_handler))
+        .route("/{id}/delete-item", post(delete_item_handler))
+        .route("/{id}/move-item", post(move_item_handler))
+        .route("/{id}/copy-item", post(copy_item_handler))
+        .route("/get-lists", get(get_lists_handler))
+        .route("/add-list", post(add_list_handler))
+        .route("/{id}/rename-list", post(rename_list_handler))
+        .route("/{id}/delete-list", post(delete_list_handler))
+        .layer(
+            auth::AuthLayer::new(state.auth.clone())
+                .trust_domain("beebfam.org")
+                .allow("/")
+                .allow("/index.html")
+                .allow("/assets/*"),
+        )
+        .with_state(state)
 }
 
 async fn index_handler() -> impl IntoResponse {
@@ -77,130 +110,443 @@ async fn static_handler(uri: Uri) -> impl IntoResponse {
     StaticFile(path)
 }
 
-async fn get_items_handler(State(state): State<AppState>) -> Result<Json<ItemResponse>, AppError> {
-    let items = get_items(&state.pool).await?;
+#[derive(Deserialize)]
+struct ItemsQuery {
+    /// the first list when it's left out
+    list_id: Option<i64>,
+}
+
+async fn get_items_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ItemsQuery>,
+) -> Result<Json<ItemResponse>, AppError> {
+    let list = pick_list(&state.pool, query.list_id).await?;
+    let items = get_items(&state.pool, list.id).await?;
     Ok(Json(ItemResponse { items }))
 }
 
 #[derive(Deserialize)]
 struct AddItemRequest {
+    list_id: Option<i64>,
     name: String,
     qty: Option<String>,
     category: String,
     store: String,
 }
 
-#[derive(Deserialize)]
-struct ToggleItemRequest {
-    name: String,
-}
+#[auth_macro::auth_guard]
+async fn add_item_handler(
+    State(state): State<AppState>,
+    Json(req): Json<AddItemRequest>,
+) -> Result<Json<ItemResponse>, AppError> {
+    if req.name.trim().is_empty() {
+        return Err(AppError::BadRequest("Item needs a name".to_string()));
+    }
+    let list = pick_list(&state.pool, req.list_id).await?;
+
+    let mut tx = state.pool.begin().await?;
+    let item = Item {
+        id: 0,
+        list_id: list.id,
+        name: req.name,
+        active: true,
+        qty: req.qty,
+        category: Some(req.category),
+        store: Some(req.store),
+    };
+    let (before, after) = put_item(&mut tx, &item).await?;
+    tx.commit().await?;
+
+    state
+        .auth
+        .audit(&current_user, "add-item", json!(before), json!(after))
+        .await?;
 
-#[derive(Deserialize)]
-struct DeleteItemRequest {
-    name: String,
+    let items = get_items(&state.pool, list.id).await?;
+    Ok(Json(ItemResponse { items }))
 }
 
 #[auth_macro::auth_guard]
-async fn add_item_handler(
+async fn toggle_item_handler(
     State(state): State<AppState>,
-    Json(req): Json<AddItemRequest>,
+    Path(id): Path<i64>,
 ) -> Result<Json<ItemResponse>, AppError> {
-    let item = sqlx::query_as!(
-        Item,
+    let item = get_item(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No item {id}")))?;
+
+    let inverse_active = !item.active;
+
+    sqlx::query!(
         r"
-        SELECT * FROM items WHERE name = ?1
-        ",
-        req.name
+            UPDATE items SET active = ?1 WHERE id = ?2
+            ",
+        inverse_active,
+        item.id,
     )
-    .fetch_optional(&state.pool)
+    .execute(&state.pool)
     .await?;
 
-    if let Some(item) = item {
-        sqlx::query!(
-            r"
-            UPDATE items SET active = ?1, qty = ?2, category = ?3, store = ?4 WHERE name = ?5
-            ",
-            true,
-            req.qty,
-            req.category,
-            req.store,
-            item.name,
+    let updated_item = Item {
+        active: inverse_active,
+        ..item.clone()
+    };
+    state
+        .auth
+        .audit(
+            &current_user,
+            "toggle-item",
+            json!(item),
+            json!(updated_item),
         )
-        .execute(&state.pool)
         .await?;
-    } else {
-        sqlx::query!(
-            r"
-            INSERT INTO items VALUES (?1, 1, ?2, ?3, ?4) 
-            ",
-            req.name,
-            req.qty,
-            req.category,
-            req.store
-        )
-        .execute(&state.pool)
-        .await?;
-    }
 
-    let items = get_items(&state.pool).await?;
+    let items = get_items(&state.pool, item.list_id).await?;
     Ok(Json(ItemResponse { items }))
 }
 
-#[auth_macro::auth_guard]
-async fn toggle_item_handler(
+#[auth_macro::auth_guard(role = "adult")]
+async fn delete_item_handler(
     State(state): State<AppState>,
-    Json(req): Json<ToggleItemRequest>,
+    Path(id): Path<i64>,
 ) -> Result<Json<ItemResponse>, AppError> {
-    let item = sqlx::query_as!(
-        Item,
+    let item = get_item(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No item {id}")))?;
+
+    sqlx::query!(
         r"
-        SELECT * FROM items WHERE name = ?1
+        DELETE FROM items WHERE id = ?1
         ",
-        req.name
+        id
     )
-    .fetch_one(&state.pool)
+    .execute(&state.pool)
     .await?;
 
-    let inverse_active = !item.active;
+    state
+        .auth
+        .audit(&current_user, "delete-item", json!(item), json!(null))
+        .await?;
+
+    let items = get_items(&state.pool, item.list_id).await?;
+    Ok(Json(ItemResponse { items }))
+}
+
+#[derive(Deserialize)]
+struct OtherListRequest {
+    list_id: i64,
+}
 
+/// Moves the item onto another list, merging it into the one there if that list already has it.
+/// Answers with the list it came from.
+#[auth_macro::auth_guard]
+async fn move_item_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<OtherListRequest>,
+) -> Result<Json<ItemResponse>, AppError> {
+    let (item, list) = item_and_other_list(&state.pool, id, req.list_id).await?;
+
+    let mut tx = state.pool.begin().await?;
+    let (replaced, moved) = put_item(
+        &mut tx,
+        &Item {
+            list_id: list.id,
+            ..item.clone()
+        },
+    )
+    .await?;
     sqlx::query!(
         r"
-            UPDATE items SET active = ?1 WHERE name = ?2
-            ",
-        inverse_active,
-        item.name,
+        DELETE FROM items WHERE id = ?1
+        ",
+        item.id
     )
-    .execute(&state.pool)
+    .execute(&mut *tx)
     .await?;
+    tx.commit().await?;
+
+    state
+        .auth
+        .audit(
+            &current_user,
+            "move-item",
+            json!({ "item": item, "replaced": replaced }),
+            json!(moved),
+        )
+        .await?;
 
-    let items = get_items(&state.pool).await?;
+    let items = get_items(&state.pool, item.list_id).await?;
     Ok(Json(ItemResponse { items }))
 }
 
+/// Puts the item on another list too, replacing the qty and category there if it's already on it.
+/// Answers with the list it came from.
 #[auth_macro::auth_guard]
-async fn delete_item_handler(
+async fn copy_item_handler(
     State(state): State<AppState>,
-    Json(req): Json<DeleteItemRequest>,
+    Path(id): Path<i64>,
+    Json(req): Json<OtherListRequest>,
 ) -> Result<Json<ItemResponse>, AppError> {
+    let (item, list) = item_and_other_list(&state.pool, id, req.list_id).await?;
+
+    let mut tx = state.pool.begin().await?;
+    let (before, copy) = put_item(
+        &mut tx,
+        &Item {
+            list_id: list.id,
+            ..item.clone()
+        },
+    )
+    .await?;
+    tx.commit().await?;
+
+    state
+        .auth
+        .audit(&current_user, "copy-item", json!(before), json!(copy))
+        .await?;
+
+    let items = get_items(&state.pool, item.list_id).await?;
+    Ok(Json(ItemResponse { items }))
+}
+
+async fn get_lists_handler(State(state): State<AppState>) -> Result<Json<ListResponse>, AppError> {
+    let lists = get_lists(&state.pool).await?;
+    Ok(Json(ListResponse { lists }))
+}
+
+#[derive(Deserialize)]
+struct ListRequest {
+    name: String,
+}
+
+#[auth_macro::auth_guard]
+async fn add_list_handler(
+    State(state): State<AppState>,
+    Json(req): Json<ListRequest>,
+) -> Result<Json<ListResponse>, AppError> {
+    let name = list_name(&req.name)?;
+    let now = chrono::Utc::now().timestamp();
+
+    let id = sqlx::query!(
+        r"
+        INSERT INTO lists (name, created_at) VALUES (?1, ?2)
+        ",
+        name,
+        now
+    )
+    .execute(&state.pool)
+    .await?
+    .last_insert_rowid();
+
+    let list = get_list(&state.pool, id).await?;
+    state
+        .auth
+        .audit(&current_user, "add-list", json!(null), json!(list))
+        .await?;
+
+    let lists = get_lists(&state.pool).await?;
+    Ok(Json(ListResponse { lists }))
+}
+
+#[auth_macro::auth_guard]
+async fn rename_list_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<ListRequest>,
+) -> Result<Json<ListResponse>, AppError> {
+    let name = list_name(&req.name)?;
+    let list = get_list(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No list {id}")))?;
+
     sqlx::query!(
         r"
-        DELETE FROM items WHERE name = ?1
+        UPDATE lists SET name = ?1 WHERE id = ?2
         ",
-        req.name
+        name,
+        id
     )
     .execute(&state.pool)
     .await?;
 
-    let items = get_items(&state.pool).await?;
-    Ok(Json(ItemResponse { items }))
+    let renamed = List {
+        name: name.to_string(),
+        ..list.clone()
+    };
+    state
+        .auth
+        .audit(&current_user, "rename-list", json!(list), json!(renamed))
+        .await?;
+
+    let lists = get_lists(&state.pool).await?;
+    Ok(Json(ListResponse { lists }))
+}
+
+/// Deletes the list along with everything on it
+#[auth_macro::auth_guard(role = "adult")]
+async fn delete_list_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<ListResponse>, AppError> {
+    let list = get_list(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No list {id}")))?;
+    // the page always needs a list to show
+    if get_lists(&state.pool).await?.len() == 1 {
+        return Err(AppError::BadRequest(
+            "The last list can't be deleted".to_string(),
+        ));
+    }
+    let items = get_items(&state.pool, id).await?;
+
+    // items go with it by their foreign key
+    sqlx::query!(
+        r"
+        DELETE FROM lists WHERE id = ?1
+        ",
+        id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    state
+        .auth
+        .audit(
+            &current_user,
+            "delete-list",
+            json!({ "list": list, "items": items }),
+            json!(null),
+        )
+        .await?;
+
+    let lists = get_lists(&state.pool).await?;
+    Ok(Json(ListResponse { lists }))
+}
+
+fn list_name(name: &str) -> Result<&str, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("List needs a name".to_string()));
+    }
+    Ok(name)
+}
+
+/// The list with `id`, or the first one without it
+async fn pick_list(pool: &Pool<Sqlite>, id: Option<i64>) -> Result<List, AppError> {
+    match id {
+        Some(id) => get_list(pool, id)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("No list {id}"))),
+        None => get_lists(pool)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| AppError::NotFound("There are no lists".to_string())),
+    }
+}
+
+async fn item_and_other_list(
+    pool: &Pool<Sqlite>,
+    id: i64,
+    list_id: i64,
+) -> Result<(Item, List), AppError> {
+    let item = get_item(pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No item {id}")))?;
+    let list = pick_list(pool, Some(list_id)).await?;
+    if item.list_id == list.id {
+        return Err(AppError::BadRequest(format!(
+            "{} is already on {}",
+            item.name, list.name
+        )));
+    }
+    Ok((item, list))
+}
+
+/// Puts `item` on its list, updating the one already there from the same store (each store has
+/// its own qty and category) or adding it. Returns what was there before and what's there now.
+async fn put_item(
+    conn: &mut SqliteConnection,
+    item: &Item,
+) -> anyhow::Result<(Option<Item>, Item)> {
+    let existing = sqlx::query_as!(
+        Item,
+        r"
+        SELECT * FROM items WHERE list_id = ?1 AND name = ?2 AND store = ?3
+        ",
+        item.list_id,
+        item.name,
+        item.store
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let id = if let Some(existing) = &existing {
+        sqlx::query!(
+            r"
+            UPDATE items SET active = ?1, qty = ?2, category = ?3 WHERE id = ?4
+            ",
+            item.active,
+            item.qty,
+            item.category,
+            existing.id,
+        )
+        .execute(&mut *conn)
+        .await?;
+        existing.id
+    } else {
+        sqlx::query!(
+            r"
+            INSERT INTO items (list_id, name, active, qty, category, store)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ",
+            item.list_id,
+            item.name,
+            item.active,
+            item.qty,
+            item.category,
+            item.store
+        )
+        .execute(&mut *conn)
+        .await?
+        .last_insert_rowid()
+    };
+
+    let updated = sqlx::query_as!(
+        Item,
+        r"
+        SELECT * FROM items WHERE id = ?1
+        ",
+        id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok((existing, updated))
 }
 
-async fn get_items(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<Item>> {
+async fn get_item(pool: &Pool<Sqlite>, id: i64) -> anyhow::Result<Option<Item>> {
+    let item = sqlx::query_as!(
+        Item,
+        r"
+        SELECT * FROM items WHERE id = ?1
+        ",
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(item)
+}
+
+async fn get_items(pool: &Pool<Sqlite>, list_id: i64) -> anyhow::Result<Vec<Item>> {
     let items = sqlx::query_as!(
         Item,
         r"
-        SELECT * FROM items
+        SELECT * FROM items WHERE list_id = ?1
         ",
+        list_id
     )
     .fetch_all(pool)
     .await?;
@@ -208,25 +554,31 @@ async fn get_items(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<Item>> {
     Ok(items)
 }
 
-pub struct AppError(anyhow::Error);
+async fn get_list(pool: &Pool<Sqlite>, id: i64) -> anyhow::Result<Option<List>> {
+    let list = sqlx::query_as!(
+        List,
+        r"
+        SELECT * FROM lists WHERE id = ?1
+        ",
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {}", self.0),
-        )
-            .into_response()
-    }
+    Ok(list)
 }
 
-impl<E> From<E> for AppError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(err: E) -> Self {
-        Self(err.into())
-    }
+async fn get_lists(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<List>> {
+    let lists = sqlx::query_as!(
+        List,
+        r"
+        SELECT * FROM lists ORDER BY id
+        ",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(lists)
 }
 
 #[derive(Embed)]
@@ -251,3 +603,269 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use auth::{Role, SESSION_COOKIE};
+    use axum::{body::Body, http::Request};
+    use serde_json::Value;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use tower::ServiceExt;
+
+    /// The migration that gave items ids
+    const ITEM_IDS: i64 = 20261018230000;
+
+    async fn empty_pool() -> Pool<Sqlite> {
+        // one connection, every in-memory connection is its own database
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    async fn start() -> Pool<Sqlite> {
+        let pool = empty_pool().await;
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+        pool
+    }
+
+    /// The app with mom signed in as an adult, its database, and the one audits go to
+    async fn start_app() -> (Router, Pool<Sqlite>, Pool<Sqlite>) {
+        let auth_pool = empty_pool().await;
+        sqlx::migrate!("../root/migrations")
+            .run(&auth_pool)
+            .await
+            .unwrap();
+        let id = sqlx::query(
+            r"
+            INSERT INTO users (username, password_hash, role, created_at) VALUES ('mom', '', ?1, 0)
+            ",
+        )
+        .bind(Role::Adult)
+        .execute(&auth_pool)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+        sqlx::query(
+            r"
+            INSERT INTO sessions (token_hash, user_id, created_at, expires_at) VALUES (?1, ?2, 0, ?3)
+            ",
+        )
+        .bind(auth::hash_token("mom-session"))
+        .bind(id)
+        .bind(i64::MAX)
+        .execute(&auth_pool)
+        .await
+        .unwrap();
+
+        let pool = start().await;
+        let state = AppState {
+            pool: pool.clone(),
+            auth: auth::Auth::new(auth_pool.clone(), "grocery-list"),
+        };
+        (router(state), pool, auth_pool)
+    }
+
+    async fn post<T: serde::de::DeserializeOwned>(app: &Router, path: &str, body: Value) -> T {
+        let req = Request::post(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::ORIGIN, "https://grocery.beebfam.org")
+            .header(header::COOKIE, format!("{SESSION_COOKIE}=mom-session"))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{path}");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    /// Adds milk from hyvee to `list_id`, returning its id
+    async fn add_milk(app: &Router, list_id: i64, qty: &str) -> i64 {
+        let body = json!({
+            "list_id": list_id,
+            "name": "milk",
+            "qty": qty,
+            "category": "dairy",
+            "store": "hyvee",
+        });
+        let items: ItemResponse = post(app, "/add-item", body).await;
+        items
+            .items
+            .iter()
+            .find(|item| item.name == "milk")
+            .unwrap()
+            .id
+    }
+
+    /// Adds the party list, which gets id 2
+    async fn add_party(app: &Router) {
+        let _: ListResponse = post(app, "/add-list", json!({ "name": "party" })).await;
+    }
+
+    fn item(name: &str, store: &str) -> Item {
+        Item {
+            list_id: 1,
+            name: name.to_string(),
+            active: true,
+            category: Some("misc".to_string()),
+            store: Some(store.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_items_from_before_they_had_ids() {
+        let pool = empty_pool().await;
+        let mut migrator = sqlx::migrate!("./migrations");
+        let before_ids: Vec<_> = migrator
+            .migrations
+            .iter()
+            .filter(|migration| migration.version < ITEM_IDS)
+            .cloned()
+            .collect();
+        let all = std::mem::replace(&mut migrator.migrations, before_ids.into());
+        migrator.run(&pool).await.unwrap();
+
+        sqlx::query(
+            r"
+            INSERT INTO items (name, active, qty, category, store)
+            VALUES ('milk', 1, '2 gal', 'dairy', 'hyvee'), ('bread', 0, NULL, 'bakery', 'aldi')
+            ",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        migrator.migrations = all;
+        migrator.run(&pool).await.unwrap();
+
+        assert_eq!(get_items(&pool, 1).await.unwrap().len(), 2);
+        let milk = get_item(&pool, 1).await.unwrap().unwrap();
+        assert_eq!(
+            (
+                milk.id,
+                milk.name.as_str(),
+                milk.active,
+                milk.qty.as_deref()
+            ),
+            (1, "milk", true, Some("2 gal"))
+        );
+        assert_eq!(milk.category.as_deref(), Some("dairy"));
+        let bread = get_item(&pool, 2).await.unwrap().unwrap();
+        assert_eq!(
+            (
+                bread.id,
+                bread.name.as_str(),
+                bread.active,
+                bread.qty.as_deref()
+            ),
+            (2, "bread", false, None)
+        );
+        assert_eq!(bread.store.as_deref(), Some("aldi"));
+    }
+
+    #[tokio::test]
+    async fn keeps_one_item_per_store() {
+        let pool = start().await;
+        let mut conn = pool.acquire().await.unwrap();
+
+        let (before, hyvee) = put_item(&mut conn, &item("milk", "hyvee")).await.unwrap();
+        assert!(before.is_none());
+        let (before, aldi) = put_item(&mut conn, &item("milk", "aldi")).await.unwrap();
+        assert!(before.is_none());
+        assert_ne!(hyvee.id, aldi.id);
+
+        // the same store updates the one already there
+        let more = Item {
+            qty: Some("2".to_string()),
+            ..item("milk", "hyvee")
+        };
+        let (before, updated) = put_item(&mut conn, &more).await.unwrap();
+        assert_eq!(before.unwrap().id, hyvee.id);
+        assert_eq!((updated.id, updated.qty.as_deref()), (hyvee.id, Some("2")));
+        drop(conn);
+
+        let items = get_items(&pool, 1).await.unwrap();
+        assert_eq!(items.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn moving_onto_a_list_that_has_it_merges_them() {
+        let (app, pool, auth_pool) = start_app().await;
+        add_party(&app).await;
+        let weekly = add_milk(&app, 1, "1 gal").await;
+        let party = add_milk(&app, 2, "3 gal").await;
+
+        let left: ItemResponse = post(
+            &app,
+            &format!("/{weekly}/move-item"),
+            json!({ "list_id": 2 }),
+        )
+        .await;
+        assert!(left.items.is_empty());
+
+        let items = get_items(&pool, 2).await.unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            (items[0].id, items[0].qty.as_deref()),
+            (party, Some("1 gal"))
+        );
+
+        // the audit keeps the qty that was overwritten
+        let before: String = sqlx::query_scalar(
+            r"
+            SELECT before_json FROM audit_log WHERE action = 'move-item'
+            ",
+        )
+        .fetch_one(&auth_pool)
+        .await
+        .unwrap();
+        let before: Value = serde_json::from_str(&before).unwrap();
+        assert_eq!(before["item"]["id"], weekly);
+        assert_eq!(before["replaced"]["id"], party);
+        assert_eq!(before["replaced"]["qty"], "3 gal");
+    }
+
+    #[tokio::test]
+    async fn copying_onto_a_list_that_has_it_updates_it() {
+        let (app, pool, _) = start_app().await;
+        add_party(&app).await;
+        let weekly = add_milk(&app, 1, "1 gal").await;
+        let party = add_milk(&app, 2, "3 gal").await;
+
+        let _: ItemResponse = post(
+            &app,
+            &format!("/{weekly}/copy-item"),
+            json!({ "list_id": 2 }),
+        )
+        .await;
+
+        let items = get_items(&pool, 1).await.unwrap();
+        assert_eq!(
+            (items.len(), items[0].id, items[0].qty.as_deref()),
+            (1, weekly, Some("1 gal"))
+        );
+        let items = get_items(&pool, 2).await.unwrap();
+        assert_eq!(
+            (items.len(), items[0].id, items[0].qty.as_deref()),
+            (1, party, Some("1 gal"))
+        );
+    }
+
+    #[tokio::test]
+    async fn deleting_a_list_deletes_its_items() {
+        let (app, pool, _) = start_app().await;
+        add_party(&app).await;
+        let weekly = add_milk(&app, 1, "1 gal").await;
+        let party = add_milk(&app, 2, "3 gal").await;
+
+        let _: ListResponse = post(&app, "/2/delete-list", json!(null)).await;
+
+        assert!(get_item(&pool, party).await.unwrap().is_none());
+        assert!(get_item(&pool, weekly).await.unwrap().is_some());
+    }
+}
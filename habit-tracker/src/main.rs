@@ -1,3 +1,4 @@
+use auth::AppError;
 use axum::{
     Json, Router,
     extract::State,
@@ -9,8 +10,9 @@ use axum::{
 use dotenvy::dotenv;
 use rust_embed::Embed;
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use sqlx::{Pool, Sqlite, SqlitePool, sqlite::SqliteConnectOptions};
-use std::{env, fs, net::SocketAddr};
+use std::{env, net::SocketAddr};
 
 const DAYS_TO_FETCH: i64 = 90;
 
@@ -41,7 +43,7 @@ struct TemplatesResponse {
 #[derive(Clone, Debug)]
 struct AppState {
     pub pool: Pool<Sqlite>,
-    pub key: String,
+    pub auth: auth::Auth,
 }
 
 #[tokio::main]
@@ -57,8 +59,7 @@ async fn main() -> anyhow::Result<()> {
 
     sqlx::migrate!("./migrations").run(&pool).await?;
 
-    let key_path = std::env::var("KEY_PATH")?;
-    let key = fs::read_to_string(key_path)?.trim().to_string();
+    let auth = auth::Auth::from_env("habit-tracker").await?;
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 8086));
     let listener = tokio::net::TcpListener::bind(addr).await?;
@@ -72,7 +73,14 @@ async fn main() -> anyhow::Result<()> {
         .route("/get-templates", get(get_templates_handler))
         .route("/add-habit", post(add_habit_handler))
         .route("/undo-last", post(undo_last_handler))
-        .with_state(AppState { pool, key });
+        .layer(
+            auth::AuthLayer::new(auth.clone())
+                .trust_domain("beebfam.org")
+                .allow("/")
+                .allow("/index.html")
+                .allow("/assets/*"),
+        )
+        .with_state(AppState { pool, auth });
 
     println!("listening on {addr}");
     _ = axum::serve(listener, app).await;
@@ -109,7 +117,7 @@ async fn add_habit_handler(
 ) -> Result<Json<HabitsResponse>, AppError> {
     let now = chrono::Utc::now().timestamp();
 
-    sqlx::query!(
+    let result = sqlx::query!(
         r"
         INSERT INTO habits (name, date, updated_at) VALUES (?1, ?2, ?3) 
         ",
@@ -120,6 +128,17 @@ async fn add_habit_handler(
     .execute(&state.pool)
     .await?;
 
+    let habit = Habit {
+        id: result.last_insert_rowid(),
+        name: req.name,
+        date: req.date,
+        updated_at: now,
+    };
+    state
+        .auth
+        .audit(&current_user, "add-habit", json!(null), json!(habit))
+        .await?;
+
     let habits = get_habits_since(&state.pool).await?;
     Ok(Json(HabitsResponse { habits }))
 }
@@ -128,15 +147,30 @@ async fn add_habit_handler(
 async fn undo_last_handler(
     State(state): State<AppState>,
 ) -> Result<Json<HabitsResponse>, AppError> {
-    sqlx::query!(
+    let habit = sqlx::query_as!(
+        Habit,
         r"
-        DELETE FROM habits 
-        WHERE id = (SELECT id FROM habits ORDER BY updated_at DESC LIMIT 1)
+        SELECT * FROM habits ORDER BY updated_at DESC LIMIT 1
         "
     )
+    .fetch_optional(&state.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Nothing to undo".to_string()))?;
+
+    sqlx::query!(
+        r"
+        DELETE FROM habits WHERE id = ?1
+        ",
+        habit.id
+    )
     .execute(&state.pool)
     .await?;
 
+    state
+        .auth
+        .audit(&current_user, "undo-habit", json!(habit), json!(null))
+        .await?;
+
     let habits = get_habits_since(&state.pool).await?;
     Ok(Json(HabitsResponse { habits }))
 }
@@ -184,27 +218,6 @@ async fn get_templates(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<HabitTemplate>
     Ok(templates)
 }
 
-pub struct AppError(anyhow::Error);
-
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {}", self.0),
-        )
-            .into_response()
-    }
-}
-
-impl<E> From<E> for AppError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(err: E) -> Self {
-        Self(err.into())
-    }
-}
-
 #[derive(Embed)]
 #[folder = "src/client/dist/"]
 struct Asset;
@@ -0,0 +1,7 @@
+parent_path=$( cd "$(dirname "${BASH_SOURCE[0]}")" ; pwd -P )
+db="root.db"
+service="root"
+git pull
+cp /var/lib/$service/$db $parent_path/$db
+sqlx migrate run
+cp $parent_path/$db /var/lib/$service/$db
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS users
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  username                 TEXT UNIQUE NOT NULL,
+  password_hash            TEXT NOT NULL,
+  created_at               INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS sessions
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  token                    TEXT UNIQUE NOT NULL,
+  user_id                  INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  label                    TEXT,
+  created_at               INTEGER NOT NULL,
+  expires_at               INTEGER NOT NULL
+);
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS api_tokens
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  user_id                  INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  name                     TEXT NOT NULL,
+  token_hash               TEXT UNIQUE NOT NULL,
+  scopes                   TEXT,
+  created_at               INTEGER NOT NULL,
+  last_used_at             INTEGER
+);
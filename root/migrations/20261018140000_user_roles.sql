@@ -0,0 +1,3 @@
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT "adult";
+-- everyone who existed before roles ran the house
+UPDATE users SET role = "admin";
@@ -0,0 +1,11 @@
+-- sessions are looked up by hash from now on, so everyone logs in again once
+DELETE FROM sessions;
+ALTER TABLE sessions RENAME COLUMN token TO token_hash;
+
+CREATE TABLE IF NOT EXISTS failed_logins
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  username                 TEXT NOT NULL,
+  ip                       TEXT NOT NULL,
+  attempted_at             INTEGER NOT NULL
+);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS audit_log
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  user_id                  INTEGER NOT NULL,
+  username                 TEXT NOT NULL,
+  service                  TEXT NOT NULL,
+  action                   TEXT NOT NULL,
+  before_json              TEXT,
+  after_json               TEXT,
+  created_at               INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_created_at ON audit_log (created_at);
@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS devices
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  name                     TEXT UNIQUE NOT NULL,
+  device                   TEXT UNIQUE NOT NULL,
+  sku                      TEXT NOT NULL,
+  created_at               INTEGER NOT NULL
+);
+
+-- the lamps that used to be hardcoded in get_device
+INSERT OR IGNORE INTO devices (name, device, sku, created_at) VALUES
+  ("tall living room",  "1A:A6:D4:AD:FC:EF:A6:1B", "H5080", unixepoch()),
+  ("small living room", "61:F6:D4:AD:FC:F3:6A:0F", "H5080", unixepoch()),
+  ("bubble lamp",       "38:31:D4:AD:FC:A8:91:CB", "H5080", unixepoch()),
+  ("bedroom black",     "44:CB:D4:AD:FC:EF:A5:E1", "H5080", unixepoch()),
+  ("studio lights",     "67:5D:CD:2A:06:06:46:5F", "H612D", unixepoch());
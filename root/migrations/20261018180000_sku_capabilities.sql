@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS sku_capabilities
+(
+  sku                      TEXT PRIMARY KEY NOT NULL,
+  brightness               BOOLEAN NOT NULL DEFAULT FALSE,
+  color                    BOOLEAN NOT NULL DEFAULT FALSE,
+  min_kelvin               INTEGER,
+  max_kelvin               INTEGER
+);
+
+-- H5080 is a smart plug so it only switches, H612D is the studio LED strip
+INSERT OR IGNORE INTO sku_capabilities (sku, brightness, color, min_kelvin, max_kelvin) VALUES
+  ("H5080", FALSE, FALSE, NULL, NULL),
+  ("H612D", TRUE,  TRUE,  2000, 9000);
@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS scenes
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  name                     TEXT UNIQUE NOT NULL,
+  created_at               INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS scene_lights
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  scene_id                 INTEGER NOT NULL REFERENCES scenes(id) ON DELETE CASCADE,
+  device_id                INTEGER NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+  toggle                   BOOLEAN,
+  brightness               INTEGER,
+  color                    INTEGER,
+  kelvin                   INTEGER,
+  UNIQUE (scene_id, device_id)
+);
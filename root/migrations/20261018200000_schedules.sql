@@ -0,0 +1,27 @@
+CREATE TABLE IF NOT EXISTS schedule_rules
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  name                     TEXT UNIQUE NOT NULL,
+  enabled                  BOOLEAN NOT NULL DEFAULT TRUE,
+  -- "cron", "sunrise" or "sunset"
+  kind                     TEXT NOT NULL,
+  cron                     TEXT,
+  offset_minutes           INTEGER NOT NULL DEFAULT 0,
+  -- either a scene or a list of light states
+  scene_id                 INTEGER REFERENCES scenes(id) ON DELETE CASCADE,
+  lights_json              TEXT,
+  created_at               INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS schedule_log
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  rule_id                  INTEGER REFERENCES schedule_rules(id) ON DELETE SET NULL,
+  rule_name                TEXT NOT NULL,
+  scheduled_for            INTEGER NOT NULL,
+  ran_at                   INTEGER NOT NULL,
+  ok                       BOOLEAN NOT NULL,
+  message                  TEXT
+);
+
+CREATE INDEX IF NOT EXISTS schedule_log_ran_at ON schedule_log (ran_at);
@@ -0,0 +1,2 @@
+-- "cloud" goes through Govee's API, "lan" talks to the light directly
+ALTER TABLE devices ADD COLUMN backend TEXT NOT NULL DEFAULT "cloud";
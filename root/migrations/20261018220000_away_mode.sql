@@ -0,0 +1,47 @@
+-- one row, away mode is either on for the house or not
+CREATE TABLE IF NOT EXISTS away_settings
+(
+  id                       INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+  enabled                  BOOLEAN NOT NULL DEFAULT FALSE,
+  -- "YYYY-MM-DD", local and inclusive
+  start_date               TEXT,
+  end_date                 TEXT,
+  -- minutes after local midnight lights may come on and must be off by
+  evening_start            INTEGER NOT NULL DEFAULT 1080,
+  evening_end              INTEGER NOT NULL DEFAULT 1380,
+  updated_at               INTEGER NOT NULL
+);
+
+INSERT OR IGNORE INTO away_settings (id, updated_at) VALUES (1, unixepoch());
+
+CREATE TABLE IF NOT EXISTS away_rooms
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  name                     TEXT UNIQUE NOT NULL,
+  -- chance the room gets lit on a given evening, 0 to 1
+  probability              REAL NOT NULL,
+  created_at               INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS away_room_devices
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  room_id                  INTEGER NOT NULL REFERENCES away_rooms(id) ON DELETE CASCADE,
+  device_id                INTEGER NOT NULL REFERENCES devices(id) ON DELETE CASCADE,
+  UNIQUE (room_id, device_id)
+);
+
+CREATE TABLE IF NOT EXISTS away_log
+(
+  id                       INTEGER PRIMARY KEY NOT NULL,
+  room_name                TEXT NOT NULL,
+  toggle                   BOOLEAN NOT NULL,
+  -- names of the lights switched, as a JSON array
+  lights_json              TEXT NOT NULL,
+  planned_for              INTEGER NOT NULL,
+  ran_at                   INTEGER NOT NULL,
+  ok                       BOOLEAN NOT NULL,
+  message                  TEXT
+);
+
+CREATE INDEX IF NOT EXISTS away_log_ran_at ON away_log (ran_at);
@@ -0,0 +1,2 @@
+-- NULL never expires, like every token made before this
+ALTER TABLE api_tokens ADD COLUMN expires_at INTEGER;
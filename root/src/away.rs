@@ -0,0 +1,670 @@
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveTime, TimeZone, Timelike, Utc};
+use log::{error, info, warn};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite, types::Json};
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    light_state::LightStateCache,
+    lights::{self, Backends, LightItem, LightStatus},
+};
+
+/// How often the plan is checked, lights switch at most this late
+const TICK: std::time::Duration = std::time::Duration::from_secs(15);
+/// How long a room stays lit, in minutes
+const MIN_SESSION: i64 = 20;
+const MAX_SESSION: i64 = 150;
+/// Chance a room comes on again after its first stretch
+const SECOND_SESSION_CHANCE: f64 = 0.4;
+/// Minutes between a room's stretches
+const MIN_GAP: i64 = 10;
+const MAX_GAP: i64 = 60;
+const DATE_FORMAT: &str = "%Y-%m-%d";
+const TIME_FORMAT: &str = "%H:%M";
+
+/// When away mode runs, dates and times are local
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Settings {
+    pub enabled: bool,
+    /// "YYYY-MM-DD", the first evening to run on
+    pub start_date: Option<String>,
+    /// "YYYY-MM-DD", the last evening to run on
+    pub end_date: Option<String>,
+    /// "HH:MM", lights come on no earlier than this
+    pub evening_start: String,
+    /// "HH:MM", and are off again by this
+    pub evening_end: String,
+}
+
+/// `Settings` once they've been checked
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    evening_start: NaiveTime,
+    evening_end: NaiveTime,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct SettingsRow {
+    enabled: bool,
+    start_date: Option<String>,
+    end_date: Option<String>,
+    evening_start: i64,
+    evening_end: i64,
+}
+
+/// A group of lights that go on and off together
+#[derive(Serialize, Debug, Clone)]
+pub struct Room {
+    pub id: i64,
+    pub name: String,
+    /// chance the room is lit on a given evening, 0 to 1
+    pub probability: f64,
+    pub lights: Vec<String>,
+    pub created_at: i64,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct RoomRow {
+    id: i64,
+    name: String,
+    probability: f64,
+    created_at: i64,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct RoomDeviceRow {
+    room_id: i64,
+    name: String,
+}
+
+/// One planned switch of a room's lights
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub at: DateTime<Local>,
+    pub room: String,
+    pub lights: Vec<String>,
+    pub toggle: bool,
+}
+
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct LogEntry {
+    pub id: i64,
+    pub room_name: String,
+    pub toggle: bool,
+    pub lights: Json<Vec<String>>,
+    pub planned_for: i64,
+    pub ran_at: i64,
+    pub ok: bool,
+    pub message: Option<String>,
+}
+
+fn minutes_to_time(minutes: i64) -> String {
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+fn time_to_minutes(time: NaiveTime) -> i64 {
+    i64::from(time.hour() * 60 + time.minute())
+}
+
+fn parse_date(date: &Option<String>, what: &str) -> Result<NaiveDate, String> {
+    let date = date
+        .as_deref()
+        .ok_or_else(|| format!("Away mode needs {what}"))?;
+    NaiveDate::parse_from_str(date, DATE_FORMAT)
+        .map_err(|_| format!("{date} isn't a YYYY-MM-DD date"))
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(time, TIME_FORMAT).map_err(|_| format!("{time} isn't an HH:MM time"))
+}
+
+impl Settings {
+    /// The dates and evening hours, with a readable reason when they don't make sense.
+    /// Dates can be left out while away mode is off.
+    fn window(&self) -> Result<Option<Window>, String> {
+        let evening_start = parse_time(&self.evening_start)?;
+        let evening_end = parse_time(&self.evening_end)?;
+        if evening_end <= evening_start {
+            return Err("The evening has to end after it starts".to_string());
+        }
+
+        if !self.enabled && self.start_date.is_none() && self.end_date.is_none() {
+            return Ok(None);
+        }
+
+        let start_date = parse_date(&self.start_date, "a start date")?;
+        let end_date = parse_date(&self.end_date, "an end date")?;
+        if end_date < start_date {
+            return Err("Away mode has to end on or after the day it starts".to_string());
+        }
+
+        Ok(Some(Window {
+            start_date,
+            end_date,
+            evening_start,
+            evening_end,
+        }))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.window().map(|_| ())
+    }
+
+    /// The window to run in on `date`, if away mode is on that evening
+    fn active_on(&self, date: NaiveDate) -> Option<Window> {
+        if !self.enabled {
+            return None;
+        }
+        self.window()
+            .ok()
+            .flatten()
+            .filter(|window| (window.start_date..=window.end_date).contains(&date))
+    }
+}
+
+/// Picks which rooms light up on `date` and when. Seeded by the date and room so the same
+/// evening always plans the same way, and a restart part way through picks up where it left
+/// off instead of leaving lights on. The window is read in `zone`, which is `Local` outside of
+/// tests.
+fn plan_evening<Tz: TimeZone>(
+    zone: &Tz,
+    date: NaiveDate,
+    window: Window,
+    rooms: &[Room],
+) -> Vec<Event> {
+    let at = |minutes: i64| {
+        let time = NaiveTime::from_num_seconds_from_midnight_opt(minutes as u32 * 60, 0)?;
+        let at = zone.from_local_datetime(&date.and_time(time)).earliest()?;
+        Some(at.with_timezone(&Local))
+    };
+    let evening_start = time_to_minutes(window.evening_start);
+    let evening_end = time_to_minutes(window.evening_end);
+
+    let mut events = vec![];
+    for room in rooms.iter().filter(|room| !room.lights.is_empty()) {
+        let seed = ((date.num_days_from_ce() as u64) << 32) | room.id as u64;
+        let mut rng = StdRng::seed_from_u64(seed);
+        if !rng.random_bool(room.probability.clamp(0.0, 1.0)) {
+            continue;
+        }
+
+        let latest_start = (evening_end - MIN_SESSION).max(evening_start);
+        let mut on = rng.random_range(evening_start..=latest_start);
+        loop {
+            let off = (on + rng.random_range(MIN_SESSION..=MAX_SESSION)).min(evening_end);
+            for (minutes, toggle) in [(on, true), (off, false)] {
+                if let Some(at) = at(minutes) {
+                    events.push(Event {
+                        at,
+                        room: room.name.clone(),
+                        lights: room.lights.clone(),
+                        toggle,
+                    });
+                }
+            }
+
+            on = off + rng.random_range(MIN_GAP..=MAX_GAP);
+            if on + MIN_SESSION > evening_end || !rng.random_bool(SECOND_SESSION_CHANCE) {
+                break;
+            }
+        }
+    }
+
+    events.sort_by_key(|event| event.at);
+    events
+}
+
+/// What away mode will do on `date`, nothing when it's off that evening
+pub async fn plan_for(pool: &Pool<Sqlite>, date: NaiveDate) -> anyhow::Result<Vec<Event>> {
+    let settings = get_settings(pool).await?;
+    let Some(window) = settings.active_on(date) else {
+        return Ok(vec![]);
+    };
+    let rooms = get_rooms(pool).await?;
+
+    Ok(plan_evening(&Local, date, window, &rooms))
+}
+
+pub async fn get_settings(pool: &Pool<Sqlite>) -> anyhow::Result<Settings> {
+    let row = sqlx::query_as!(
+        SettingsRow,
+        r#"
+        SELECT enabled AS "enabled: bool", start_date, end_date, evening_start, evening_end
+        FROM away_settings WHERE id = 1
+        "#,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(Settings {
+        enabled: row.enabled,
+        start_date: row.start_date,
+        end_date: row.end_date,
+        evening_start: minutes_to_time(row.evening_start),
+        evening_end: minutes_to_time(row.evening_end),
+    })
+}
+
+/// Saves settings that passed `Settings::validate`
+pub async fn save_settings(pool: &Pool<Sqlite>, settings: &Settings) -> anyhow::Result<()> {
+    let evening_start =
+        time_to_minutes(parse_time(&settings.evening_start).map_err(anyhow::Error::msg)?);
+    let evening_end =
+        time_to_minutes(parse_time(&settings.evening_end).map_err(anyhow::Error::msg)?);
+    let now = Utc::now().timestamp();
+
+    sqlx::query!(
+        r"
+        UPDATE away_settings
+        SET enabled = ?1, start_date = ?2, end_date = ?3, evening_start = ?4, evening_end = ?5,
+            updated_at = ?6
+        WHERE id = 1
+        ",
+        settings.enabled,
+        settings.start_date,
+        settings.end_date,
+        evening_start,
+        evening_end,
+        now
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn get_rooms(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<Room>> {
+    let rooms = sqlx::query_as!(
+        RoomRow,
+        r"
+        SELECT id, name, probability, created_at FROM away_rooms ORDER BY name
+        ",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let devices = sqlx::query_as!(
+        RoomDeviceRow,
+        r"
+        SELECT away_room_devices.room_id, devices.name
+        FROM away_room_devices
+        JOIN devices ON devices.id = away_room_devices.device_id
+        ORDER BY devices.name
+        ",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rooms
+        .into_iter()
+        .map(|room| Room {
+            lights: devices
+                .iter()
+                .filter(|device| device.room_id == room.id)
+                .map(|device| device.name.clone())
+                .collect(),
+            id: room.id,
+            name: room.name,
+            probability: room.probability,
+            created_at: room.created_at,
+        })
+        .collect())
+}
+
+pub async fn get_room(pool: &Pool<Sqlite>, id: i64) -> anyhow::Result<Option<Room>> {
+    let room = get_rooms(pool)
+        .await?
+        .into_iter()
+        .find(|room| room.id == id);
+
+    Ok(room)
+}
+
+/// Creates the room, or replaces it when `id` is given. Every light has to name a device in
+/// the registry.
+pub async fn save_room(
+    pool: &Pool<Sqlite>,
+    id: Option<i64>,
+    name: &str,
+    probability: f64,
+    lights: &[String],
+) -> anyhow::Result<i64> {
+    let mut tx = pool.begin().await?;
+
+    let id = match id {
+        Some(id) => {
+            sqlx::query!(
+                r"
+                UPDATE away_rooms SET name = ?1, probability = ?2 WHERE id = ?3
+                ",
+                name,
+                probability,
+                id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query!(
+                r"
+                DELETE FROM away_room_devices WHERE room_id = ?1
+                ",
+                id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            id
+        }
+        None => {
+            let now = Utc::now().timestamp();
+            sqlx::query!(
+                r"
+                INSERT INTO away_rooms (name, probability, created_at) VALUES (?1, ?2, ?3)
+                ",
+                name,
+                probability,
+                now
+            )
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid()
+        }
+    };
+
+    for light in lights {
+        sqlx::query!(
+            r"
+            INSERT INTO away_room_devices (room_id, device_id)
+            SELECT ?1, id FROM devices WHERE name = ?2
+            ",
+            id,
+            light
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(id)
+}
+
+pub async fn get_log(pool: &Pool<Sqlite>, limit: i64) -> anyhow::Result<Vec<LogEntry>> {
+    let entries = sqlx::query_as!(
+        LogEntry,
+        r#"
+        SELECT id, room_name, toggle AS "toggle: bool",
+               lights_json AS "lights: Json<Vec<String>>", planned_for, ran_at, ok AS "ok: bool",
+               message
+        FROM away_log
+        ORDER BY ran_at DESC, id DESC
+        LIMIT ?1
+        "#,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+/// Switches rooms as tonight's plan comes due. Switches missed while root was down are skipped,
+/// the plan for the rest of the evening still runs. Rooms it turned on that the plan no longer
+/// turns off, like when away mode is switched off part way through the evening, are turned off
+/// right away.
+pub async fn run(pool: Pool<Sqlite>, backends: Backends, light_states: Arc<LightStateCache>) {
+    let mut last_check = Local::now();
+    let mut interval = tokio::time::interval(TICK);
+    // the lights of each room turned on and not off yet, by room name
+    let mut lit: HashMap<String, Vec<String>> = HashMap::new();
+
+    loop {
+        interval.tick().await;
+        let now = Local::now();
+
+        // an evening never crosses midnight, but a tick can
+        let mut dates = vec![last_check.date_naive()];
+        if now.date_naive() != last_check.date_naive() {
+            dates.push(now.date_naive());
+        }
+
+        let mut events = vec![];
+        for date in dates {
+            match plan_for(&pool, date).await {
+                Ok(planned) => events.extend(planned),
+                Err(err) => error!("Failed to plan away mode: {err:?}"),
+            }
+        }
+
+        for event in events
+            .iter()
+            .filter(|event| event.at > last_check && event.at <= now)
+        {
+            run_event(&pool, &backends, &light_states, event).await;
+            if event.toggle {
+                lit.insert(event.room.clone(), event.lights.clone());
+            } else {
+                lit.remove(&event.room);
+            }
+        }
+
+        let stranded: Vec<Event> = lit
+            .iter()
+            .filter(|(room, _)| {
+                !events
+                    .iter()
+                    .any(|event| event.room == **room && event.at > now)
+            })
+            .map(|(room, lights)| Event {
+                at: now,
+                room: room.clone(),
+                lights: lights.clone(),
+                toggle: false,
+            })
+            .collect();
+        for event in stranded {
+            run_event(&pool, &backends, &light_states, &event).await;
+            lit.remove(&event.room);
+        }
+
+        last_check = now;
+    }
+}
+
+/// Switches the event's room and logs how it went
+async fn run_event(
+    pool: &Pool<Sqlite>,
+    backends: &Backends,
+    light_states: &LightStateCache,
+    event: &Event,
+) {
+    let result = switch(pool, backends, light_states, event).await;
+    match &result {
+        Ok(()) => info!("Away mode turned {} {}", on_off(event.toggle), event.room),
+        Err(err) => warn!("Away mode couldn't switch {}: {err:?}", event.room),
+    }
+    if let Err(err) = log_switch(pool, event, &result).await {
+        error!("Failed to log away mode: {err:?}");
+    }
+}
+
+fn on_off(toggle: bool) -> &'static str {
+    if toggle { "on" } else { "off" }
+}
+
+async fn switch(
+    pool: &Pool<Sqlite>,
+    backends: &Backends,
+    light_states: &LightStateCache,
+    event: &Event,
+) -> anyhow::Result<()> {
+    let items: Vec<LightItem> = event
+        .lights
+        .iter()
+        .map(|name| LightItem {
+            name: name.clone(),
+            toggle: Some(event.toggle),
+            brightness: None,
+            color: None,
+            kelvin: None,
+        })
+        .collect();
+
+    let plans = lights::plan(pool, &items).await?;
+    let results = lights::send(backends, &plans).await;
+    light_states.record(&plans, &results);
+
+    let failures: Vec<String> = results
+        .iter()
+        .filter(|result| result.status != LightStatus::Ok)
+        .map(|result| format!("{}: {}", result.name, result.status))
+        .collect();
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(failures.join(", ")))
+    }
+}
+
+async fn log_switch(
+    pool: &Pool<Sqlite>,
+    event: &Event,
+    result: &anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let lights_json = serde_json::to_string(&event.lights)?;
+    let planned_for = event.at.timestamp();
+    let now = Utc::now().timestamp();
+    let ok = result.is_ok();
+    let message = result.as_ref().err().map(|err| err.to_string());
+
+    sqlx::query!(
+        r"
+        INSERT INTO away_log (room_name, toggle, lights_json, planned_for, ran_at, ok, message)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        ",
+        event.room,
+        event.toggle,
+        lights_json,
+        planned_for,
+        now,
+        ok,
+        message
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Keeps `Event` times readable in responses
+#[derive(Serialize, Debug)]
+pub struct PlannedSwitch {
+    pub at: String,
+    pub room: String,
+    pub lights: Vec<String>,
+    pub toggle: bool,
+}
+
+impl From<Event> for PlannedSwitch {
+    fn from(event: Event) -> Self {
+        PlannedSwitch {
+            at: event.at.to_rfc3339(),
+            room: event.room,
+            lights: event.lights,
+            toggle: event.toggle,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::America::Chicago;
+
+    fn room(id: i64, probability: f64, lights: &[&str]) -> Room {
+        Room {
+            id,
+            name: format!("room {id}"),
+            probability,
+            lights: lights.iter().map(|light| light.to_string()).collect(),
+            created_at: 0,
+        }
+    }
+
+    fn summary(events: &[Event]) -> Vec<(DateTime<Local>, String, bool)> {
+        events
+            .iter()
+            .map(|event| (event.at, event.room.clone(), event.toggle))
+            .collect()
+    }
+
+    #[test]
+    fn plans_each_evening_the_same_way_within_the_window() {
+        let window = Window {
+            start_date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+            evening_start: NaiveTime::from_hms_opt(18, 30, 0).unwrap(),
+            evening_end: NaiveTime::from_hms_opt(22, 0, 0).unwrap(),
+        };
+        let rooms = [
+            room(1, 1.0, &["studio lights"]),
+            room(2, 0.5, &["bubble lamp", "hall"]),
+            room(3, 0.0, &["porch"]),
+            room(4, 1.0, &[]),
+        ];
+
+        // includes the night the clocks go forward
+        for day in 1..=31 {
+            let date = NaiveDate::from_ymd_opt(2024, 3, day).unwrap();
+            let events = plan_evening(&Chicago, date, window, &rooms);
+            assert_eq!(
+                summary(&events),
+                summary(&plan_evening(&Chicago, date, window, &rooms)),
+                "{date}"
+            );
+
+            let start = Chicago
+                .from_local_datetime(&date.and_time(window.evening_start))
+                .unwrap();
+            let end = Chicago
+                .from_local_datetime(&date.and_time(window.evening_end))
+                .unwrap();
+            assert!(
+                events
+                    .iter()
+                    .all(|event| start <= event.at && event.at <= end)
+            );
+            assert!(events.windows(2).all(|pair| pair[0].at <= pair[1].at));
+
+            for room in &rooms {
+                let toggles: Vec<_> = events
+                    .iter()
+                    .filter(|event| event.room == room.name)
+                    .map(|event| event.toggle)
+                    .collect();
+                match room.id {
+                    1 => assert!(!toggles.is_empty(), "{date}"),
+                    3 | 4 => assert!(toggles.is_empty(), "{date}"),
+                    _ => {}
+                }
+                // on and off in turn, ending off
+                assert!(
+                    toggles.chunks(2).all(|pair| pair == [true, false]),
+                    "{date}"
+                );
+            }
+        }
+
+        // a room's plan doesn't depend on the other rooms
+        let date = NaiveDate::from_ymd_opt(2024, 3, 15).unwrap();
+        let alone: Vec<_> = plan_evening(&Chicago, date, window, &rooms[..1]);
+        let together: Vec<_> = plan_evening(&Chicago, date, window, &rooms)
+            .into_iter()
+            .filter(|event| event.room == rooms[0].name)
+            .collect();
+        assert_eq!(summary(&alone), summary(&together));
+    }
+}
@@ -0,0 +1,112 @@
+use sqlx::{Pool, Sqlite};
+use std::{
+    collections::HashMap,
+    env,
+    ops::RangeInclusive,
+    sync::{OnceLock, RwLock},
+};
+
+/// Costs the bcrypt crate will hash with, anything else fails every hash
+const BCRYPT_COSTS: RangeInclusive<u32> = 4..=31;
+
+static BCRYPT_COST: OnceLock<u32> = OnceLock::new();
+
+/// Reads the work factor for new hashes from `BCRYPT_COST`, so it can go up as hardware gets
+/// faster. Runs once at startup so a bad cost stops root booting instead of failing logins.
+pub fn load_bcrypt_cost() -> anyhow::Result<()> {
+    let cost = match env::var("BCRYPT_COST") {
+        Ok(cost) => cost
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("BCRYPT_COST isn't a number: {cost}"))?,
+        Err(_) => bcrypt::DEFAULT_COST,
+    };
+    if !BCRYPT_COSTS.contains(&cost) {
+        return Err(anyhow::anyhow!(
+            "BCRYPT_COST has to be {} to {}, not {cost}",
+            BCRYPT_COSTS.start(),
+            BCRYPT_COSTS.end()
+        ));
+    }
+
+    BCRYPT_COST.get_or_init(|| cost);
+    Ok(())
+}
+
+/// The cheapest cost, so tests don't spend seconds hashing
+#[cfg(test)]
+pub fn use_cheapest_cost() {
+    BCRYPT_COST.get_or_init(|| *BCRYPT_COSTS.start());
+}
+
+pub fn bcrypt_cost() -> u32 {
+    BCRYPT_COST.get().copied().unwrap_or(bcrypt::DEFAULT_COST)
+}
+
+/// Whether a hash was made with a different cost than the current setting
+pub fn needs_rehash(password_hash: &str) -> bool {
+    password_hash
+        .parse::<bcrypt::HashParts>()
+        .is_ok_and(|parts| parts.get_cost() != bcrypt_cost())
+}
+
+/// Hashes `password` and stores it for `user_id`
+pub async fn set_password(pool: &Pool<Sqlite>, user_id: i64, password: &str) -> anyhow::Result<()> {
+    let password_hash = bcrypt::hash(password, bcrypt_cost())?;
+
+    sqlx::query!(
+        r"
+        UPDATE users SET password_hash = ?1 WHERE id = ?2
+        ",
+        password_hash,
+        user_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(sqlx::FromRow, Debug, Clone)]
+pub struct Credential {
+    pub id: i64,
+    pub username: String,
+    pub password_hash: String,
+}
+
+/// Password hashes kept in memory so logins don't hit the database,
+/// reloaded on SIGHUP after `root add-user` and friends change the table
+#[derive(Debug, Default)]
+pub struct Credentials {
+    by_username: RwLock<HashMap<String, Credential>>,
+}
+
+impl Credentials {
+    pub async fn load(pool: &Pool<Sqlite>) -> anyhow::Result<Self> {
+        let credentials = Self::default();
+        credentials.reload(pool).await?;
+        Ok(credentials)
+    }
+
+    pub async fn reload(&self, pool: &Pool<Sqlite>) -> anyhow::Result<()> {
+        let rows = sqlx::query_as!(
+            Credential,
+            r"
+            SELECT id, username, password_hash FROM users
+            ",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let by_username = rows
+            .into_iter()
+            .map(|row| (row.username.clone(), row))
+            .collect();
+        *self.by_username.write().unwrap() = by_username;
+        Ok(())
+    }
+
+    pub fn get(&self, username: &str) -> Option<Credential> {
+        self.by_username.read().unwrap().get(username).cloned()
+    }
+}
@@ -0,0 +1,229 @@
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone};
+use std::str::FromStr;
+
+/// How far ahead to look before giving up, covers a Feb 29th schedule
+const MAX_DAYS_AHEAD: u32 = 8 * 366;
+
+/// A five field cron expression, `minute hour day-of-month month day-of-week`, in the time zone
+/// it's asked about (local time, outside of tests).
+/// Fields take `*`, numbers, ranges (`1-5`), lists (`1,3`) and steps (`*/15`, `8-18/2`).
+/// Sunday is 0 or 7.
+#[derive(Debug, Clone)]
+pub struct Cron {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days_of_month: Vec<u32>,
+    months: Vec<u32>,
+    days_of_week: Vec<u32>,
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl FromStr for Cron {
+    type Err = String;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(format!(
+                "Expected 5 fields in `{expr}`, minute hour day-of-month month day-of-week"
+            ));
+        };
+
+        let mut days_of_week = parse_field(day_of_week, 0, 7)?;
+        for day in &mut days_of_week {
+            *day %= 7;
+        }
+        days_of_week.sort_unstable();
+        days_of_week.dedup();
+
+        Ok(Cron {
+            minutes: parse_field(minute, 0, 59)?,
+            hours: parse_field(hour, 0, 23)?,
+            days_of_month: parse_field(day_of_month, 1, 31)?,
+            months: parse_field(month, 1, 12)?,
+            days_of_week,
+            any_day_of_month: day_of_month == "*",
+            any_day_of_week: day_of_week == "*",
+        })
+    }
+}
+
+impl Cron {
+    /// The first matching minute strictly after `after`, in its time zone
+    pub fn next_after<Tz: TimeZone>(&self, after: DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let zone = after.timezone();
+        let mut date = after.date_naive();
+
+        for _ in 0..MAX_DAYS_AHEAD {
+            if self.matches_day(date) {
+                for &hour in &self.hours {
+                    for &minute in &self.minutes {
+                        let Some(naive) = date.and_hms_opt(hour, minute, 0) else {
+                            continue;
+                        };
+                        // times skipped by a DST jump never happen
+                        let Some(time) = zone.from_local_datetime(&naive).earliest() else {
+                            continue;
+                        };
+                        if time > after {
+                            return Some(time);
+                        }
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+
+        None
+    }
+
+    /// Like cron, when both day fields are restricted either one matching is enough
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        if !self.months.contains(&date.month()) {
+            return false;
+        }
+
+        let day_of_month = self.days_of_month.contains(&date.day());
+        let day_of_week = self
+            .days_of_week
+            .contains(&date.weekday().num_days_from_sunday());
+
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (true, true) => true,
+            (true, false) => day_of_week,
+            (false, true) => day_of_month,
+            (false, false) => day_of_month || day_of_week,
+        }
+    }
+}
+
+/// Every value a field allows, sorted
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<u32>, String> {
+    let mut values = vec![];
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, parse_number(step)?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(format!("Step can't be 0 in `{part}`"));
+        }
+
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (parse_number(start)?, parse_number(end)?)
+        } else {
+            let start = parse_number(range)?;
+            // `5/10` means every 10 starting at 5
+            (start, if part.contains('/') { max } else { start })
+        };
+
+        if start < min || end > max || start > end {
+            return Err(format!("`{part}` is outside {min}-{max}"));
+        }
+        values.extend((start..=end).step_by(step as usize));
+    }
+
+    values.sort_unstable();
+    values.dedup();
+    Ok(values)
+}
+
+fn parse_number(number: &str) -> Result<u32, String> {
+    number
+        .parse()
+        .map_err(|_| format!("`{number}` isn't a number"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::{America::Chicago, Tz};
+
+    /// A time in a zone with DST, so the answers don't depend on the machine
+    fn local(datetime: &str) -> DateTime<Tz> {
+        let naive = chrono::NaiveDateTime::parse_from_str(datetime, "%Y-%m-%d %H:%M").unwrap();
+        Chicago.from_local_datetime(&naive).earliest().unwrap()
+    }
+
+    #[test]
+    fn rejects_bad_expressions() {
+        let cases = [
+            ("* * * *", "Expected 5 fields"),
+            ("* * * * * *", "Expected 5 fields"),
+            ("60 * * * *", "`60` is outside 0-59"),
+            ("* 24 * * *", "`24` is outside 0-23"),
+            ("* * 0 * *", "`0` is outside 1-31"),
+            ("* * * 13 *", "`13` is outside 1-12"),
+            ("* * * * 8", "`8` is outside 0-7"),
+            ("5-1 * * * *", "`5-1` is outside 0-59"),
+            ("*/0 * * * *", "Step can't be 0"),
+            ("a * * * *", "`a` isn't a number"),
+            ("1-x * * * *", "`x` isn't a number"),
+            ("*/y * * * *", "`y` isn't a number"),
+        ];
+
+        for (expr, expected) in cases {
+            let err = expr.parse::<Cron>().unwrap_err();
+            assert!(err.contains(expected), "{expr}: {err}");
+        }
+    }
+
+    #[test]
+    fn parses_fields() {
+        let cron: Cron = "*/15 8-18/5 1,15 * 7".parse().unwrap();
+        assert_eq!(cron.minutes, [0, 15, 30, 45]);
+        assert_eq!(cron.hours, [8, 13, 18]);
+        assert_eq!(cron.days_of_month, [1, 15]);
+        assert_eq!(cron.months, (1..=12).collect::<Vec<_>>());
+        assert_eq!(cron.days_of_week, [0]);
+
+        let cron: Cron = "5/20 * * * 0,7".parse().unwrap();
+        assert_eq!(cron.minutes, [5, 25, 45]);
+        assert_eq!(cron.days_of_week, [0]);
+    }
+
+    #[test]
+    fn next_after() {
+        let cases = [
+            // plain daily time, today if it's still ahead
+            ("30 6 * * *", "2026-10-16 06:00", Some("2026-10-16 06:30")),
+            ("30 6 * * *", "2026-10-16 06:30", Some("2026-10-17 06:30")),
+            // Sunday as 7 and as 0
+            ("0 8 * * 7", "2026-10-16 12:00", Some("2026-10-18 08:00")),
+            ("0 8 * * 0", "2026-10-16 12:00", Some("2026-10-18 08:00")),
+            // both day fields restricted, the 13th or a Friday
+            ("0 0 13 * 5", "2026-10-01 12:00", Some("2026-10-02 00:00")),
+            ("0 0 13 * 5", "2026-10-09 12:00", Some("2026-10-13 00:00")),
+            // 2:30 doesn't happen the night the clocks go forward
+            ("30 2 * * *", "2026-03-07 12:00", Some("2026-03-09 02:30")),
+            ("0 3 * * *", "2026-03-07 12:00", Some("2026-03-08 03:00")),
+            // 1:30 happens twice the night they go back, it fires on the first
+            ("30 1 * * *", "2026-10-31 12:00", Some("2026-11-01 01:30")),
+            // leap days wait for the next leap year
+            ("0 9 29 2 *", "2026-03-01 00:00", Some("2028-02-29 09:00")),
+            ("0 9 29 2 *", "2028-02-29 09:00", Some("2032-02-29 09:00")),
+            ("0 0 30 2 *", "2026-01-01 00:00", None),
+        ];
+
+        for (expr, after, expected) in cases {
+            let cron: Cron = expr.parse().unwrap();
+            assert_eq!(
+                cron.next_after(local(after)),
+                expected.map(local),
+                "{expr} after {after}"
+            );
+        }
+    }
+
+    #[test]
+    fn fires_once_when_the_clocks_go_back() {
+        let cron: Cron = "30 1 * * *".parse().unwrap();
+        let first = cron.next_after(local("2026-10-31 12:00")).unwrap();
+        let second = cron.next_after(first).unwrap();
+        assert_eq!(second, local("2026-11-02 01:30"));
+    }
+}
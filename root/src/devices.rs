@@ -0,0 +1,208 @@
+use chrono::Utc;
+use govee::GoveeApi;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+
+/// How root reaches a device
+#[derive(sqlx::Type, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// through Govee's cloud API
+    #[default]
+    Cloud,
+    /// straight to the light over the local network, keeps working when the internet is down
+    Lan,
+}
+
+#[derive(sqlx::FromRow, Debug, Clone, Serialize)]
+pub struct Device {
+    pub id: i64,
+    pub name: String,
+    /// the MAC-like id Govee addresses the device by
+    pub device: String,
+    pub sku: String,
+    pub backend: Backend,
+    pub created_at: i64,
+}
+
+impl Device {
+    pub fn target(&self) -> govee::Target {
+        govee::Target {
+            sku: self.sku.clone(),
+            device: self.device.clone(),
+        }
+    }
+}
+
+/// What a model of light accepts beyond switching on and off
+#[derive(sqlx::FromRow, Debug, Clone, Default, Serialize)]
+pub struct SkuCapabilities {
+    pub sku: String,
+    pub brightness: bool,
+    pub color: bool,
+    pub min_kelvin: Option<i64>,
+    pub max_kelvin: Option<i64>,
+}
+
+impl SkuCapabilities {
+    fn from_govee(device: &govee::Device) -> Self {
+        let support = device.support();
+        SkuCapabilities {
+            sku: device.sku.clone(),
+            brightness: support.brightness,
+            color: support.color,
+            min_kelvin: support.kelvin.map(|range| range.min),
+            max_kelvin: support.kelvin.map(|range| range.max),
+        }
+    }
+}
+
+pub async fn get_devices(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<Device>> {
+    let devices = sqlx::query_as!(
+        Device,
+        r#"
+        SELECT id, name, device, sku, backend AS "backend: Backend", created_at
+        FROM devices ORDER BY name
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(devices)
+}
+
+pub async fn get_device(pool: &Pool<Sqlite>, id: i64) -> anyhow::Result<Option<Device>> {
+    let device = sqlx::query_as!(
+        Device,
+        r#"
+        SELECT id, name, device, sku, backend AS "backend: Backend", created_at
+        FROM devices WHERE id = ?1
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(device)
+}
+
+/// Capabilities of every SKU we know about, keyed by SKU
+pub async fn get_sku_capabilities(
+    pool: &Pool<Sqlite>,
+) -> anyhow::Result<HashMap<String, SkuCapabilities>> {
+    let rows = sqlx::query_as!(
+        SkuCapabilities,
+        r"
+        SELECT * FROM sku_capabilities
+        ",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| (row.sku.clone(), row)).collect())
+}
+
+pub async fn add_device(
+    pool: &Pool<Sqlite>,
+    name: &str,
+    device: &str,
+    sku: &str,
+    backend: Backend,
+) -> anyhow::Result<Device> {
+    let now = Utc::now().timestamp();
+
+    let id = sqlx::query!(
+        r"
+        INSERT INTO devices (name, device, sku, backend, created_at) VALUES (?1, ?2, ?3, ?4, ?5)
+        ",
+        name,
+        device,
+        sku,
+        backend,
+        now
+    )
+    .execute(pool)
+    .await?
+    .last_insert_rowid();
+
+    Ok(Device {
+        id,
+        name: name.to_string(),
+        device: device.to_string(),
+        sku: sku.to_string(),
+        backend,
+        created_at: now,
+    })
+}
+
+/// Pulls every device on the Govee account and adds the ones we don't know yet.
+/// Known devices keep their local name and only pick up SKU changes, and every SKU's
+/// capabilities are refreshed. Returns how many devices were added.
+pub async fn sync_from_govee(pool: &Pool<Sqlite>, govee: &impl GoveeApi) -> anyhow::Result<usize> {
+    let mut added = 0;
+    for govee_device in govee.devices().await? {
+        let capabilities = SkuCapabilities::from_govee(&govee_device);
+        sqlx::query!(
+            r"
+            INSERT INTO sku_capabilities (sku, brightness, color, min_kelvin, max_kelvin)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT (sku) DO UPDATE SET
+              brightness = excluded.brightness,
+              color = excluded.color,
+              min_kelvin = excluded.min_kelvin,
+              max_kelvin = excluded.max_kelvin
+            ",
+            capabilities.sku,
+            capabilities.brightness,
+            capabilities.color,
+            capabilities.min_kelvin,
+            capabilities.max_kelvin
+        )
+        .execute(pool)
+        .await?;
+
+        let updated = sqlx::query!(
+            r"
+            UPDATE devices SET sku = ?1 WHERE device = ?2
+            ",
+            govee_device.sku,
+            govee_device.device
+        )
+        .execute(pool)
+        .await?;
+
+        if updated.rows_affected() > 0 {
+            continue;
+        }
+
+        // the Govee app allows duplicate names, our names have to be unique
+        let name_taken = sqlx::query_scalar!(
+            r"
+            SELECT COUNT(*) FROM devices WHERE name = ?1
+            ",
+            govee_device.device_name
+        )
+        .fetch_one(pool)
+        .await?
+            > 0;
+        let name = if name_taken {
+            format!("{} ({})", govee_device.device_name, govee_device.device)
+        } else {
+            govee_device.device_name
+        };
+
+        add_device(
+            pool,
+            &name,
+            &govee_device.device,
+            &govee_device.sku,
+            Backend::Cloud,
+        )
+        .await?;
+        added += 1;
+    }
+
+    Ok(added)
+}
@@ -0,0 +1,108 @@
+use govee::{DeviceState, Target};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    devices::Device,
+    lights::{Backends, LightPlan, LightResult, LightStatus},
+};
+
+/// How long a reported state is trusted. Govee allows 10,000 requests a day per account and
+/// every page load asks about every light.
+const TTL: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct Cached {
+    fetched_at: Instant,
+    state: DeviceState,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LightState {
+    pub name: String,
+    #[serde(flatten)]
+    pub state: DeviceState,
+    /// why Govee couldn't be asked, the state fields are all null then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Recently reported light states by device, so page loads don't each cost a request per light
+#[derive(Debug, Default)]
+pub struct LightStateCache {
+    entries: Mutex<HashMap<Target, Cached>>,
+}
+
+impl LightStateCache {
+    fn get(&self, target: &Target) -> Option<DeviceState> {
+        self.entries
+            .lock()
+            .unwrap()
+            .get(target)
+            .filter(|cached| cached.fetched_at.elapsed() < TTL)
+            .map(|cached| cached.state.clone())
+    }
+
+    /// The state of every device, from the cache when it's fresh and from Govee otherwise
+    pub async fn states(&self, backends: &Backends, devices: &[Device]) -> Vec<LightState> {
+        let futs = devices.iter().map(|device| async move {
+            let target = device.target();
+            if let Some(state) = self.get(&target) {
+                return LightState {
+                    name: device.name.clone(),
+                    state,
+                    error: None,
+                };
+            }
+
+            match backends.state(device.backend, &target).await {
+                Ok(state) => {
+                    self.entries.lock().unwrap().insert(
+                        target,
+                        Cached {
+                            fetched_at: Instant::now(),
+                            state: state.clone(),
+                        },
+                    );
+                    LightState {
+                        name: device.name.clone(),
+                        state,
+                        error: None,
+                    }
+                }
+                Err(err) => LightState {
+                    name: device.name.clone(),
+                    state: DeviceState::default(),
+                    error: Some(err.to_string()),
+                },
+            }
+        });
+
+        futures_util::future::join_all(futs).await
+    }
+
+    /// Applies what was just sent to the cached states without asking Govee. Lights that failed
+    /// part way are forgotten since there's no telling what they're doing.
+    pub fn record(&self, plans: &[LightPlan], results: &[LightResult]) {
+        let mut entries = self.entries.lock().unwrap();
+
+        for (plan, result) in plans.iter().zip(results) {
+            let Some(target) = &plan.target else {
+                continue;
+            };
+            if result.status != LightStatus::Ok {
+                entries.remove(target);
+                continue;
+            }
+            if let Some(cached) = entries.get_mut(target) {
+                for capability in &plan.capabilities {
+                    cached.state.apply(*capability);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,486 @@
+use govee::{Capability, DeviceState, GoveeApi, Rgb, Target};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::collections::HashMap;
+
+use crate::devices::{self, Backend, Device, SkuCapabilities};
+
+const MIN_BRIGHTNESS: u8 = 1;
+const MAX_BRIGHTNESS: u8 = 100;
+
+/// A light entry asking for something its device can't do
+#[derive(Debug)]
+pub struct InvalidLight(pub String);
+
+impl std::fmt::Display for InvalidLight {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidLight {}
+
+/// What to change on one light, anything left out stays as it is
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LightItem {
+    pub name: String,
+    pub toggle: Option<bool>,
+    /// percent
+    pub brightness: Option<u8>,
+    pub color: Option<Rgb>,
+    pub kelvin: Option<i64>,
+}
+
+impl LightItem {
+    /// Govee takes one capability per request, so each change becomes its own.
+    /// Errors with a readable message when the device's SKU can't do one of them.
+    pub fn capabilities(&self, sku: &SkuCapabilities) -> Result<Vec<Capability>, String> {
+        let mut capabilities = vec![];
+
+        if let Some(on) = self.toggle {
+            capabilities.push(Capability::Power(on));
+        }
+
+        if let Some(percent) = self.brightness {
+            if !sku.brightness {
+                return Err(format!("{} can't be dimmed", self.name));
+            }
+            if !(MIN_BRIGHTNESS..=MAX_BRIGHTNESS).contains(&percent) {
+                return Err(format!(
+                    "Brightness has to be between {MIN_BRIGHTNESS} and {MAX_BRIGHTNESS}"
+                ));
+            }
+            capabilities.push(Capability::Brightness(percent));
+        }
+
+        if self.color.is_some() && self.kelvin.is_some() {
+            return Err(format!(
+                "{} can't take a color and a color temperature at once",
+                self.name
+            ));
+        }
+
+        if let Some(color) = self.color {
+            if !sku.color {
+                return Err(format!("{} doesn't do colors", self.name));
+            }
+            capabilities.push(Capability::Color(color));
+        }
+
+        if let Some(kelvin) = self.kelvin {
+            let (Some(min), Some(max)) = (sku.min_kelvin, sku.max_kelvin) else {
+                return Err(format!("{} doesn't do color temperatures", self.name));
+            };
+            if !(min..=max).contains(&kelvin) {
+                return Err(format!(
+                    "{} takes color temperatures between {min}K and {max}K",
+                    self.name
+                ));
+            }
+            capabilities.push(Capability::ColorTemperature(kelvin));
+        }
+
+        if capabilities.is_empty() {
+            return Err(format!("Nothing to change on {}", self.name));
+        }
+
+        Ok(capabilities)
+    }
+}
+
+/// The changes for one light, in the order they should go out
+#[derive(Debug, Clone)]
+pub struct LightPlan {
+    pub name: String,
+    /// `None` when the name isn't in the registry
+    pub target: Option<Target>,
+    pub backend: Backend,
+    pub capabilities: Vec<Capability>,
+}
+
+/// Every way root can reach a light, whichever of them are set up
+#[derive(Clone, Debug, Default)]
+pub struct Backends {
+    /// `None` when `GOVEE_KEY` isn't set
+    pub cloud: Option<govee::Client>,
+    /// `None` when the LAN socket couldn't be bound
+    pub lan: Option<govee::LanClient>,
+}
+
+impl Backends {
+    pub fn cloud(&self) -> Result<&govee::Client, govee::Error> {
+        self.cloud.as_ref().ok_or(govee::Error::MissingKey)
+    }
+
+    pub fn lan(&self) -> Result<&govee::LanClient, govee::Error> {
+        self.lan.as_ref().ok_or_else(|| {
+            govee::Error::Lan(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                "not set up",
+            ))
+        })
+    }
+
+    /// LAN lights that don't answer in time go through the cloud instead, when it's set up
+    pub async fn control(
+        &self,
+        backend: Backend,
+        target: &Target,
+        capability: Capability,
+    ) -> Result<(), govee::Error> {
+        if backend == Backend::Cloud {
+            return self.cloud()?.control(target, capability).await;
+        }
+
+        let lan = async { self.lan()?.control(target, capability).await }.await;
+        match (lan, &self.cloud) {
+            (Err(err), Some(cloud)) if is_lan_failure(&err) => {
+                warn!(
+                    "{} didn't answer on the LAN, using the cloud: {err}",
+                    target.device
+                );
+                cloud.control(target, capability).await
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Falls back to the cloud like `control`
+    pub async fn state(
+        &self,
+        backend: Backend,
+        target: &Target,
+    ) -> Result<DeviceState, govee::Error> {
+        if backend == Backend::Cloud {
+            return self.cloud()?.state(target).await;
+        }
+
+        let lan = async { self.lan()?.state(target).await }.await;
+        match (lan, &self.cloud) {
+            (Err(err), Some(cloud)) if is_lan_failure(&err) => {
+                warn!(
+                    "{} didn't answer on the LAN, using the cloud: {err}",
+                    target.device
+                );
+                cloud.state(target).await
+            }
+            (result, _) => result,
+        }
+    }
+}
+
+fn is_lan_failure(err: &govee::Error) -> bool {
+    matches!(err, govee::Error::Lan(_) | govee::Error::NoReply)
+}
+
+/// How one light's requests went
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum LightStatus {
+    Ok,
+    UnknownDevice,
+    /// couldn't reach Govee or it answered with a non-2xx status
+    HttpError {
+        message: String,
+    },
+    /// Govee answered but refused the command
+    GoveeError {
+        code: i64,
+        message: String,
+    },
+    RateLimited,
+    /// couldn't reach the light on the local network
+    LanError {
+        message: String,
+    },
+}
+
+impl std::fmt::Display for LightStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LightStatus::Ok => write!(f, "ok"),
+            LightStatus::UnknownDevice => write!(f, "unknown device"),
+            LightStatus::HttpError { message } => write!(f, "{message}"),
+            LightStatus::GoveeError { code, message } => write!(f, "Govee error {code}: {message}"),
+            LightStatus::RateLimited => write!(f, "rate limited"),
+            LightStatus::LanError { message } => write!(f, "{message}"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct LightResult {
+    pub name: String,
+    #[serde(flatten)]
+    pub status: LightStatus,
+}
+
+impl From<govee::Error> for LightStatus {
+    fn from(err: govee::Error) -> Self {
+        match err {
+            govee::Error::RateLimited => LightStatus::RateLimited,
+            govee::Error::Govee { code, message } => LightStatus::GoveeError { code, message },
+            err @ (govee::Error::Lan(_) | govee::Error::NoReply) => LightStatus::LanError {
+                message: err.to_string(),
+            },
+            err => LightStatus::HttpError {
+                message: err.to_string(),
+            },
+        }
+    }
+}
+
+/// Turns each light into the Govee requests it needs, checking everything up front so a bad
+/// request doesn't leave the lights half changed. Lights missing from the registry are planned
+/// as unknown so they show up in the results.
+pub async fn plan(pool: &Pool<Sqlite>, items: &[LightItem]) -> anyhow::Result<Vec<LightPlan>> {
+    let devices: HashMap<String, Device> = devices::get_devices(pool)
+        .await?
+        .into_iter()
+        .map(|device| (device.name.clone(), device))
+        .collect();
+    let sku_capabilities = devices::get_sku_capabilities(pool).await?;
+
+    let mut plans = vec![];
+    for item in items {
+        let Some(device) = devices.get(&item.name) else {
+            warn!("No device named {}", item.name);
+            plans.push(LightPlan {
+                name: item.name.clone(),
+                target: None,
+                backend: Backend::default(),
+                capabilities: vec![],
+            });
+            continue;
+        };
+        let supported = sku_capabilities
+            .get(&device.sku)
+            .cloned()
+            .unwrap_or_default();
+        let capabilities = item.capabilities(&supported).map_err(InvalidLight)?;
+        plans.push(LightPlan {
+            name: item.name.clone(),
+            target: Some(device.target()),
+            backend: device.backend,
+            capabilities,
+        });
+    }
+
+    Ok(plans)
+}
+
+/// Sends every light at once, each light's own changes one after another so they land in
+/// order. A light stops at its first failed change.
+pub async fn send(backends: &Backends, plans: &[LightPlan]) -> Vec<LightResult> {
+    let futs = plans.iter().map(|plan| async move {
+        let status = match &plan.target {
+            None => LightStatus::UnknownDevice,
+            Some(target) => {
+                let mut status = LightStatus::Ok;
+                for capability in &plan.capabilities {
+                    if let Err(err) = backends.control(plan.backend, target, *capability).await {
+                        status = err.into();
+                        warn!("{} failed: {status:?}", plan.name);
+                        break;
+                    }
+                }
+                status
+            }
+        };
+        LightResult {
+            name: plan.name.clone(),
+            status,
+        }
+    });
+
+    futures_util::future::join_all(futs).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use govee::fake::{Failure, FakeGovee};
+
+    const STUDIO: &str = "67:5D:CD:2A:06:06:46:5F";
+    const BUBBLE_LAMP: &str = "38:31:D4:AD:FC:A8:91:CB";
+
+    async fn start(max_attempts: u32) -> (FakeGovee, Backends) {
+        let fake = FakeGovee::start(([127, 0, 0, 1], 0).into(), FakeGovee::house())
+            .await
+            .unwrap();
+        let cloud = govee::Client::new("test-key")
+            .unwrap()
+            .base_url(fake.base_url())
+            .max_attempts(max_attempts);
+        let backends = Backends {
+            cloud: Some(cloud),
+            lan: None,
+        };
+        (fake, backends)
+    }
+
+    fn plan(name: &str, target: Option<(&str, &str)>, capabilities: Vec<Capability>) -> LightPlan {
+        LightPlan {
+            name: name.to_string(),
+            target: target.map(|(sku, device)| Target {
+                sku: sku.to_string(),
+                device: device.to_string(),
+            }),
+            backend: Backend::Cloud,
+            capabilities,
+        }
+    }
+
+    fn statuses(results: Vec<LightResult>) -> Vec<(String, LightStatus)> {
+        results
+            .into_iter()
+            .map(|result| (result.name, result.status))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn reports_each_light() {
+        let (fake, backends) = start(3).await;
+        let plans = [
+            plan(
+                "studio lights",
+                Some(("H612D", STUDIO)),
+                vec![Capability::Power(true), Capability::Brightness(30)],
+            ),
+            plan(
+                "bubble lamp",
+                Some(("H5080", BUBBLE_LAMP)),
+                vec![Capability::Power(true)],
+            ),
+            plan("garage", None, vec![Capability::Power(true)]),
+            plan(
+                "unplugged",
+                Some(("H5080", "00:00:00:00:00:00:00:00")),
+                vec![Capability::Power(true)],
+            ),
+        ];
+
+        let results = send(&backends, &plans).await;
+
+        assert_eq!(
+            statuses(results),
+            [
+                ("studio lights".to_string(), LightStatus::Ok),
+                ("bubble lamp".to_string(), LightStatus::Ok),
+                ("garage".to_string(), LightStatus::UnknownDevice),
+                (
+                    "unplugged".to_string(),
+                    LightStatus::GoveeError {
+                        code: 400,
+                        message: "Device not found".to_string(),
+                    }
+                ),
+            ]
+        );
+
+        let studio = plans[0].target.clone().unwrap();
+        let lamp = plans[1].target.clone().unwrap();
+        let studio_controls: Vec<_> = fake
+            .controls()
+            .into_iter()
+            .filter(|(target, _)| *target == studio)
+            .map(|(_, capability)| capability)
+            .collect();
+        assert_eq!(
+            studio_controls,
+            [Capability::Power(true), Capability::Brightness(30)]
+        );
+        assert_eq!(fake.value(&studio, "brightness"), Some(30));
+        assert_eq!(fake.value(&lamp, "powerSwitch"), Some(1));
+    }
+
+    #[tokio::test]
+    async fn retries_before_reporting() {
+        let (fake, backends) = start(3).await;
+        let plans = [plan(
+            "bubble lamp",
+            Some(("H5080", BUBBLE_LAMP)),
+            vec![Capability::Power(true)],
+        )];
+
+        fake.fail_next(Failure::Status(500));
+        let results = send(&backends, &plans).await;
+
+        assert_eq!(
+            statuses(results),
+            [("bubble lamp".to_string(), LightStatus::Ok)]
+        );
+        assert_eq!(fake.controls().len(), 1);
+        assert_eq!(
+            fake.value(plans[0].target.as_ref().unwrap(), "powerSwitch"),
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_a_light_at_its_first_failure() {
+        let (fake, backends) = start(1).await;
+        let plans = [plan(
+            "studio lights",
+            Some(("H612D", STUDIO)),
+            vec![Capability::Power(true), Capability::Brightness(30)],
+        )];
+
+        fake.fail_next(Failure::RateLimited);
+        let results = send(&backends, &plans).await;
+
+        assert_eq!(
+            statuses(results),
+            [("studio lights".to_string(), LightStatus::RateLimited)]
+        );
+        assert!(fake.controls().is_empty());
+        assert_eq!(
+            fake.value(plans[0].target.as_ref().unwrap(), "brightness"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_cloud_when_the_lan_doesnt_answer() {
+        let (fake, mut backends) = start(3).await;
+        // nothing listens on a port that was just given back
+        let nobody = std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let lan = govee::LanClient::bind(govee::LanConfig {
+            scan_addr: nobody,
+            listen_addr: ([127, 0, 0, 1], 0).into(),
+            control_port: nobody.port(),
+            timeout: std::time::Duration::from_millis(200),
+        })
+        .await
+        .unwrap();
+        backends.lan = Some(lan);
+        let plans = [LightPlan {
+            backend: Backend::Lan,
+            ..plan(
+                "studio lights",
+                Some(("H612D", STUDIO)),
+                vec![Capability::Power(true)],
+            )
+        }];
+
+        let results = send(&backends, &plans).await;
+
+        assert_eq!(
+            statuses(results),
+            [("studio lights".to_string(), LightStatus::Ok)]
+        );
+        let studio = plans[0].target.as_ref().unwrap();
+        assert_eq!(fake.controls(), [(studio.clone(), Capability::Power(true))]);
+        let state = backends.state(Backend::Lan, studio).await.unwrap();
+        assert_eq!(state.power, Some(true));
+
+        // without the cloud the LAN's failure is the answer
+        backends.cloud = None;
+        let results = send(&backends, &plans).await;
+        assert!(matches!(results[0].status, LightStatus::LanError { .. }));
+    }
+}
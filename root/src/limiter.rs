@@ -0,0 +1,298 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Failures an address gets before backoff kicks in
+const FREE_ATTEMPTS: u32 = 3;
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(15 * 60);
+/// Failures after which an address is locked out outright
+const LOCKOUT_ATTEMPTS: u32 = 10;
+const LOCKOUT: Duration = Duration::from_secs(60 * 60);
+/// Failures across every address before every login waits, catches attacks spread over more
+/// addresses than the per-address limit can see
+const GLOBAL_MAX_FAILURES: usize = 100;
+const GLOBAL_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// Failures for one username before its logins are spaced out. Only slowed, never locked, so
+/// nobody can lock a family member out by failing logins as them.
+const USERNAME_FREE_FAILURES: usize = 10;
+const USERNAME_SPACING: Duration = Duration::from_secs(10);
+const USERNAME_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// Addresses that stopped failing this long ago are forgotten
+const FORGET_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug)]
+struct Attempts {
+    failures: u32,
+    blocked_until: Instant,
+    last_failure: Instant,
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    by_ip: HashMap<IpAddr, Attempts>,
+    by_username: HashMap<String, VecDeque<Instant>>,
+    global: VecDeque<Instant>,
+}
+
+/// Throttles failed logins per address with exponential backoff, across every address, and
+/// slows them down per username
+#[derive(Debug, Default)]
+pub struct LoginLimiter {
+    state: Mutex<LimiterState>,
+}
+
+/// A password check that's counted as a failure until `LoginLimiter::succeeded` says otherwise.
+/// Counting it before the check means a burst of guesses can't all get in before the first one
+/// fails.
+#[derive(Debug)]
+pub struct Attempt {
+    ip: IpAddr,
+    username: String,
+    at: Instant,
+}
+
+impl LoginLimiter {
+    /// Counts a check of `username`'s password from `ip`, or says how long to wait first
+    pub fn try_acquire(&self, ip: IpAddr, username: &str) -> Result<Attempt, Duration> {
+        self.try_acquire_at(ip, username, Instant::now())
+    }
+
+    fn try_acquire_at(
+        &self,
+        ip: IpAddr,
+        username: &str,
+        now: Instant,
+    ) -> Result<Attempt, Duration> {
+        let mut state = self.state.lock().unwrap();
+
+        forget_old(&mut state.global, GLOBAL_WINDOW, now);
+        state.by_username.retain(|_, failures| {
+            forget_old(failures, USERNAME_WINDOW, now);
+            !failures.is_empty()
+        });
+        state
+            .by_ip
+            .retain(|_, attempts| now.duration_since(attempts.last_failure) < FORGET_AFTER);
+
+        let mut waits = vec![];
+        if let Some(attempts) = state.by_ip.get(&ip) {
+            waits.push(attempts.blocked_until.saturating_duration_since(now));
+        }
+        if state.global.len() >= GLOBAL_MAX_FAILURES
+            && let Some(oldest) = state.global.front()
+        {
+            waits.push(GLOBAL_WINDOW.saturating_sub(now.duration_since(*oldest)));
+        }
+        if let Some(failures) = state.by_username.get(username)
+            && failures.len() >= USERNAME_FREE_FAILURES
+            && let Some(last) = failures.back()
+        {
+            waits.push(USERNAME_SPACING.saturating_sub(now.duration_since(*last)));
+        }
+        if let Some(wait) = waits.into_iter().filter(|wait| !wait.is_zero()).max() {
+            return Err(wait);
+        }
+
+        state.global.push_back(now);
+        state
+            .by_username
+            .entry(username.to_string())
+            .or_default()
+            .push_back(now);
+
+        let attempts = state.by_ip.entry(ip).or_insert(Attempts {
+            failures: 0,
+            blocked_until: now,
+            last_failure: now,
+        });
+        attempts.failures += 1;
+        attempts.last_failure = now;
+        attempts.blocked_until = if attempts.failures >= LOCKOUT_ATTEMPTS {
+            now + LOCKOUT
+        } else if attempts.failures > FREE_ATTEMPTS {
+            let doublings = attempts.failures - FREE_ATTEMPTS - 1;
+            now + BASE_BACKOFF
+                .saturating_mul(2u32.saturating_pow(doublings))
+                .min(MAX_BACKOFF)
+        } else {
+            now
+        };
+
+        Ok(Attempt {
+            ip,
+            username: username.to_string(),
+            at: now,
+        })
+    }
+
+    /// The password was right, so the attempt isn't a failure and the address starts over
+    pub fn succeeded(&self, attempt: Attempt) {
+        let mut state = self.state.lock().unwrap();
+
+        state.by_ip.remove(&attempt.ip);
+        forget_one(&mut state.global, attempt.at);
+        if let Some(failures) = state.by_username.get_mut(&attempt.username) {
+            forget_one(failures, attempt.at);
+        }
+    }
+}
+
+/// Drops failures that have left the window
+fn forget_old(failures: &mut VecDeque<Instant>, window: Duration, now: Instant) {
+    while failures
+        .front()
+        .is_some_and(|failed_at| now.duration_since(*failed_at) > window)
+    {
+        failures.pop_front();
+    }
+}
+
+/// Takes back the failure counted at `at`
+fn forget_one(failures: &mut VecDeque<Instant>, at: Instant) {
+    if let Some(index) = failures.iter().rposition(|failed_at| *failed_at == at) {
+        failures.remove(index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(last: u8) -> IpAddr {
+        [192, 0, 2, last].into()
+    }
+
+    #[test]
+    fn backs_off_then_locks_out_an_address() {
+        let limiter = LoginLimiter::default();
+        let start = Instant::now();
+
+        for _ in 0..FREE_ATTEMPTS {
+            limiter.try_acquire_at(ip(1), "mom", start).unwrap();
+        }
+        // the fourth failure starts the backoff
+        limiter.try_acquire_at(ip(1), "mom", start).unwrap();
+        assert_eq!(
+            limiter.try_acquire_at(ip(1), "mom", start).unwrap_err(),
+            BASE_BACKOFF
+        );
+        // other addresses aren't affected
+        limiter.try_acquire_at(ip(2), "mom", start).unwrap();
+
+        let mut now = start;
+        for _ in FREE_ATTEMPTS + 1..LOCKOUT_ATTEMPTS {
+            now += MAX_BACKOFF;
+            limiter.try_acquire_at(ip(1), "dad", now).unwrap();
+        }
+        assert_eq!(
+            limiter.try_acquire_at(ip(1), "dad", now).unwrap_err(),
+            LOCKOUT
+        );
+        limiter.try_acquire_at(ip(1), "dad", now + LOCKOUT).unwrap();
+    }
+
+    #[test]
+    fn a_burst_is_counted_before_any_check_finishes() {
+        let limiter = LoginLimiter::default();
+        let now = Instant::now();
+
+        let allowed = (0..20)
+            .filter(|_| limiter.try_acquire_at(ip(1), "mom", now).is_ok())
+            .count();
+        assert_eq!(allowed, FREE_ATTEMPTS as usize + 1);
+    }
+
+    #[test]
+    fn success_clears_the_address_and_takes_back_the_attempt() {
+        let limiter = LoginLimiter::default();
+        let now = Instant::now();
+
+        for _ in 0..=FREE_ATTEMPTS {
+            limiter.try_acquire_at(ip(1), "mom", now).unwrap();
+        }
+        assert!(limiter.try_acquire_at(ip(1), "mom", now).is_err());
+
+        let later = now + BASE_BACKOFF;
+        let attempt = limiter.try_acquire_at(ip(1), "mom", later).unwrap();
+        limiter.succeeded(attempt);
+
+        limiter.try_acquire_at(ip(1), "mom", later).unwrap();
+        let state = limiter.state.lock().unwrap();
+        assert_eq!(state.global.len(), FREE_ATTEMPTS as usize + 2);
+        assert_eq!(state.by_ip[&ip(1)].failures, 1);
+    }
+
+    #[test]
+    fn pauses_everyone_after_too_many_failures_overall() {
+        let limiter = LoginLimiter::default();
+        let now = Instant::now();
+
+        // spread out so no one address or username is held back
+        for n in 0..GLOBAL_MAX_FAILURES {
+            let address = IpAddr::from([10, 0, (n / 256) as u8, (n % 256) as u8]);
+            limiter
+                .try_acquire_at(address, &format!("user{n}"), now)
+                .unwrap();
+        }
+
+        assert_eq!(
+            limiter.try_acquire_at(ip(1), "mom", now).unwrap_err(),
+            GLOBAL_WINDOW
+        );
+        let later = now + Duration::from_secs(60);
+        assert_eq!(
+            limiter.try_acquire_at(ip(1), "mom", later).unwrap_err(),
+            GLOBAL_WINDOW - Duration::from_secs(60)
+        );
+        // the failures leave the window
+        limiter
+            .try_acquire_at(ip(1), "mom", now + GLOBAL_WINDOW + Duration::from_secs(1))
+            .unwrap();
+    }
+
+    #[test]
+    fn slows_a_username_down_without_locking_it() {
+        let limiter = LoginLimiter::default();
+        let start = Instant::now();
+
+        // one failure from each of many addresses
+        for n in 0..USERNAME_FREE_FAILURES {
+            limiter.try_acquire_at(ip(n as u8), "mom", start).unwrap();
+        }
+        assert_eq!(
+            limiter.try_acquire_at(ip(200), "mom", start).unwrap_err(),
+            USERNAME_SPACING
+        );
+        limiter.try_acquire_at(ip(200), "dad", start).unwrap();
+
+        // still a try every so often, from anywhere
+        let later = start + USERNAME_SPACING;
+        limiter.try_acquire_at(ip(201), "mom", later).unwrap();
+        assert_eq!(
+            limiter.try_acquire_at(ip(202), "mom", later).unwrap_err(),
+            USERNAME_SPACING
+        );
+        limiter
+            .try_acquire_at(ip(202), "mom", later + USERNAME_SPACING)
+            .unwrap();
+    }
+
+    #[test]
+    fn failures_leave_the_username_window() {
+        let limiter = LoginLimiter::default();
+        let start = Instant::now();
+
+        for n in 0..USERNAME_FREE_FAILURES {
+            limiter.try_acquire_at(ip(n as u8), "mom", start).unwrap();
+        }
+        assert!(limiter.try_acquire_at(ip(200), "mom", start).is_err());
+
+        let later = start + USERNAME_WINDOW + Duration::from_secs(1);
+        limiter.try_acquire_at(ip(200), "mom", later).unwrap();
+        assert_eq!(limiter.state.lock().unwrap().by_username["mom"].len(), 1);
+    }
+}
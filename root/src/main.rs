@@ -1,55 +1,330 @@
+mod away;
+mod credentials;
+mod cron;
+mod devices;
+mod light_state;
+mod lights;
+mod limiter;
+mod scenes;
+mod scheduler;
+mod sun;
+
+use auth::AppError;
 use axum::{
     Json, Router,
-    extract::State,
+    extract::{ConnectInfo, Path, Query, State},
     http::{StatusCode, Uri, header},
     response::{IntoResponse, Response},
     routing::{get, post},
 };
-use reqwest::header::{HeaderMap, HeaderValue};
+use rand::Rng;
+use reqwest::header::HeaderMap;
 
-use axum_extra::extract::{CookieJar, cookie::Cookie};
-use chrono::Utc;
+use axum_extra::extract::{
+    CookieJar,
+    cookie::{Cookie, SameSite},
+};
+use chrono::{Local, Utc};
+use credentials::Credentials;
+use devices::{Backend, Device};
 use dotenvy::dotenv;
+use light_state::{LightState, LightStateCache};
+use lights::{Backends, LightItem, LightResult};
+use limiter::LoginLimiter;
+use log::{error, info, warn};
 use rust_embed::Embed;
-use serde::Deserialize;
+use scenes::Scene;
+use scheduler::{Action, LogEntry, Rule, Trigger};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::{env, fs, net::SocketAddr};
+use sqlx::{Pool, Sqlite, SqlitePool, sqlite::SqliteConnectOptions};
+use std::{
+    collections::HashSet,
+    env,
+    io::BufRead,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
 use time::OffsetDateTime;
+use tokio::signal::unix::{SignalKind, signal};
 
-const COOKIE_NAME: &str = "beebfam-key";
 const ONE_YEAR_IN_SECONDS: i64 = 31_556_952;
 
+/// Checked against when the username doesn't exist so both paths cost a bcrypt verify
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| {
+    bcrypt::hash("not a real password", credentials::bcrypt_cost())
+        .expect("BCRYPT_COST is checked at startup")
+});
+
 #[derive(Clone, Debug)]
 struct AppState {
-    pub key: String,
+    pub pool: Pool<Sqlite>,
+    pub auth: auth::Auth,
+    pub credentials: Arc<Credentials>,
+    pub limiter: Arc<LoginLimiter>,
+    /// the reverse proxy whose forwarded client address is believed
+    pub trusted_proxy: Option<IpAddr>,
+    pub location: Option<sun::Location>,
+    pub backends: Backends,
+    pub light_states: Arc<LightStateCache>,
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    env_logger::init();
     _ = dotenv();
+    credentials::load_bcrypt_cost()?;
+    let raw_database_url = env::var("DATABASE_URL").expect("DATABASE_URL to be defined");
+    let database_url = raw_database_url.split(":").last().unwrap();
+
+    let connection_options = SqliteConnectOptions::new()
+        .filename(database_url)
+        .create_if_missing(true);
+    let pool = SqlitePool::connect_with(connection_options).await?;
+
+    sqlx::migrate!("./migrations").run(&pool).await?;
+
+    // admin commands run against the database and exit instead of serving
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        return run_command(&pool, &args).await;
+    }
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 8080));
     let listener = tokio::net::TcpListener::bind(addr).await?;
     let addr = listener.local_addr()?;
 
-    let key_path = std::env::var("KEY_PATH")?;
-    let key = fs::read_to_string(key_path)?.trim().to_string();
+    let auth = auth::Auth::new(pool.clone(), "root");
+    let credentials = Arc::new(Credentials::load(&pool).await?);
+    let limiter = Arc::new(LoginLimiter::default());
+    let trusted_proxy = match env::var("TRUSTED_PROXY") {
+        Ok(proxy) => Some(
+            proxy
+                .trim()
+                .parse::<IpAddr>()
+                .map_err(|err| anyhow::anyhow!("TRUSTED_PROXY isn't an address: {err}"))?,
+        ),
+        Err(_) => None,
+    };
+    let location = sun::Location::from_env()?;
+
+    let cloud = match govee::Client::from_env() {
+        Ok(client) => Some(client),
+        Err(err) => {
+            warn!("Cloud lights won't work: {err}");
+            None
+        }
+    };
+    let lan = match govee::LanClient::from_env().await {
+        Ok(client) => Some(client),
+        Err(err) => {
+            warn!("LAN lights won't work: {err}");
+            None
+        }
+    };
+    let backends = Backends { cloud, lan };
+
+    let light_states = Arc::new(LightStateCache::default());
 
-    let app = Router::new()
+    tokio::spawn(scheduler::run(
+        pool.clone(),
+        backends.clone(),
+        light_states.clone(),
+        location,
+    ));
+
+    tokio::spawn(away::run(
+        pool.clone(),
+        backends.clone(),
+        light_states.clone(),
+    ));
+
+    // `kill -HUP` picks up users added or changed from the command line
+    let mut hangups = signal(SignalKind::hangup())?;
+    let reload_pool = pool.clone();
+    let reload_credentials = credentials.clone();
+    tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            match reload_credentials.reload(&reload_pool).await {
+                Ok(()) => info!("Reloaded credentials"),
+                Err(err) => error!("Failed to reload credentials: {err:?}"),
+            }
+        }
+    });
+
+    let app = router(AppState {
+        pool,
+        auth,
+        credentials,
+        limiter,
+        trusted_proxy,
+        location,
+        backends,
+        light_states,
+    });
+
+    println!("listening on {addr}");
+    _ = axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await;
+    Ok(())
+}
+
+/// Every route, behind the auth layer
+fn router(state: AppState) -> Router {
+    Router::new()
         .route("/", get(index_handler))
         .route("/index.html", get(index_handler))
         .route("/assets/{*file}", get(static_handler))
         .route("/login", post(login_handler))
+        .route("/logout", post(logout_handler))
+        .route("/change-password", post(change_password_handler))
+        .route("/get-current-user", get(get_current_user_handler))
+        .route("/get-sessions", get(get_sessions_handler))
+        .route("/sessions/{id}/revoke", post(revoke_session_handler))
+        .route("/get-users", get(get_users_handler))
+        .route("/users/{id}/role", post(set_role_handler))
+        .route("/get-tokens", get(get_tokens_handler))
+        .route("/tokens", post(create_token_handler))
+        .route("/tokens/{id}/revoke", post(revoke_token_handler))
+        .route("/get-devices", get(get_devices_handler))
+        .route("/devices", post(add_device_handler))
+        .route("/devices/sync", post(sync_devices_handler))
+        .route("/devices/{id}/rename", post(rename_device_handler))
+        .route("/devices/{id}/backend", post(set_device_backend_handler))
+        .route("/devices/{id}/remove", post(remove_device_handler))
         .route("/light-control", post(light_control_handler))
-        .with_state(AppState { key });
+        .route("/light-state", get(light_state_handler))
+        .route("/get-scenes", get(get_scenes_handler))
+        .route("/scenes", post(create_scene_handler))
+        .route("/scenes/{id}/update", post(update_scene_handler))
+        .route("/scenes/{id}/remove", post(remove_scene_handler))
+        .route("/scene/{name}/apply", post(apply_scene_handler))
+        .route("/get-schedules", get(get_schedules_handler))
+        .route("/schedules", post(create_schedule_handler))
+        .route("/schedules/{id}/update", post(update_schedule_handler))
+        .route("/schedules/{id}/remove", post(remove_schedule_handler))
+        .route("/schedules/{id}/next-firings", get(next_firings_handler))
+        .route("/get-schedule-log", get(get_schedule_log_handler))
+        .route("/get-away", get(get_away_handler))
+        .route("/away", post(update_away_handler))
+        .route("/away/rooms", post(create_away_room_handler))
+        .route("/away/rooms/{id}/update", post(update_away_room_handler))
+        .route("/away/rooms/{id}/remove", post(remove_away_room_handler))
+        .route("/get-away-log", get(get_away_log_handler))
+        .route("/get-audit", get(get_audit_handler))
+        .layer(
+            auth::AuthLayer::new(state.auth.clone())
+                .trust_domain("beebfam.org")
+                .allow("/")
+                .allow("/index.html")
+                .allow("/assets/*")
+                .allow("/login")
+                .allow("/logout"),
+        )
+        .with_state(state)
+}
 
-    println!("listening on {addr}");
-    _ = axum::serve(listener, app).await;
-    Ok(())
+async fn run_command(pool: &Pool<Sqlite>, args: &[String]) -> anyhow::Result<()> {
+    match args {
+        [command, username, role @ ..] if command == "add-user" && role.len() <= 1 => {
+            let role: auth::Role = role
+                .first()
+                .map_or(Ok(auth::Role::Adult), |role| role.parse())?;
+
+            let password = read_password(username)?;
+            let password_hash = bcrypt::hash(password, credentials::bcrypt_cost())?;
+            let now = Utc::now().timestamp();
+
+            sqlx::query!(
+                r"
+                INSERT INTO users (username, password_hash, role, created_at) VALUES (?1, ?2, ?3, ?4)
+                ",
+                username,
+                password_hash,
+                role,
+                now
+            )
+            .execute(pool)
+            .await?;
+
+            println!("Added {username} as {role:?}");
+            Ok(())
+        }
+        [command, username] if command == "set-password" => {
+            let user_id = sqlx::query_scalar!(
+                r"
+                SELECT id FROM users WHERE username = ?1
+                ",
+                username
+            )
+            .fetch_optional(pool)
+            .await?;
+            let user_count = sqlx::query_scalar!(
+                r"
+                SELECT COUNT(*) FROM users
+                "
+            )
+            .fetch_one(pool)
+            .await?;
+
+            let password = read_password(username)?;
+
+            match user_id {
+                Some(user_id) => {
+                    credentials::set_password(pool, user_id, &password).await?;
+                    // a reset usually means the old password got out
+                    sqlx::query!(
+                        r"
+                        DELETE FROM sessions WHERE user_id = ?1
+                        ",
+                        user_id
+                    )
+                    .execute(pool)
+                    .await?;
+                    println!("Reset {username} and logged them out everywhere");
+                }
+                // bootstrapping a fresh database, the first person gets to run it
+                None if user_count == 0 => {
+                    let password_hash = bcrypt::hash(password, credentials::bcrypt_cost())?;
+                    let now = Utc::now().timestamp();
+                    sqlx::query!(
+                        r"
+                        INSERT INTO users (username, password_hash, role, created_at) VALUES (?1, ?2, ?3, ?4)
+                        ",
+                        username,
+                        password_hash,
+                        auth::Role::Admin,
+                        now
+                    )
+                    .execute(pool)
+                    .await?;
+                    println!("Added {username} as Admin");
+                }
+                None => return Err(anyhow::anyhow!("No user named {username}, use add-user")),
+            }
+            Ok(())
+        }
+        _ => Err(anyhow::anyhow!(
+            "Usage: root add-user <username> [guest|kid|adult|admin]\n       root set-password <username>"
+        )),
+    }
+}
+
+fn read_password(username: &str) -> anyhow::Result<String> {
+    println!("Password for {username}:");
+    let mut password = String::new();
+    std::io::stdin().lock().read_line(&mut password)?;
+    // only the newline, spaces are part of the password like they are when logging in
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
 }
 
 #[derive(Deserialize)]
 struct LoginRequest {
+    username: String,
     password: String,
 }
 
@@ -64,147 +339,1474 @@ async fn static_handler(uri: Uri) -> impl IntoResponse {
 }
 
 async fn login_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
     cookies: CookieJar,
+    headers: HeaderMap,
     Json(req): Json<LoginRequest>,
-) -> Result<CookieJar, AppError> {
-    let pw_path = std::env::var("PASSWORD_PATH")?;
-    let pw = fs::read_to_string(pw_path)?;
-    let key_path = std::env::var("KEY_PATH")?;
-    let key = fs::read_to_string(key_path)?;
-
-    if cookies
-        .get(COOKIE_NAME)
-        .is_some_and(|val| val.value() == key.trim())
+) -> Result<CookieJar, LoginError> {
+    if let Some(cookie) = cookies.get(auth::SESSION_COOKIE)
+        && state
+            .auth
+            .session_user(cookie.value().trim())
+            .await?
+            .is_some()
     {
         return Ok(cookies);
     }
 
-    let result = bcrypt::verify(req.password, pw.trim());
+    // the page hasn't had anything typed in yet, which isn't a guess worth counting
+    if req.username.trim().is_empty() || req.password.is_empty() {
+        return Err(AppError::Unauthorized.into());
+    }
 
-    let updated_cookies = if result.is_ok_and(|is_true| is_true) {
-        cookies.add(
-            Cookie::build((COOKIE_NAME, key.trim().to_string()))
-                .path("/")
-                .expires(
-                    OffsetDateTime::from_unix_timestamp(
-                        Utc::now().timestamp() + ONE_YEAR_IN_SECONDS,
-                    )
-                    .unwrap(),
-                )
-                .domain(".beebfam.org"),
+    let ip = client_ip(&headers, addr, state.trusted_proxy);
+    let attempt = match state.limiter.try_acquire(ip, &req.username) {
+        Ok(attempt) => attempt,
+        Err(wait) => {
+            warn!("Throttled login for {} from {ip}", req.username);
+            return Err(LoginError::TooManyRequests(wait));
+        }
+    };
+
+    let credential = state.credentials.get(&req.username);
+    let password_hash = credential
+        .as_ref()
+        .map_or(DUMMY_HASH.to_string(), |credential| {
+            credential.password_hash.clone()
+        });
+    let verified = verify_password(req.password.clone(), password_hash).await?;
+
+    let Some(user) = credential.filter(|_| verified) else {
+        warn!("Failed login for {} from {ip}", req.username);
+
+        let ip = ip.to_string();
+        let now = Utc::now().timestamp();
+        sqlx::query!(
+            r"
+            INSERT INTO failed_logins (username, ip, attempted_at) VALUES (?1, ?2, ?3)
+            ",
+            req.username,
+            ip,
+            now
         )
-    } else {
-        return Err(AppError(anyhow::anyhow!("Nope, sorry")));
+        .execute(&state.pool)
+        .await?;
+
+        return Err(AppError::Unauthorized.into());
     };
+    state.limiter.succeeded(attempt);
+
+    // the password is only ever in hand here, so this is where old hashes get upgraded
+    if credentials::needs_rehash(&user.password_hash) {
+        credentials::set_password(&state.pool, user.id, &req.password).await?;
+        state.credentials.reload(&state.pool).await?;
+        info!("Rehashed password for {}", user.username);
+    }
+
+    // lets people tell their phone apart from their laptop when revoking
+    let label = headers
+        .get(header::USER_AGENT)
+        .and_then(|val| val.to_str().ok())
+        .map(str::to_string);
+    let token = generate_token();
+    let token_hash = auth::hash_token(&token);
+    let now = Utc::now().timestamp();
+    let expires_at = now + ONE_YEAR_IN_SECONDS;
+
+    sqlx::query!(
+        r"
+        INSERT INTO sessions (token_hash, user_id, label, created_at, expires_at) VALUES (?1, ?2, ?3, ?4, ?5)
+        ",
+        token_hash,
+        user.id,
+        label,
+        now,
+        expires_at
+    )
+    .execute(&state.pool)
+    .await?;
 
-    Ok(updated_cookies)
+    Ok(cookies.add(
+        Cookie::build((auth::SESSION_COOKIE, token))
+            .path("/")
+            .expires(OffsetDateTime::from_unix_timestamp(expires_at).unwrap())
+            .domain(".beebfam.org")
+            .secure(true)
+            .http_only(true)
+            // every beebfam subdomain is the same site, so Lax only keeps other sites out
+            .same_site(SameSite::Lax),
+    ))
+}
+
+/// bcrypt takes a while on purpose, so it runs off the async threads
+async fn verify_password(password: String, hash: String) -> anyhow::Result<bool> {
+    let verified = tokio::task::spawn_blocking(move || bcrypt::verify(password, &hash)).await?;
+    Ok(verified.is_ok_and(|is_true| is_true))
+}
+
+async fn logout_handler(
+    State(state): State<AppState>,
+    cookies: CookieJar,
+) -> Result<CookieJar, AppError> {
+    if let Some(cookie) = cookies.get(auth::SESSION_COOKIE) {
+        let token_hash = auth::hash_token(cookie.value());
+        sqlx::query!(
+            r"
+            DELETE FROM sessions WHERE token_hash = ?1
+            ",
+            token_hash
+        )
+        .execute(&state.pool)
+        .await?;
+    }
+
+    Ok(cookies.remove(
+        Cookie::build(auth::SESSION_COOKIE)
+            .path("/")
+            .domain(".beebfam.org")
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Lax),
+    ))
 }
 
 #[derive(Deserialize)]
-struct LightRequest {
-    requests: Vec<LightItem>,
+struct ChangePasswordRequest {
+    old_password: String,
+    new_password: String,
+}
+
+/// Guesses at the old password are throttled like logins, a stolen session shouldn't be a way
+/// to find out the password. Passwords are kept exactly as typed.
+#[auth_macro::auth_guard(role = "guest")]
+async fn change_password_handler(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    Json(req): Json<ChangePasswordRequest>,
+) -> Result<Response, AppError> {
+    let credential = state
+        .credentials
+        .get(&current_user.username)
+        .ok_or(AppError::Unauthorized)?;
+
+    let ip = client_ip(&headers, addr, state.trusted_proxy);
+    let attempt = match state.limiter.try_acquire(ip, &current_user.username) {
+        Ok(attempt) => attempt,
+        Err(wait) => {
+            warn!(
+                "Throttled password change for {} from {ip}",
+                current_user.username
+            );
+            return Ok(LoginError::TooManyRequests(wait).into_response());
+        }
+    };
+    if !verify_password(req.old_password, credential.password_hash).await? {
+        warn!("Wrong old password for {} from {ip}", current_user.username);
+        return Err(AppError::Forbidden);
+    }
+    state.limiter.succeeded(attempt);
+
+    if req.new_password.len() < 8 {
+        return Err(AppError::BadRequest(
+            "New password needs at least 8 characters".to_string(),
+        ));
+    }
+
+    credentials::set_password(&state.pool, current_user.id, &req.new_password).await?;
+    state.credentials.reload(&state.pool).await?;
+
+    // anyone riding on the old password gets logged out, this device stays in
+    let token_hash = cookies
+        .get(auth::SESSION_COOKIE)
+        .map(|cookie| auth::hash_token(cookie.value()));
+    let logged_out = sqlx::query!(
+        r"
+        DELETE FROM sessions WHERE user_id = ?1 AND token_hash IS NOT ?2
+        ",
+        current_user.id,
+        token_hash
+    )
+    .execute(&state.pool)
+    .await?
+    .rows_affected();
+
+    // never the password or its hash, only that it changed
+    state
+        .auth
+        .audit(
+            &current_user,
+            "change-password",
+            json!(null),
+            json!({ "username": current_user.username, "logged_out_sessions": logged_out }),
+        )
+        .await?;
+
+    Ok(().into_response())
+}
+
+#[derive(Serialize, Debug)]
+struct CurrentUserResponse {
+    user: auth::User,
+}
+
+/// Lets the page ask whether it's logged in without trying to log in
+#[auth_macro::auth_guard(role = "guest")]
+async fn get_current_user_handler(
+    State(state): State<AppState>,
+) -> Result<Json<CurrentUserResponse>, AppError> {
+    Ok(Json(CurrentUserResponse { user: current_user }))
+}
+
+#[derive(sqlx::FromRow, Debug, Serialize)]
+struct Session {
+    id: i64,
+    label: Option<String>,
+    created_at: i64,
+    expires_at: i64,
+    current: bool,
+}
+
+#[derive(Serialize, Debug)]
+struct SessionsResponse {
+    sessions: Vec<Session>,
+}
+
+#[auth_macro::auth_guard(role = "guest")]
+async fn get_sessions_handler(
+    State(state): State<AppState>,
+) -> Result<Json<SessionsResponse>, AppError> {
+    let token_hash = cookies
+        .get(auth::SESSION_COOKIE)
+        .map(|cookie| auth::hash_token(cookie.value()));
+    let sessions = get_sessions(&state.pool, current_user.id, token_hash.as_deref()).await?;
+    Ok(Json(SessionsResponse { sessions }))
+}
+
+#[auth_macro::auth_guard(role = "guest")]
+async fn revoke_session_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<SessionsResponse>, AppError> {
+    let token_hash = cookies
+        .get(auth::SESSION_COOKIE)
+        .map(|cookie| auth::hash_token(cookie.value()));
+
+    // scoped to the caller so nobody can log out someone else's devices
+    let session = get_sessions(&state.pool, current_user.id, token_hash.as_deref())
+        .await?
+        .into_iter()
+        .find(|session| session.id == id)
+        .ok_or_else(|| AppError::NotFound(format!("No session {id}")))?;
+
+    sqlx::query!(
+        r"
+        DELETE FROM sessions WHERE id = ?1 AND user_id = ?2
+        ",
+        id,
+        current_user.id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    state
+        .auth
+        .audit(
+            &current_user,
+            "revoke-session",
+            json!({ "username": current_user.username, "session": session }),
+            json!(null),
+        )
+        .await?;
+
+    let sessions = get_sessions(&state.pool, current_user.id, token_hash.as_deref()).await?;
+    Ok(Json(SessionsResponse { sessions }))
+}
+
+async fn get_sessions(
+    pool: &Pool<Sqlite>,
+    user_id: i64,
+    current_token_hash: Option<&str>,
+) -> anyhow::Result<Vec<Session>> {
+    let sessions = sqlx::query_as!(
+        Session,
+        r#"
+        SELECT id, label, created_at, expires_at, token_hash = ?2 AS "current!: bool" FROM sessions
+        WHERE user_id = ?1
+        ORDER BY created_at DESC
+        "#,
+        user_id,
+        current_token_hash
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(sessions)
+}
+
+#[derive(sqlx::FromRow, Debug, Serialize)]
+struct UserItem {
+    id: i64,
+    username: String,
+    role: auth::Role,
+    created_at: i64,
+}
+
+#[derive(Serialize, Debug)]
+struct UsersResponse {
+    users: Vec<UserItem>,
+}
+
+#[derive(Deserialize)]
+struct SetRoleRequest {
+    role: auth::Role,
+}
+
+#[auth_macro::auth_guard(role = "admin")]
+async fn get_users_handler(State(state): State<AppState>) -> Result<Json<UsersResponse>, AppError> {
+    let users = get_users(&state.pool).await?;
+    Ok(Json(UsersResponse { users }))
+}
+
+#[auth_macro::auth_guard(role = "admin")]
+async fn set_role_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<SetRoleRequest>,
+) -> Result<Json<UsersResponse>, AppError> {
+    // keeps the house from ending up without an admin by accident
+    if id == current_user.id {
+        return Err(AppError::BadRequest(
+            "Can't change your own role".to_string(),
+        ));
+    }
+
+    let users = get_users(&state.pool).await?;
+    let before = users
+        .iter()
+        .find(|user| user.id == id)
+        .ok_or_else(|| AppError::NotFound(format!("No user {id}")))?;
+
+    sqlx::query!(
+        r"
+        UPDATE users SET role = ?1 WHERE id = ?2
+        ",
+        req.role,
+        id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    let mut after = json!(before);
+    after["role"] = json!(req.role);
+    state
+        .auth
+        .audit(&current_user, "set-role", json!(before), after)
+        .await?;
+
+    let users = get_users(&state.pool).await?;
+    Ok(Json(UsersResponse { users }))
+}
+
+async fn get_users(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<UserItem>> {
+    let users = sqlx::query_as!(
+        UserItem,
+        r#"
+        SELECT id, username, role AS "role: auth::Role", created_at FROM users
+        ORDER BY username
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(users)
 }
 
 #[derive(Deserialize)]
-struct LightItem {
+struct CreateTokenRequest {
     name: String,
-    toggle: bool,
+    /// services the token works against, every service when left out
+    scopes: Option<Vec<String>>,
+    /// stops working after this many days, never when left out
+    expires_in_days: Option<i64>,
 }
 
+#[derive(Serialize, Debug)]
+struct CreateTokenResponse {
+    id: i64,
+    name: String,
+    token: String,
+}
+
+#[derive(sqlx::FromRow, Debug, Serialize)]
+struct ApiToken {
+    id: i64,
+    name: String,
+    scopes: Option<String>,
+    created_at: i64,
+    last_used_at: Option<i64>,
+    expires_at: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+struct TokensResponse {
+    tokens: Vec<ApiToken>,
+}
+
+#[auth_macro::auth_guard]
+async fn get_tokens_handler(
+    State(state): State<AppState>,
+) -> Result<Json<TokensResponse>, AppError> {
+    let tokens = get_tokens(&state.pool, current_user.id).await?;
+    Ok(Json(TokensResponse { tokens }))
+}
+
+/// The plaintext token is only ever returned here, the database keeps a hash
 #[auth_macro::auth_guard]
+async fn create_token_handler(
+    State(state): State<AppState>,
+    Json(req): Json<CreateTokenRequest>,
+) -> Result<Json<CreateTokenResponse>, AppError> {
+    let name = req.name.trim().to_string();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("Token needs a name".to_string()));
+    }
+
+    let scopes = match req.scopes {
+        Some(scopes) if !scopes.is_empty() => {
+            if scopes
+                .iter()
+                .any(|scope| scope.trim().is_empty() || scope.contains(','))
+            {
+                return Err(AppError::BadRequest(
+                    "Scopes must be service names".to_string(),
+                ));
+            }
+            Some(
+                scopes
+                    .iter()
+                    .map(|scope| scope.trim())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            )
+        }
+        _ => None,
+    };
+
+    let now = Utc::now().timestamp();
+    let expires_at = match req.expires_in_days {
+        Some(days) if !(1..=3650).contains(&days) => {
+            return Err(AppError::BadRequest(
+                "Tokens last 1 to 3650 days".to_string(),
+            ));
+        }
+        Some(days) => Some(now + days * 24 * 60 * 60),
+        None => None,
+    };
+
+    let token = format!("beeb_{}", generate_token());
+    let token_hash = auth::hash_token(&token);
+
+    let id = sqlx::query!(
+        r"
+        INSERT INTO api_tokens (user_id, name, token_hash, scopes, created_at, expires_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ",
+        current_user.id,
+        name,
+        token_hash,
+        scopes,
+        now,
+        expires_at
+    )
+    .execute(&state.pool)
+    .await?
+    .last_insert_rowid();
+
+    // the token itself stays out of the log like it stays out of the database
+    state
+        .auth
+        .audit(
+            &current_user,
+            "create-token",
+            json!(null),
+            json!({
+                "username": current_user.username,
+                "id": id,
+                "name": name,
+                "scopes": scopes,
+                "expires_at": expires_at,
+            }),
+        )
+        .await?;
+
+    Ok(Json(CreateTokenResponse { id, name, token }))
+}
+
+#[auth_macro::auth_guard]
+async fn revoke_token_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<TokensResponse>, AppError> {
+    let token = get_tokens(&state.pool, current_user.id)
+        .await?
+        .into_iter()
+        .find(|token| token.id == id)
+        .ok_or_else(|| AppError::NotFound(format!("No token {id}")))?;
+
+    sqlx::query!(
+        r"
+        DELETE FROM api_tokens WHERE id = ?1 AND user_id = ?2
+        ",
+        id,
+        current_user.id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    state
+        .auth
+        .audit(
+            &current_user,
+            "revoke-token",
+            json!({ "username": current_user.username, "token": token }),
+            json!(null),
+        )
+        .await?;
+
+    let tokens = get_tokens(&state.pool, current_user.id).await?;
+    Ok(Json(TokensResponse { tokens }))
+}
+
+async fn get_tokens(pool: &Pool<Sqlite>, user_id: i64) -> anyhow::Result<Vec<ApiToken>> {
+    let tokens = sqlx::query_as!(
+        ApiToken,
+        r"
+        SELECT id, name, scopes, created_at, last_used_at, expires_at FROM api_tokens
+        WHERE user_id = ?1
+        ORDER BY created_at DESC
+        ",
+        user_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(tokens)
+}
+
+/// The address behind the reverse proxy, which sets these headers for every request. Anyone else
+/// could make them up, so they only count on requests coming from `trusted_proxy`.
+fn client_ip(headers: &HeaderMap, addr: SocketAddr, trusted_proxy: Option<IpAddr>) -> IpAddr {
+    if trusted_proxy != Some(addr.ip()) {
+        return addr.ip();
+    }
+
+    let forwarded = headers
+        .get("x-real-ip")
+        .or_else(|| headers.get("x-forwarded-for"))
+        .and_then(|val| val.to_str().ok())
+        .and_then(|val| val.rsplit(',').next())
+        .and_then(|val| val.trim().parse().ok());
+
+    forwarded.unwrap_or(addr.ip())
+}
+
+fn generate_token() -> String {
+    let bytes: [u8; 32] = rand::rng().random();
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[derive(Serialize, Debug)]
+struct DevicesResponse {
+    devices: Vec<Device>,
+}
+
+#[derive(Deserialize)]
+struct AddDeviceRequest {
+    name: String,
+    device: String,
+    sku: String,
+    #[serde(default)]
+    backend: Backend,
+}
+
+#[derive(Deserialize)]
+struct RenameDeviceRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct DeviceBackendRequest {
+    backend: Backend,
+}
+
+#[auth_macro::auth_guard]
+async fn get_devices_handler(
+    State(state): State<AppState>,
+) -> Result<Json<DevicesResponse>, AppError> {
+    let devices = devices::get_devices(&state.pool).await?;
+    Ok(Json(DevicesResponse { devices }))
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn add_device_handler(
+    State(state): State<AppState>,
+    Json(req): Json<AddDeviceRequest>,
+) -> Result<Json<DevicesResponse>, AppError> {
+    let name = req.name.trim();
+    let device = req.device.trim();
+    let sku = req.sku.trim();
+    if name.is_empty() || device.is_empty() || sku.is_empty() {
+        return Err(AppError::BadRequest(
+            "Devices need a name, device id and SKU".to_string(),
+        ));
+    }
+
+    let added = devices::add_device(&state.pool, name, device, sku, req.backend).await?;
+    state
+        .auth
+        .audit(&current_user, "add-device", json!(null), json!(added))
+        .await?;
+
+    let devices = devices::get_devices(&state.pool).await?;
+    Ok(Json(DevicesResponse { devices }))
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn rename_device_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<RenameDeviceRequest>,
+) -> Result<Json<DevicesResponse>, AppError> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("Device needs a name".to_string()));
+    }
+
+    let device = devices::get_device(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No device {id}")))?;
+
+    sqlx::query!(
+        r"
+        UPDATE devices SET name = ?1 WHERE id = ?2
+        ",
+        name,
+        id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    let renamed = Device {
+        name: name.to_string(),
+        ..device.clone()
+    };
+    state
+        .auth
+        .audit(
+            &current_user,
+            "rename-device",
+            json!(device),
+            json!(renamed),
+        )
+        .await?;
+
+    let devices = devices::get_devices(&state.pool).await?;
+    Ok(Json(DevicesResponse { devices }))
+}
+
+/// Switches a device between Govee's cloud and the local network
+#[auth_macro::auth_guard(role = "adult")]
+async fn set_device_backend_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<DeviceBackendRequest>,
+) -> Result<Json<DevicesResponse>, AppError> {
+    let device = devices::get_device(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No device {id}")))?;
+
+    sqlx::query!(
+        r"
+        UPDATE devices SET backend = ?1 WHERE id = ?2
+        ",
+        req.backend,
+        id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    let updated = Device {
+        backend: req.backend,
+        ..device.clone()
+    };
+    state
+        .auth
+        .audit(
+            &current_user,
+            "set-device-backend",
+            json!(device),
+            json!(updated),
+        )
+        .await?;
+
+    let devices = devices::get_devices(&state.pool).await?;
+    Ok(Json(DevicesResponse { devices }))
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn remove_device_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<DevicesResponse>, AppError> {
+    let device = devices::get_device(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No device {id}")))?;
+
+    sqlx::query!(
+        r"
+        DELETE FROM devices WHERE id = ?1
+        ",
+        id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    state
+        .auth
+        .audit(&current_user, "remove-device", json!(device), json!(null))
+        .await?;
+
+    let devices = devices::get_devices(&state.pool).await?;
+    Ok(Json(DevicesResponse { devices }))
+}
+
+/// Adds whatever is on the Govee account that the registry doesn't have yet
+#[auth_macro::auth_guard(role = "adult")]
+async fn sync_devices_handler(
+    State(state): State<AppState>,
+) -> Result<Json<DevicesResponse>, AppError> {
+    let before = devices::get_devices(&state.pool).await?;
+    let added = devices::sync_from_govee(&state.pool, state.backends.cloud()?).await?;
+    info!("Synced {added} new devices from Govee");
+
+    let devices = devices::get_devices(&state.pool).await?;
+    state
+        .auth
+        .audit(&current_user, "sync-devices", json!(before), json!(devices))
+        .await?;
+
+    Ok(Json(DevicesResponse { devices }))
+}
+
+#[derive(Deserialize, Serialize)]
+struct LightRequest {
+    requests: Vec<LightItem>,
+}
+
+/// One result per requested light, in the order they were asked for
+#[derive(Serialize, Debug)]
+struct LightResponse {
+    results: Vec<LightResult>,
+}
+
+#[auth_macro::auth_guard(role = "adult")]
 async fn light_control_handler(
     State(state): State<AppState>,
     Json(req): Json<LightRequest>,
-) -> Result<(), AppError> {
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        "Govee-API-Key",
-        HeaderValue::from_str(&env::var("GOVEE_KEY").unwrap()).unwrap(),
-    );
-
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        .build()
-        .unwrap();
+) -> Result<Json<LightResponse>, AppError> {
+    let plans = lights::plan(&state.pool, &req.requests)
+        .await
+        .map_err(light_error)?;
 
-    let mut futs = vec![];
-
-    for req in req.requests {
-        if let Some(device) = get_device(&req.name) {
-            let future = client
-                .post("https://openapi.api.govee.com/router/api/v1/device/control")
-                .json(&json!({
-                    "requestId": "uuid",
-                    "payload": {
-                        "sku": device.sku,
-                        "device": device.device,
-                        "capability": {
-                            "type": "devices.capabilities.on_off",
-                            "instance": "powerSwitch",
-                            "value": req.toggle as u32
-                       }
-                    }
-                }))
-                .send();
-            futs.push(future);
-        }
+    state
+        .auth
+        .audit(&current_user, "light-control", json!(null), json!(req))
+        .await?;
+
+    let results = lights::send(&state.backends, &plans).await;
+    state.light_states.record(&plans, &results);
+    Ok(Json(LightResponse { results }))
+}
+
+#[derive(Serialize, Debug)]
+struct LightStateResponse {
+    lights: Vec<LightState>,
+}
+
+/// What every registered light is doing, cached for a short while
+#[auth_macro::auth_guard]
+async fn light_state_handler(
+    State(state): State<AppState>,
+) -> Result<Json<LightStateResponse>, AppError> {
+    let devices = devices::get_devices(&state.pool).await?;
+    let lights = state.light_states.states(&state.backends, &devices).await;
+    Ok(Json(LightStateResponse { lights }))
+}
+
+#[derive(Serialize, Debug)]
+struct ScenesResponse {
+    scenes: Vec<Scene>,
+}
+
+#[derive(Deserialize)]
+struct SceneRequest {
+    name: String,
+    lights: Vec<LightItem>,
+}
+
+#[auth_macro::auth_guard]
+async fn get_scenes_handler(
+    State(state): State<AppState>,
+) -> Result<Json<ScenesResponse>, AppError> {
+    let scenes = scenes::get_scenes(&state.pool).await?;
+    Ok(Json(ScenesResponse { scenes }))
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn create_scene_handler(
+    State(state): State<AppState>,
+    Json(req): Json<SceneRequest>,
+) -> Result<Json<ScenesResponse>, AppError> {
+    let name = check_scene(&state.pool, &req).await?;
+    let id = scenes::save_scene(&state.pool, None, &name, &req.lights).await?;
+
+    let created = scenes::get_scene_by_id(&state.pool, id).await?;
+    state
+        .auth
+        .audit(&current_user, "create-scene", json!(null), json!(created))
+        .await?;
+
+    let scenes = scenes::get_scenes(&state.pool).await?;
+    Ok(Json(ScenesResponse { scenes }))
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn update_scene_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<SceneRequest>,
+) -> Result<Json<ScenesResponse>, AppError> {
+    let scene = scenes::get_scene_by_id(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No scene {id}")))?;
+
+    let name = check_scene(&state.pool, &req).await?;
+    scenes::save_scene(&state.pool, Some(id), &name, &req.lights).await?;
+
+    let updated = scenes::get_scene_by_id(&state.pool, id).await?;
+    state
+        .auth
+        .audit(&current_user, "update-scene", json!(scene), json!(updated))
+        .await?;
+
+    let scenes = scenes::get_scenes(&state.pool).await?;
+    Ok(Json(ScenesResponse { scenes }))
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn remove_scene_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<ScenesResponse>, AppError> {
+    let scene = scenes::get_scene_by_id(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No scene {id}")))?;
+
+    // the schedules would go with it, so they have to be changed or removed first
+    let schedules = sqlx::query_scalar!(
+        r"
+        SELECT name FROM schedule_rules WHERE scene_id = ?1 ORDER BY name
+        ",
+        id
+    )
+    .fetch_all(&state.pool)
+    .await?;
+    if !schedules.is_empty() {
+        return Err(AppError::Conflict(format!(
+            "{} still uses {}",
+            schedules.join(", "),
+            scene.name
+        )));
+    }
+
+    sqlx::query!(
+        r"
+        DELETE FROM scenes WHERE id = ?1
+        ",
+        id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    state
+        .auth
+        .audit(&current_user, "remove-scene", json!(scene), json!(null))
+        .await?;
+
+    let scenes = scenes::get_scenes(&state.pool).await?;
+    Ok(Json(ScenesResponse { scenes }))
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn apply_scene_handler(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<LightResponse>, AppError> {
+    let scene = scenes::get_scene(&state.pool, &name)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No scene named {name}")))?;
+
+    let plans = lights::plan(&state.pool, &scene.lights)
+        .await
+        .map_err(light_error)?;
+
+    state
+        .auth
+        .audit(&current_user, "apply-scene", json!(null), json!(scene))
+        .await?;
+
+    let results = lights::send(&state.backends, &plans).await;
+    state.light_states.record(&plans, &results);
+    Ok(Json(LightResponse { results }))
+}
+
+/// Validates a scene before it's saved, returning its trimmed name
+async fn check_scene(pool: &Pool<Sqlite>, req: &SceneRequest) -> Result<String, AppError> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("Scene needs a name".to_string()));
     }
 
-    futures_util::future::join_all(futs).await;
+    check_lights(pool, &req.lights).await?;
+
+    Ok(name.to_string())
+}
+
+/// Stricter than `/light-control` for lights that get saved, every one has to exist and
+/// show up only once
+async fn check_lights(pool: &Pool<Sqlite>, items: &[LightItem]) -> Result<(), AppError> {
+    let devices = devices::get_devices(pool).await?;
+    if let Some(light) = items
+        .iter()
+        .find(|light| !devices.iter().any(|device| device.name == light.name))
+    {
+        return Err(AppError::BadRequest(format!(
+            "No device named {}",
+            light.name
+        )));
+    }
+
+    let mut seen = HashSet::new();
+    if let Some(light) = items.iter().find(|light| !seen.insert(&light.name)) {
+        return Err(AppError::BadRequest(format!(
+            "{} is listed twice",
+            light.name
+        )));
+    }
+
+    // also catches capabilities the lights don't have
+    lights::plan(pool, items).await.map_err(light_error)?;
+
     Ok(())
 }
 
+#[derive(Serialize, Debug)]
+struct SchedulesResponse {
+    rules: Vec<Rule>,
+}
+
+#[derive(Deserialize)]
+struct ScheduleRequest {
+    name: String,
+    #[serde(default = "enabled_by_default")]
+    enabled: bool,
+    #[serde(flatten)]
+    trigger: Trigger,
+    #[serde(flatten)]
+    action: Action,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+#[derive(Serialize, Debug)]
+struct FiringsResponse {
+    /// RFC 3339 in the server's time zone
+    firings: Vec<String>,
+}
+
 #[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct DeviceInfo {
-    device: String,
-    sku: String,
+struct ScheduleLogQuery {
+    rule_id: Option<i64>,
+    limit: Option<i64>,
 }
 
-fn get_device(device_name: &str) -> Option<DeviceInfo> {
-    match device_name {
-        "tall living room" => Some(DeviceInfo {
-            device: "1A:A6:D4:AD:FC:EF:A6:1B".to_string(),
-            sku: "H5080".to_string(),
-        }),
-        "small living room" => Some(DeviceInfo {
-            device: "61:F6:D4:AD:FC:F3:6A:0F".to_string(),
-            sku: "H5080".to_string(),
-        }),
-        "bubble lamp" => Some(DeviceInfo {
-            device: "38:31:D4:AD:FC:A8:91:CB".to_string(),
-            sku: "H5080".to_string(),
-        }),
-        "bedroom black" => Some(DeviceInfo {
-            device: "44:CB:D4:AD:FC:EF:A5:E1".to_string(),
-            sku: "H5080".to_string(),
-        }),
-        "studio lights" => Some(DeviceInfo {
-            device: "67:5D:CD:2A:06:06:46:5F".to_string(),
-            sku: "H612D".to_string(),
-        }),
-        _ => None,
+#[derive(Serialize, Debug)]
+struct ScheduleLogResponse {
+    entries: Vec<LogEntry>,
+}
+
+const NEXT_FIRINGS: usize = 10;
+const DEFAULT_LOG_LIMIT: i64 = 100;
+const MAX_LOG_LIMIT: i64 = 1000;
+
+#[auth_macro::auth_guard]
+async fn get_schedules_handler(
+    State(state): State<AppState>,
+) -> Result<Json<SchedulesResponse>, AppError> {
+    let rules = scheduler::get_rules(&state.pool).await?;
+    Ok(Json(SchedulesResponse { rules }))
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn create_schedule_handler(
+    State(state): State<AppState>,
+    Json(req): Json<ScheduleRequest>,
+) -> Result<Json<SchedulesResponse>, AppError> {
+    let name = check_schedule(&state, &req).await?;
+    let id = scheduler::save_rule(
+        &state.pool,
+        None,
+        &name,
+        req.enabled,
+        &req.trigger,
+        &req.action,
+    )
+    .await?;
+
+    let created = scheduler::get_rule(&state.pool, id).await?;
+    state
+        .auth
+        .audit(
+            &current_user,
+            "create-schedule",
+            json!(null),
+            json!(created),
+        )
+        .await?;
+
+    let rules = scheduler::get_rules(&state.pool).await?;
+    Ok(Json(SchedulesResponse { rules }))
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn update_schedule_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<ScheduleRequest>,
+) -> Result<Json<SchedulesResponse>, AppError> {
+    let rule = scheduler::get_rule(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No schedule {id}")))?;
+
+    let name = check_schedule(&state, &req).await?;
+    scheduler::save_rule(
+        &state.pool,
+        Some(id),
+        &name,
+        req.enabled,
+        &req.trigger,
+        &req.action,
+    )
+    .await?;
+
+    let updated = scheduler::get_rule(&state.pool, id).await?;
+    state
+        .auth
+        .audit(
+            &current_user,
+            "update-schedule",
+            json!(rule),
+            json!(updated),
+        )
+        .await?;
+
+    let rules = scheduler::get_rules(&state.pool).await?;
+    Ok(Json(SchedulesResponse { rules }))
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn remove_schedule_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<SchedulesResponse>, AppError> {
+    let rule = scheduler::get_rule(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No schedule {id}")))?;
+
+    sqlx::query!(
+        r"
+        DELETE FROM schedule_rules WHERE id = ?1
+        ",
+        id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    state
+        .auth
+        .audit(&current_user, "remove-schedule", json!(rule), json!(null))
+        .await?;
+
+    let rules = scheduler::get_rules(&state.pool).await?;
+    Ok(Json(SchedulesResponse { rules }))
+}
+
+/// Dry run of when a rule would fire next, whether or not it's enabled
+#[auth_macro::auth_guard]
+async fn next_firings_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<FiringsResponse>, AppError> {
+    let rule = scheduler::get_rule(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No schedule {id}")))?;
+
+    let firings = rule
+        .trigger
+        .next_firings(chrono::Local::now(), state.location, NEXT_FIRINGS)
+        .iter()
+        .map(|firing| firing.to_rfc3339())
+        .collect();
+    Ok(Json(FiringsResponse { firings }))
+}
+
+#[auth_macro::auth_guard]
+async fn get_schedule_log_handler(
+    State(state): State<AppState>,
+    Query(query): Query<ScheduleLogQuery>,
+) -> Result<Json<ScheduleLogResponse>, AppError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LOG_LIMIT)
+        .clamp(1, MAX_LOG_LIMIT);
+
+    let entries = scheduler::get_log(&state.pool, query.rule_id, limit).await?;
+    Ok(Json(ScheduleLogResponse { entries }))
+}
+
+/// Validates a rule before it's saved, returning its trimmed name
+async fn check_schedule(state: &AppState, req: &ScheduleRequest) -> Result<String, AppError> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("Schedule needs a name".to_string()));
     }
+
+    req.trigger
+        .validate(state.location)
+        .map_err(AppError::BadRequest)?;
+
+    match &req.action {
+        Action::Scene(scene) => {
+            if scenes::get_scene(&state.pool, scene).await?.is_none() {
+                return Err(AppError::BadRequest(format!("No scene named {scene}")));
+            }
+        }
+        Action::Lights(items) => check_lights(&state.pool, items).await?,
+    }
+
+    Ok(name.to_string())
 }
 
-pub struct AppError(anyhow::Error);
+#[derive(Serialize, Debug)]
+struct AwayResponse {
+    settings: away::Settings,
+    rooms: Vec<away::Room>,
+    /// what away mode will switch today, empty when it's off
+    tonight: Vec<away::PlannedSwitch>,
+}
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {}", self.0),
+#[derive(Deserialize)]
+struct AwayRoomRequest {
+    name: String,
+    probability: f64,
+    lights: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct AwayLogQuery {
+    limit: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+struct AwayLogResponse {
+    entries: Vec<away::LogEntry>,
+}
+
+async fn away_response(pool: &Pool<Sqlite>) -> Result<Json<AwayResponse>, AppError> {
+    let settings = away::get_settings(pool).await?;
+    let rooms = away::get_rooms(pool).await?;
+    let tonight = away::plan_for(pool, Local::now().date_naive())
+        .await?
+        .into_iter()
+        .map(away::PlannedSwitch::from)
+        .collect();
+
+    Ok(Json(AwayResponse {
+        settings,
+        rooms,
+        tonight,
+    }))
+}
+
+#[auth_macro::auth_guard]
+async fn get_away_handler(State(state): State<AppState>) -> Result<Json<AwayResponse>, AppError> {
+    away_response(&state.pool).await
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn update_away_handler(
+    State(state): State<AppState>,
+    Json(req): Json<away::Settings>,
+) -> Result<Json<AwayResponse>, AppError> {
+    req.validate().map_err(AppError::BadRequest)?;
+
+    let before = away::get_settings(&state.pool).await?;
+    away::save_settings(&state.pool, &req).await?;
+    state
+        .auth
+        .audit(&current_user, "update-away", json!(before), json!(req))
+        .await?;
+
+    away_response(&state.pool).await
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn create_away_room_handler(
+    State(state): State<AppState>,
+    Json(req): Json<AwayRoomRequest>,
+) -> Result<Json<AwayResponse>, AppError> {
+    let name = check_away_room(&state.pool, &req).await?;
+    let id = away::save_room(&state.pool, None, &name, req.probability, &req.lights).await?;
+
+    let created = away::get_room(&state.pool, id).await?;
+    state
+        .auth
+        .audit(
+            &current_user,
+            "create-away-room",
+            json!(null),
+            json!(created),
         )
-            .into_response()
+        .await?;
+
+    away_response(&state.pool).await
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn update_away_room_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    Json(req): Json<AwayRoomRequest>,
+) -> Result<Json<AwayResponse>, AppError> {
+    let room = away::get_room(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No room {id}")))?;
+
+    let name = check_away_room(&state.pool, &req).await?;
+    away::save_room(&state.pool, Some(id), &name, req.probability, &req.lights).await?;
+
+    let updated = away::get_room(&state.pool, id).await?;
+    state
+        .auth
+        .audit(
+            &current_user,
+            "update-away-room",
+            json!(room),
+            json!(updated),
+        )
+        .await?;
+
+    away_response(&state.pool).await
+}
+
+#[auth_macro::auth_guard(role = "adult")]
+async fn remove_away_room_handler(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<AwayResponse>, AppError> {
+    let room = away::get_room(&state.pool, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No room {id}")))?;
+
+    sqlx::query!(
+        r"
+        DELETE FROM away_rooms WHERE id = ?1
+        ",
+        id
+    )
+    .execute(&state.pool)
+    .await?;
+
+    state
+        .auth
+        .audit(&current_user, "remove-away-room", json!(room), json!(null))
+        .await?;
+
+    away_response(&state.pool).await
+}
+
+/// What away mode switched, newest first
+#[auth_macro::auth_guard]
+async fn get_away_log_handler(
+    State(state): State<AppState>,
+    Query(query): Query<AwayLogQuery>,
+) -> Result<Json<AwayLogResponse>, AppError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LOG_LIMIT)
+        .clamp(1, MAX_LOG_LIMIT);
+    let entries = away::get_log(&state.pool, limit).await?;
+    Ok(Json(AwayLogResponse { entries }))
+}
+
+/// Validates a room before it's saved, returning its trimmed name
+async fn check_away_room(pool: &Pool<Sqlite>, req: &AwayRoomRequest) -> Result<String, AppError> {
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("Room needs a name".to_string()));
+    }
+    if !(0.0..=1.0).contains(&req.probability) {
+        return Err(AppError::BadRequest(
+            "Probability has to be between 0 and 1".to_string(),
+        ));
+    }
+    if req.lights.is_empty() {
+        return Err(AppError::BadRequest(
+            "Room needs at least one light".to_string(),
+        ));
+    }
+
+    let items: Vec<LightItem> = req
+        .lights
+        .iter()
+        .map(|name| LightItem {
+            name: name.clone(),
+            toggle: Some(true),
+            brightness: None,
+            color: None,
+            kelvin: None,
+        })
+        .collect();
+    check_lights(pool, &items).await?;
+
+    Ok(name.to_string())
+}
+
+#[derive(Deserialize, Debug)]
+struct AuditQuery {
+    service: Option<String>,
+    username: Option<String>,
+    action: Option<String>,
+    /// unix seconds, inclusive
+    since: Option<i64>,
+    /// unix seconds, exclusive
+    until: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(sqlx::FromRow, Debug, Serialize)]
+struct AuditEntry {
+    id: i64,
+    user_id: i64,
+    username: String,
+    service: String,
+    action: String,
+    before: Option<sqlx::types::Json<serde_json::Value>>,
+    after: Option<sqlx::types::Json<serde_json::Value>>,
+    created_at: i64,
+}
+
+#[derive(Serialize, Debug)]
+struct AuditResponse {
+    entries: Vec<AuditEntry>,
+}
+
+const DEFAULT_AUDIT_LIMIT: i64 = 100;
+const MAX_AUDIT_LIMIT: i64 = 1000;
+
+/// Newest first, every filter is optional
+#[auth_macro::auth_guard(role = "adult")]
+async fn get_audit_handler(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditResponse>, AppError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_AUDIT_LIMIT)
+        .clamp(1, MAX_AUDIT_LIMIT);
+
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"
+        SELECT id, user_id, username, service, action,
+               before_json AS "before: sqlx::types::Json<serde_json::Value>",
+               after_json AS "after: sqlx::types::Json<serde_json::Value>",
+               created_at
+        FROM audit_log
+        WHERE (?1 IS NULL OR service = ?1)
+          AND (?2 IS NULL OR username = ?2)
+          AND (?3 IS NULL OR action = ?3)
+          AND (?4 IS NULL OR created_at >= ?4)
+          AND (?5 IS NULL OR created_at < ?5)
+        ORDER BY created_at DESC, id DESC
+        LIMIT ?6
+        "#,
+        query.service,
+        query.username,
+        query.action,
+        query.since,
+        query.until,
+        limit
+    )
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok(Json(AuditResponse { entries }))
+}
+
+/// auth's errors plus being throttled, which only happens to logins
+enum LoginError {
+    App(AppError),
+    TooManyRequests(Duration),
+}
+
+impl IntoResponse for LoginError {
+    fn into_response(self) -> Response {
+        match self {
+            LoginError::App(err) => err.into_response(),
+            LoginError::TooManyRequests(wait) => {
+                let retry_after = wait.as_secs().max(1);
+                (
+                    [(header::RETRY_AFTER, retry_after.to_string())],
+                    auth::error_response(
+                        StatusCode::TOO_MANY_REQUESTS,
+                        "too_many_requests",
+                        &format!("Try again in {retry_after} seconds"),
+                    ),
+                )
+                    .into_response()
+            }
+        }
     }
 }
 
-impl<E> From<E> for AppError
+impl<E> From<E> for LoginError
 where
-    E: Into<anyhow::Error>,
+    E: Into<AppError>,
 {
     fn from(err: E) -> Self {
-        Self(err.into())
+        Self::App(err.into())
+    }
+}
+
+/// Lights asking for something their device can't do are the caller's mistake
+fn light_error(err: anyhow::Error) -> AppError {
+    match err.downcast::<lights::InvalidLight>() {
+        Ok(lights::InvalidLight(message)) => AppError::BadRequest(message),
+        Err(err) => err.into(),
     }
 }
 
@@ -230,3 +1832,181 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, extract::connect_info::MockConnectInfo, http::Request};
+    use serde_json::Value;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use tower::ServiceExt;
+
+    /// Root with mom in it as `role`, with `password`
+    async fn start(role: auth::Role, password: &str) -> Router {
+        credentials::use_cheapest_cost();
+        // one connection, every in-memory connection is its own database
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::migrate!("./migrations").run(&pool).await.unwrap();
+
+        let password_hash = bcrypt::hash(password, credentials::bcrypt_cost()).unwrap();
+        sqlx::query!(
+            r"
+            INSERT INTO users (username, password_hash, role, created_at) VALUES ('mom', ?1, ?2, 0)
+            ",
+            password_hash,
+            role
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let state = AppState {
+            auth: auth::Auth::new(pool.clone(), "root"),
+            credentials: Arc::new(Credentials::load(&pool).await.unwrap()),
+            limiter: Default::default(),
+            trusted_proxy: None,
+            location: None,
+            backends: Default::default(),
+            light_states: Default::default(),
+            pool,
+        };
+        router(state).layer(MockConnectInfo(SocketAddr::from(([192, 0, 2, 1], 1234))))
+    }
+
+    async fn post(app: &Router, path: &str, session: Option<&str>, body: Value) -> Response {
+        let mut req = Request::post(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::ORIGIN, "https://beebfam.org");
+        if let Some(session) = session {
+            req = req.header(
+                header::COOKIE,
+                format!("{}={session}", auth::SESSION_COOKIE),
+            );
+        }
+        let req = req.body(Body::from(body.to_string())).unwrap();
+        app.clone().oneshot(req).await.unwrap()
+    }
+
+    /// Logs in, returning the session when it worked
+    async fn login(app: &Router, password: &str) -> Option<String> {
+        let response = post(
+            app,
+            "/login",
+            None,
+            json!({ "username": "mom", "password": password }),
+        )
+        .await;
+        let cookie = response.headers().get(header::SET_COOKIE)?.to_str().ok()?;
+        let session = cookie
+            .strip_prefix(auth::SESSION_COOKIE)?
+            .strip_prefix('=')?
+            .split(';')
+            .next()?;
+        Some(session.to_string())
+    }
+
+    fn change(old: &str, new: &str) -> Value {
+        json!({ "old_password": old, "new_password": new })
+    }
+
+    #[tokio::test]
+    async fn logs_in_with_a_changed_password_as_typed() {
+        let app = start(auth::Role::Adult, "old password").await;
+        let session = login(&app, "old password").await.unwrap();
+
+        let new = "  new password  ";
+        let response = post(
+            &app,
+            "/change-password",
+            Some(&session),
+            change("old password", new),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert!(login(&app, new).await.is_some());
+        assert!(login(&app, new.trim()).await.is_none());
+        assert!(login(&app, "old password").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn throttles_old_password_guesses() {
+        let app = start(auth::Role::Adult, "old password").await;
+        let session = login(&app, "old password").await.unwrap();
+
+        let mut statuses = vec![];
+        for _ in 0..6 {
+            let response = post(
+                &app,
+                "/change-password",
+                Some(&session),
+                change("a guess", "new password"),
+            )
+            .await;
+            statuses.push(response.status());
+        }
+        assert_eq!(statuses[..4], [StatusCode::FORBIDDEN; 4]);
+        assert_eq!(statuses[4..], [StatusCode::TOO_MANY_REQUESTS; 2]);
+
+        // the right one waits too, guesses and logins share the limit
+        let response = post(
+            &app,
+            "/change-password",
+            Some(&session),
+            change("old password", "new password"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(login(&app, "old password").await.is_none());
+    }
+
+    async fn get(app: &Router, path: &str, session: &str) -> Response {
+        let req = Request::get(path)
+            .header(
+                header::COOKIE,
+                format!("{}={session}", auth::SESSION_COOKIE),
+            )
+            .body(Body::empty())
+            .unwrap();
+        app.clone().oneshot(req).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn guests_manage_their_own_sessions() {
+        let app = start(auth::Role::Guest, "guest password").await;
+        let phone = login(&app, "guest password").await.unwrap();
+        let laptop = login(&app, "guest password").await.unwrap();
+
+        let response = get(&app, "/get-sessions", &laptop).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let sessions: Value = serde_json::from_slice(&body).unwrap();
+        let sessions = sessions["sessions"].as_array().unwrap();
+        assert_eq!(sessions.len(), 2);
+
+        // the lost phone is the other one
+        let phone_id = sessions
+            .iter()
+            .find(|session| session["current"] != json!(true))
+            .unwrap()["id"]
+            .clone();
+        let path = format!("/sessions/{phone_id}/revoke");
+        let response = post(&app, &path, Some(&laptop), json!(null)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = get(&app, "/get-sessions", &phone).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = get(&app, "/get-sessions", &laptop).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // still read-only everywhere else
+        let response = post(&app, "/devices/sync", Some(&laptop), json!(null)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,174 @@
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::{Pool, Sqlite};
+
+use govee::Rgb;
+
+use crate::lights::LightItem;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Scene {
+    pub id: i64,
+    pub name: String,
+    pub lights: Vec<LightItem>,
+    pub created_at: i64,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct SceneRow {
+    id: i64,
+    name: String,
+    created_at: i64,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct SceneLightRow {
+    scene_id: i64,
+    name: String,
+    toggle: Option<bool>,
+    brightness: Option<i64>,
+    color: Option<i64>,
+    kelvin: Option<i64>,
+}
+
+impl From<SceneLightRow> for LightItem {
+    fn from(row: SceneLightRow) -> Self {
+        LightItem {
+            name: row.name,
+            toggle: row.toggle,
+            brightness: row.brightness.map(|percent| percent as u8),
+            color: row.color.map(|packed| Rgb::from_packed(packed as u32)),
+            kelvin: row.kelvin,
+        }
+    }
+}
+
+pub async fn get_scenes(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<Scene>> {
+    let scenes = sqlx::query_as!(
+        SceneRow,
+        r"
+        SELECT * FROM scenes ORDER BY name
+        ",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut lights = sqlx::query_as!(
+        SceneLightRow,
+        r#"
+        SELECT scene_lights.scene_id, devices.name,
+               scene_lights.toggle AS "toggle: bool",
+               scene_lights.brightness, scene_lights.color, scene_lights.kelvin
+        FROM scene_lights
+        JOIN devices ON devices.id = scene_lights.device_id
+        ORDER BY devices.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(scenes
+        .into_iter()
+        .map(|scene| {
+            let (scene_lights, rest) = lights
+                .drain(..)
+                .partition(|light| light.scene_id == scene.id);
+            lights = rest;
+
+            Scene {
+                id: scene.id,
+                name: scene.name,
+                lights: scene_lights.into_iter().map(LightItem::from).collect(),
+                created_at: scene.created_at,
+            }
+        })
+        .collect())
+}
+
+pub async fn get_scene(pool: &Pool<Sqlite>, name: &str) -> anyhow::Result<Option<Scene>> {
+    let scene = get_scenes(pool)
+        .await?
+        .into_iter()
+        .find(|scene| scene.name == name);
+
+    Ok(scene)
+}
+
+pub async fn get_scene_by_id(pool: &Pool<Sqlite>, id: i64) -> anyhow::Result<Option<Scene>> {
+    let scene = get_scenes(pool)
+        .await?
+        .into_iter()
+        .find(|scene| scene.id == id);
+
+    Ok(scene)
+}
+
+/// Creates the scene, or replaces its name and lights when `id` is given.
+/// Every light has to name a device in the registry.
+pub async fn save_scene(
+    pool: &Pool<Sqlite>,
+    id: Option<i64>,
+    name: &str,
+    lights: &[LightItem],
+) -> anyhow::Result<i64> {
+    let mut tx = pool.begin().await?;
+
+    let id = match id {
+        Some(id) => {
+            sqlx::query!(
+                r"
+                UPDATE scenes SET name = ?1 WHERE id = ?2
+                ",
+                name,
+                id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            sqlx::query!(
+                r"
+                DELETE FROM scene_lights WHERE scene_id = ?1
+                ",
+                id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            id
+        }
+        None => {
+            let now = Utc::now().timestamp();
+            sqlx::query!(
+                r"
+                INSERT INTO scenes (name, created_at) VALUES (?1, ?2)
+                ",
+                name,
+                now
+            )
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid()
+        }
+    };
+
+    for light in lights {
+        let color = light.color.map(Rgb::to_packed);
+        sqlx::query!(
+            r"
+            INSERT INTO scene_lights (scene_id, device_id, toggle, brightness, color, kelvin)
+            SELECT ?1, id, ?2, ?3, ?4, ?5 FROM devices WHERE name = ?6
+            ",
+            id,
+            light.toggle,
+            light.brightness,
+            color,
+            light.kelvin,
+            light.name
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(id)
+}
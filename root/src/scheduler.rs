@@ -0,0 +1,510 @@
+use chrono::{DateTime, Duration, Local, TimeZone, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Sqlite};
+use std::sync::Arc;
+
+use crate::{
+    cron::Cron,
+    light_state::LightStateCache,
+    lights::{self, Backends, LightItem, LightStatus},
+    scenes,
+    sun::{Location, SunEvent},
+};
+
+/// How often rules are checked, they fire at most this late
+const TICK: std::time::Duration = std::time::Duration::from_secs(15);
+/// Long enough to get past a polar night
+const MAX_SUN_DAYS_AHEAD: u32 = 400;
+/// Keeps sun offsets within the day the event happens on
+pub const MAX_OFFSET_MINUTES: i64 = 12 * 60;
+
+/// When a rule fires, sun offsets are in minutes and can be negative
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Trigger {
+    Cron {
+        cron: String,
+    },
+    Sunrise {
+        #[serde(default)]
+        offset_minutes: i64,
+    },
+    Sunset {
+        #[serde(default)]
+        offset_minutes: i64,
+    },
+}
+
+/// What a rule does, apply a scene by name or set lights directly
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Scene(String),
+    Lights(Vec<LightItem>),
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Rule {
+    pub id: i64,
+    pub name: String,
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub trigger: Trigger,
+    #[serde(flatten)]
+    pub action: Action,
+    pub created_at: i64,
+}
+
+#[derive(sqlx::FromRow, Debug)]
+struct RuleRow {
+    id: i64,
+    name: String,
+    enabled: bool,
+    kind: String,
+    cron: Option<String>,
+    offset_minutes: i64,
+    scene_name: Option<String>,
+    lights_json: Option<String>,
+    created_at: i64,
+}
+
+impl TryFrom<RuleRow> for Rule {
+    type Error = anyhow::Error;
+
+    fn try_from(row: RuleRow) -> Result<Self, Self::Error> {
+        let trigger = match (row.kind.as_str(), row.cron) {
+            ("cron", Some(cron)) => Trigger::Cron { cron },
+            ("sunrise", _) => Trigger::Sunrise {
+                offset_minutes: row.offset_minutes,
+            },
+            ("sunset", _) => Trigger::Sunset {
+                offset_minutes: row.offset_minutes,
+            },
+            (kind, _) => return Err(anyhow::anyhow!("Rule {} has a bad kind {kind}", row.id)),
+        };
+
+        let action = match (row.scene_name, row.lights_json) {
+            (Some(scene), _) => Action::Scene(scene),
+            (None, Some(lights)) => Action::Lights(serde_json::from_str(&lights)?),
+            (None, None) => return Err(anyhow::anyhow!("Rule {} has no action", row.id)),
+        };
+
+        Ok(Rule {
+            id: row.id,
+            name: row.name,
+            enabled: row.enabled,
+            trigger,
+            action,
+            created_at: row.created_at,
+        })
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct LogEntry {
+    pub id: i64,
+    pub rule_id: Option<i64>,
+    pub rule_name: String,
+    pub scheduled_for: i64,
+    pub ran_at: i64,
+    pub ok: bool,
+    pub message: Option<String>,
+}
+
+impl Trigger {
+    /// Whether the trigger can ever fire, with a readable reason when it can't
+    pub fn validate(&self, location: Option<Location>) -> Result<(), String> {
+        match self {
+            Trigger::Cron { cron } => cron.parse::<Cron>().map(|_| ()),
+            Trigger::Sunrise { offset_minutes } | Trigger::Sunset { offset_minutes } => {
+                if location.is_none() {
+                    return Err("Set LATITUDE and LONGITUDE to schedule around the sun".to_string());
+                }
+                if offset_minutes.abs() > MAX_OFFSET_MINUTES {
+                    return Err(format!(
+                        "Offsets have to be within {MAX_OFFSET_MINUTES} minutes"
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The first firing strictly after `after`, in its time zone
+    pub fn next_after<Tz: TimeZone>(
+        &self,
+        after: DateTime<Tz>,
+        location: Option<Location>,
+    ) -> Option<DateTime<Tz>> {
+        match self {
+            Trigger::Cron { cron } => cron.parse::<Cron>().ok()?.next_after(after),
+            Trigger::Sunrise { offset_minutes } => {
+                next_sun_event(after, location?, SunEvent::Sunrise, *offset_minutes)
+            }
+            Trigger::Sunset { offset_minutes } => {
+                next_sun_event(after, location?, SunEvent::Sunset, *offset_minutes)
+            }
+        }
+    }
+
+    pub fn next_firings<Tz: TimeZone>(
+        &self,
+        after: DateTime<Tz>,
+        location: Option<Location>,
+        count: usize,
+    ) -> Vec<DateTime<Tz>> {
+        let mut firings = vec![];
+        let mut after = after;
+        while firings.len() < count {
+            let Some(next) = self.next_after(after, location) else {
+                break;
+            };
+            firings.push(next.clone());
+            after = next;
+        }
+        firings
+    }
+}
+
+fn next_sun_event<Tz: TimeZone>(
+    after: DateTime<Tz>,
+    location: Location,
+    event: SunEvent,
+    offset_minutes: i64,
+) -> Option<DateTime<Tz>> {
+    let offset = Duration::minutes(offset_minutes);
+    // a positive offset can push yesterday's event past `after`
+    let mut date = after.date_naive().pred_opt()?;
+
+    for _ in 0..MAX_SUN_DAYS_AHEAD {
+        if let Some(time) = location.sun_time(date, event) {
+            let time = time.with_timezone(&after.timezone()) + offset;
+            if time > after {
+                return Some(time);
+            }
+        }
+        date = date.succ_opt()?;
+    }
+
+    None
+}
+
+pub async fn get_rules(pool: &Pool<Sqlite>) -> anyhow::Result<Vec<Rule>> {
+    let rows = sqlx::query_as!(
+        RuleRow,
+        r#"
+        SELECT schedule_rules.id, schedule_rules.name, enabled AS "enabled: bool", kind, cron,
+               offset_minutes, scenes.name AS "scene_name?", lights_json,
+               schedule_rules.created_at
+        FROM schedule_rules
+        LEFT JOIN scenes ON scenes.id = schedule_rules.scene_id
+        ORDER BY schedule_rules.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter().map(Rule::try_from).collect()
+}
+
+pub async fn get_rule(pool: &Pool<Sqlite>, id: i64) -> anyhow::Result<Option<Rule>> {
+    let rule = get_rules(pool)
+        .await?
+        .into_iter()
+        .find(|rule| rule.id == id);
+
+    Ok(rule)
+}
+
+/// Creates the rule, or replaces it when `id` is given. A scene action has to name an
+/// existing scene.
+pub async fn save_rule(
+    pool: &Pool<Sqlite>,
+    id: Option<i64>,
+    name: &str,
+    enabled: bool,
+    trigger: &Trigger,
+    action: &Action,
+) -> anyhow::Result<i64> {
+    let (kind, cron, offset_minutes) = match trigger {
+        Trigger::Cron { cron } => ("cron", Some(cron.as_str()), 0),
+        Trigger::Sunrise { offset_minutes } => ("sunrise", None, *offset_minutes),
+        Trigger::Sunset { offset_minutes } => ("sunset", None, *offset_minutes),
+    };
+    let (scene_name, lights_json) = match action {
+        Action::Scene(scene) => (Some(scene.as_str()), None),
+        Action::Lights(lights) => (None, Some(serde_json::to_string(lights)?)),
+    };
+    let scene_id = match scene_name {
+        Some(scene_name) => Some(
+            sqlx::query_scalar!(
+                r"
+                SELECT id FROM scenes WHERE name = ?1
+                ",
+                scene_name
+            )
+            .fetch_one(pool)
+            .await?,
+        ),
+        None => None,
+    };
+
+    let id = match id {
+        Some(id) => {
+            sqlx::query!(
+                r"
+                UPDATE schedule_rules
+                SET name = ?1, enabled = ?2, kind = ?3, cron = ?4, offset_minutes = ?5,
+                    scene_id = ?6, lights_json = ?7
+                WHERE id = ?8
+                ",
+                name,
+                enabled,
+                kind,
+                cron,
+                offset_minutes,
+                scene_id,
+                lights_json,
+                id
+            )
+            .execute(pool)
+            .await?;
+            id
+        }
+        None => {
+            let now = Utc::now().timestamp();
+            sqlx::query!(
+                r"
+                INSERT INTO schedule_rules
+                  (name, enabled, kind, cron, offset_minutes, scene_id, lights_json, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+                ",
+                name,
+                enabled,
+                kind,
+                cron,
+                offset_minutes,
+                scene_id,
+                lights_json,
+                now
+            )
+            .execute(pool)
+            .await?
+            .last_insert_rowid()
+        }
+    };
+
+    Ok(id)
+}
+
+/// Newest first, optionally only one rule's
+pub async fn get_log(
+    pool: &Pool<Sqlite>,
+    rule_id: Option<i64>,
+    limit: i64,
+) -> anyhow::Result<Vec<LogEntry>> {
+    let entries = sqlx::query_as!(
+        LogEntry,
+        r#"
+        SELECT id, rule_id, rule_name, scheduled_for, ran_at, ok AS "ok: bool", message
+        FROM schedule_log
+        WHERE (?1 IS NULL OR rule_id = ?1)
+        ORDER BY ran_at DESC, id DESC
+        LIMIT ?2
+        "#,
+        rule_id,
+        limit
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(entries)
+}
+
+/// Checks the rules every `TICK` and applies the ones that came due since the last check.
+/// Firings missed while root was down are skipped rather than replayed.
+pub async fn run(
+    pool: Pool<Sqlite>,
+    backends: Backends,
+    light_states: Arc<LightStateCache>,
+    location: Option<Location>,
+) {
+    if location.is_none() {
+        warn!("LATITUDE and LONGITUDE aren't set, sunrise and sunset rules won't run");
+    }
+
+    let mut last_check = Local::now();
+    let mut interval = tokio::time::interval(TICK);
+
+    loop {
+        interval.tick().await;
+        let now = Local::now();
+
+        let rules = match get_rules(&pool).await {
+            Ok(rules) => rules,
+            Err(err) => {
+                error!("Failed to load schedule rules: {err:?}");
+                continue;
+            }
+        };
+
+        for rule in rules.iter().filter(|rule| rule.enabled) {
+            let Some(due) = rule.trigger.next_after(last_check, location) else {
+                continue;
+            };
+            if due > now {
+                continue;
+            }
+
+            let result = apply(&pool, &backends, &light_states, &rule.action).await;
+            match &result {
+                Ok(()) => info!("Ran schedule {}", rule.name),
+                Err(err) => warn!("Schedule {} failed: {err:?}", rule.name),
+            }
+            if let Err(err) = log_run(&pool, rule, due, &result).await {
+                error!("Failed to log schedule {}: {err:?}", rule.name);
+            }
+        }
+
+        last_check = now;
+    }
+}
+
+async fn apply(
+    pool: &Pool<Sqlite>,
+    backends: &Backends,
+    light_states: &LightStateCache,
+    action: &Action,
+) -> anyhow::Result<()> {
+    let items = match action {
+        Action::Scene(name) => {
+            scenes::get_scene(pool, name)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("No scene named {name}"))?
+                .lights
+        }
+        Action::Lights(items) => items.clone(),
+    };
+
+    let plans = lights::plan(pool, &items).await?;
+    let results = lights::send(backends, &plans).await;
+    light_states.record(&plans, &results);
+
+    let failures: Vec<String> = results
+        .iter()
+        .filter(|result| result.status != LightStatus::Ok)
+        .map(|result| format!("{}: {}", result.name, result.status))
+        .collect();
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(failures.join(", ")))
+    }
+}
+
+async fn log_run(
+    pool: &Pool<Sqlite>,
+    rule: &Rule,
+    scheduled_for: DateTime<Local>,
+    result: &anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let scheduled_for = scheduled_for.timestamp();
+    let now = Utc::now().timestamp();
+    let ok = result.is_ok();
+    let message = result.as_ref().err().map(|err| err.to_string());
+
+    sqlx::query!(
+        r"
+        INSERT INTO schedule_log (rule_id, rule_name, scheduled_for, ran_at, ok, message)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        ",
+        rule.id,
+        rule.name,
+        scheduled_for,
+        now,
+        ok,
+        message
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    const NEW_YORK: Location = Location {
+        latitude: 40.7128,
+        longitude: -74.006,
+    };
+
+    fn utc(datetime: &str) -> DateTime<Utc> {
+        datetime.parse().unwrap()
+    }
+
+    #[test]
+    fn validates_triggers() {
+        let cron = |cron: &str| Trigger::Cron {
+            cron: cron.to_string(),
+        };
+        let sunrise = |offset_minutes| Trigger::Sunrise { offset_minutes };
+        let cases = [
+            (cron("30 6 * * 1-5"), Some(NEW_YORK), None),
+            (cron("30 6 * *"), Some(NEW_YORK), Some("Expected 5 fields")),
+            (sunrise(0), None, Some("Set LATITUDE and LONGITUDE")),
+            (sunrise(-MAX_OFFSET_MINUTES), Some(NEW_YORK), None),
+            (
+                sunrise(MAX_OFFSET_MINUTES + 1),
+                Some(NEW_YORK),
+                Some("Offsets"),
+            ),
+        ];
+
+        for (trigger, location, expected) in cases {
+            match (trigger.validate(location), expected) {
+                (Ok(()), None) => {}
+                (Err(err), Some(expected)) => assert!(err.contains(expected), "{err}"),
+                (result, _) => panic!("{trigger:?} gave {result:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn sun_triggers_follow_the_offset() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 20).unwrap();
+        let sunset = NEW_YORK.sun_time(date, SunEvent::Sunset).unwrap();
+
+        let early = Trigger::Sunset {
+            offset_minutes: -15,
+        };
+        let next = early.next_after(utc("2024-06-20T12:00:00Z"), Some(NEW_YORK));
+        assert_eq!(next, Some(sunset - Duration::minutes(15)));
+
+        // yesterday's sunset pushed into today
+        let late = Trigger::Sunset {
+            offset_minutes: 600,
+        };
+        let next = late.next_after(utc("2024-06-21T08:00:00Z"), Some(NEW_YORK));
+        assert_eq!(next, Some(sunset + Duration::minutes(600)));
+    }
+
+    #[test]
+    fn sun_triggers_wait_out_polar_night() {
+        let tromso = Location {
+            latitude: 69.6492,
+            longitude: 18.9553,
+        };
+        let sunrise = Trigger::Sunrise { offset_minutes: 0 };
+
+        let next = sunrise
+            .next_after(utc("2024-12-01T00:00:00Z"), Some(tromso))
+            .unwrap();
+        assert!(next > utc("2025-01-10T00:00:00Z") && next < utc("2025-01-20T00:00:00Z"));
+        assert_eq!(sunrise.next_after(next, None), None);
+    }
+}
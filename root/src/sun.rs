@@ -0,0 +1,125 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use std::env;
+
+/// Days from the Unix epoch to J2000 (2000-01-01 12:00 UTC)
+const J2000_UNIX_DAYS: f64 = 10_957.5;
+const SECONDS_PER_DAY: f64 = 86_400.;
+/// Where the sun's upper edge touches the horizon, accounting for refraction
+const HORIZON_DEGREES: f64 = -0.833;
+const AXIAL_TILT_DEGREES: f64 = 23.4397;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SunEvent {
+    Sunrise,
+    Sunset,
+}
+
+/// Where the house is, for sunrise and sunset
+#[derive(Debug, Clone, Copy)]
+pub struct Location {
+    pub latitude: f64,
+    /// east is positive
+    pub longitude: f64,
+}
+
+impl Location {
+    /// From `LATITUDE` and `LONGITUDE`, `None` when they aren't set
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let (Ok(latitude), Ok(longitude)) = (env::var("LATITUDE"), env::var("LONGITUDE")) else {
+            return Ok(None);
+        };
+
+        let location = Location {
+            latitude: latitude.parse()?,
+            longitude: longitude.parse()?,
+        };
+        if !(-90. ..=90.).contains(&location.latitude)
+            || !(-180. ..=180.).contains(&location.longitude)
+        {
+            return Err(anyhow::anyhow!("LATITUDE or LONGITUDE is out of range"));
+        }
+
+        Ok(Some(location))
+    }
+
+    /// When the sun rises or sets on `date` here, `None` on days it doesn't (polar day or night).
+    /// Uses the sunrise equation, good to about a minute away from the poles.
+    pub fn sun_time(&self, date: NaiveDate, event: SunEvent) -> Option<DateTime<Utc>> {
+        let days_since_j2000 = (date - NaiveDate::from_ymd_opt(2000, 1, 1)?).num_days() as f64;
+        let mean_solar_noon = days_since_j2000 + 0.0008 - self.longitude / 360.;
+
+        let mean_anomaly = (357.5291 + 0.98560028 * mean_solar_noon).rem_euclid(360.);
+        let m = mean_anomaly.to_radians();
+        let center = 1.9148 * m.sin() + 0.02 * (2. * m).sin() + 0.0003 * (3. * m).sin();
+        let ecliptic_longitude = (mean_anomaly + center + 180. + 102.9372).rem_euclid(360.);
+        let lambda = ecliptic_longitude.to_radians();
+
+        let solar_transit = mean_solar_noon + 0.0053 * m.sin() - 0.0069 * (2. * lambda).sin();
+
+        let declination = (lambda.sin() * AXIAL_TILT_DEGREES.to_radians().sin()).asin();
+        let latitude = self.latitude.to_radians();
+        let cos_hour_angle = (HORIZON_DEGREES.to_radians().sin()
+            - latitude.sin() * declination.sin())
+            / (latitude.cos() * declination.cos());
+        if !(-1. ..=1.).contains(&cos_hour_angle) {
+            return None;
+        }
+        let hour_angle = cos_hour_angle.acos().to_degrees() / 360.;
+
+        let event_day = match event {
+            SunEvent::Sunrise => solar_transit - hour_angle,
+            SunEvent::Sunset => solar_transit + hour_angle,
+        };
+        let unix_seconds = (event_day + J2000_UNIX_DAYS) * SECONDS_PER_DAY;
+
+        DateTime::from_timestamp(unix_seconds.round() as i64, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NEW_YORK: Location = Location {
+        latitude: 40.7128,
+        longitude: -74.006,
+    };
+    const TROMSO: Location = Location {
+        latitude: 69.6492,
+        longitude: 18.9553,
+    };
+
+    fn utc(datetime: &str) -> DateTime<Utc> {
+        format!("{datetime}:00Z").parse().unwrap()
+    }
+
+    #[test]
+    fn sun_times() {
+        // published times for New York, rounded to the minute
+        let cases = [
+            ("2024-06-20", SunEvent::Sunrise, "2024-06-20T09:25"),
+            ("2024-06-20", SunEvent::Sunset, "2024-06-21T00:31"),
+            ("2024-12-21", SunEvent::Sunrise, "2024-12-21T12:17"),
+            ("2024-12-21", SunEvent::Sunset, "2024-12-21T21:32"),
+        ];
+
+        for (date, event, expected) in cases {
+            let date: NaiveDate = date.parse().unwrap();
+            let time = NEW_YORK.sun_time(date, event).unwrap();
+            let off_by = (time - utc(expected)).num_seconds().abs();
+            assert!(off_by <= 120, "{event:?} on {date} was {time}");
+        }
+    }
+
+    #[test]
+    fn no_sun_times_in_polar_day_or_night() {
+        for date in ["2024-06-21", "2024-12-21"] {
+            let date: NaiveDate = date.parse().unwrap();
+            assert_eq!(TROMSO.sun_time(date, SunEvent::Sunrise), None);
+            assert_eq!(TROMSO.sun_time(date, SunEvent::Sunset), None);
+        }
+
+        let date: NaiveDate = "2024-03-20".parse().unwrap();
+        assert!(TROMSO.sun_time(date, SunEvent::Sunrise).is_some());
+    }
+}